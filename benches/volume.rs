@@ -0,0 +1,95 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::{cell::RefCell, sync::Arc};
+use wasmfs::volume::{MkdirOptions, Volume};
+
+const WIDE: usize = 1000;
+const DEEP: usize = 32;
+
+fn recursive() -> Option<MkdirOptions> {
+    Some(MkdirOptions {
+        recursive: Some(true),
+        ..Default::default()
+    })
+}
+
+fn deep_path() -> String {
+    (0..DEEP).map(|i| format!("/d{}", i)).collect()
+}
+
+// A volume with `WIDE` files in `/wide` and a `DEEP` levels deep directory.
+fn populated() -> Arc<RefCell<Volume>> {
+    let vol = Volume::new();
+    {
+        let mut vol = vol.borrow_mut();
+        vol.mkdir_sync(String::from("/wide"), None).unwrap();
+        for i in 0..WIDE {
+            vol.write_file_sync(format!("/wide/{}", i), b"data", None)
+                .unwrap();
+        }
+        vol.mkdir_sync(deep_path(), recursive()).unwrap();
+        vol.write_file_sync(format!("{}/file", deep_path()), b"data", None)
+            .unwrap();
+    }
+    vol
+}
+
+fn creation(c: &mut Criterion) {
+    c.bench_function("create 1000 files", |b| {
+        b.iter_batched(
+            Volume::new,
+            |vol| {
+                let mut vol = vol.borrow_mut();
+                for i in 0..WIDE {
+                    vol.write_file_sync(format!("/{}", i), b"data", None)
+                        .unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    c.bench_function("mkdir 32 levels", |b| {
+        b.iter_batched(
+            Volume::new,
+            |vol| {
+                vol.borrow_mut()
+                    .mkdir_sync(deep_path(), recursive())
+                    .unwrap();
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn lookup(c: &mut Criterion) {
+    let vol = populated();
+    let mut vol = vol.borrow_mut();
+    c.bench_function("stat in wide directory", |b| {
+        b.iter(|| vol.stat_sync(String::from("/wide/500"), None).unwrap())
+    });
+    let path = format!("{}/file", deep_path());
+    c.bench_function("stat 32 levels deep", |b| {
+        b.iter(|| vol.stat_sync(path.clone(), None).unwrap())
+    });
+    c.bench_function("exists missing", |b| {
+        b.iter(|| vol.exists_sync(String::from("/wide/missing")))
+    });
+    c.bench_function("readdir 1000 entries", |b| {
+        b.iter(|| vol.readdir_sync(String::from("/wide")).unwrap())
+    });
+}
+
+fn renaming(c: &mut Criterion) {
+    let vol = populated();
+    let mut vol = vol.borrow_mut();
+    let mut from = String::from("/wide");
+    let mut to = String::from("/moved");
+    c.bench_function("rename directory of 1000 files", |b| {
+        b.iter(|| {
+            vol.rename_sync(from.clone(), to.clone()).unwrap();
+            std::mem::swap(&mut from, &mut to);
+        })
+    });
+}
+
+criterion_group!(benches, creation, lookup, renaming);
+criterion_main!(benches);
@@ -0,0 +1,257 @@
+use std::{
+    marker::PhantomData,
+    ops::{Index, IndexMut},
+    sync::Arc,
+};
+
+// Typed index into an `Arena`.
+pub trait ArenaId: Copy {
+    fn from_index(index: usize) -> Self;
+    fn index(self) -> usize;
+}
+
+// Index of a `Node` in the `nodes` arena of its volume, which doubles as its
+// I-node number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InodeId(usize);
+
+// Index of a `Link` in the `links` arena of its volume.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LinkId(usize);
+
+impl ArenaId for InodeId {
+    fn from_index(index: usize) -> Self {
+        InodeId(index)
+    }
+
+    fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl ArenaId for LinkId {
+    fn from_index(index: usize) -> Self {
+        LinkId(index)
+    }
+
+    fn index(self) -> usize {
+        self.0
+    }
+}
+
+// Number of slots per chunk, see `Arena`.
+const CHUNK_SIZE: usize = 64;
+
+// Slab of values addressed by `I`. Removed slots are handed out again, most
+// recently freed first, so ids stay small and dense.
+//
+// Clones share their values and are cheap: slots live in chunks that are
+// copied on the first write after cloning, and values are copied on the first
+// mutable access, so each copy only pays for what changed.
+pub struct Arena<I, T> {
+    chunks: Vec<Arc<Vec<Option<Arc<T>>>>>,
+    free: Vec<usize>,
+    len: usize,
+    _id: PhantomData<I>,
+}
+
+impl<I, T> Default for Arena<I, T> {
+    fn default() -> Self {
+        Arena {
+            chunks: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+            _id: PhantomData,
+        }
+    }
+}
+
+impl<I, T> Clone for Arena<I, T> {
+    fn clone(&self) -> Self {
+        Arena {
+            chunks: self.chunks.clone(),
+            free: self.free.clone(),
+            len: self.len,
+            _id: PhantomData,
+        }
+    }
+}
+
+impl<I: ArenaId, T> Arena<I, T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Number of slots, taken or not.
+    fn capacity(&self) -> usize {
+        self.chunks.last().map_or(0, |chunk| {
+            (self.chunks.len() - 1) * CHUNK_SIZE + chunk.len()
+        })
+    }
+
+    fn slot(&self, index: usize) -> Option<&Option<Arc<T>>> {
+        self.chunks.get(index / CHUNK_SIZE)?.get(index % CHUNK_SIZE)
+    }
+
+    // Copies the chunk of `index` first if it is shared with a clone.
+    fn slot_mut(&mut self, index: usize) -> Option<&mut Option<Arc<T>>> {
+        let chunk = self.chunks.get_mut(index / CHUNK_SIZE)?;
+        Arc::make_mut(chunk).get_mut(index % CHUNK_SIZE)
+    }
+
+    fn push_slot(&mut self, value: Option<Arc<T>>) {
+        match self.chunks.last_mut() {
+            Some(chunk) if chunk.len() < CHUNK_SIZE => Arc::make_mut(chunk).push(value),
+            _ => {
+                let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+                chunk.push(value);
+                self.chunks.push(Arc::new(chunk));
+            }
+        }
+    }
+
+    // Id the next `insert` will return.
+    pub fn next_id(&self) -> I {
+        I::from_index(self.free.last().copied().unwrap_or(self.capacity()))
+    }
+
+    pub fn insert(&mut self, value: T) -> I {
+        self.len += 1;
+        match self.free.pop() {
+            Some(index) => {
+                *self.slot_mut(index).unwrap() = Some(Arc::new(value));
+                I::from_index(index)
+            }
+            None => {
+                let index = self.capacity();
+                self.push_slot(Some(Arc::new(value)));
+                I::from_index(index)
+            }
+        }
+    }
+
+    // Puts `value` at `id`, e.g. to restore a saved arena. Returns the value
+    // back if the slot is taken.
+    pub fn insert_at(&mut self, id: I, value: T) -> Result<(), T> {
+        let index = id.index();
+        while self.capacity() <= index {
+            self.free.push(self.capacity());
+            self.push_slot(None);
+        }
+        if self.contains(id) {
+            return Err(value);
+        }
+        // Slots grown to are freed in order, so one just grown to is found at
+        // the end right away.
+        if let Some(free) = self.free.iter().rposition(|free| *free == index) {
+            self.free.remove(free);
+        }
+        *self.slot_mut(index).unwrap() = Some(Arc::new(value));
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn get(&self, id: I) -> Option<&T> {
+        self.slot(id.index())?.as_deref()
+    }
+
+    pub fn contains(&self, id: I) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (I, &T)> {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.iter())
+            .enumerate()
+            .filter_map(|(index, slot)| Some((I::from_index(index), slot.as_deref()?)))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.iter())
+            .filter_map(|slot| slot.as_deref())
+    }
+}
+
+impl<I: ArenaId, T: Clone> Arena<I, T> {
+    pub fn remove(&mut self, id: I) -> Option<T> {
+        let value = self.slot_mut(id.index())?.take()?;
+        self.free.push(id.index());
+        self.len -= 1;
+        Some(Arc::unwrap_or_clone(value))
+    }
+
+    // Copies the value first if it is shared with a clone.
+    pub fn get_mut(&mut self, id: I) -> Option<&mut T> {
+        self.slot_mut(id.index())?.as_mut().map(Arc::make_mut)
+    }
+
+    // Whether the value at `id` is the very same in `other`, i.e. neither
+    // changed it since they were cloned from each other.
+    pub fn shares(&self, other: &Arena<I, T>, id: I) -> bool {
+        match (self.slot(id.index()), other.slot(id.index())) {
+            (Some(Some(a)), Some(Some(b))) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl<I: ArenaId, T> Index<I> for Arena<I, T> {
+    type Output = T;
+
+    fn index(&self, id: I) -> &T {
+        self.get(id).expect("stale arena id")
+    }
+}
+
+impl<I: ArenaId, T: Clone> IndexMut<I> for Arena<I, T> {
+    fn index_mut(&mut self, id: I) -> &mut T {
+        self.get_mut(id).expect("stale arena id")
+    }
+}
+
+#[test]
+fn test_arena_reuses_freed_slots() {
+    let mut arena: Arena<LinkId, &str> = Arena::new();
+    let a = arena.insert("a");
+    let b = arena.insert("b");
+    assert_eq!(arena.remove(a), Some("a"));
+    assert_eq!(arena.remove(a), None);
+    assert!(arena.get(a).is_none());
+    assert_eq!(arena.next_id(), a);
+    assert_eq!(arena.insert("c"), a);
+    assert_eq!(arena[a], "c");
+    assert_eq!(arena[b], "b");
+    assert_eq!(arena.len(), 2);
+    assert_eq!(
+        arena.iter().map(|(_, value)| *value).collect::<Vec<_>>(),
+        ["c", "b"]
+    );
+}
+
+#[test]
+fn test_arena_clones_share_values() {
+    let mut arena: Arena<LinkId, String> = Arena::new();
+    let ids: Vec<LinkId> = (0..100).map(|i| arena.insert(i.to_string())).collect();
+    let mut copy = arena.clone();
+    copy[ids[70]].push('!');
+    copy.remove(ids[3]);
+    assert_eq!(arena[ids[70]], "70");
+    assert_eq!(copy[ids[70]], "70!");
+    assert_eq!(arena[ids[3]], "3");
+    assert!(!copy.contains(ids[3]));
+    assert!(copy.shares(&arena, ids[69]));
+    assert!(!copy.shares(&arena, ids[70]));
+    assert_eq!(copy.insert(String::new()), ids[3]);
+    assert_eq!(arena.len(), 100);
+}
@@ -0,0 +1,64 @@
+// Storage a directory of a volume can be handed over to, see
+// `Volume::mount_backend`. Lookups that reach the mount point continue in the
+// backend with the rest of the path.
+//
+// Paths are absolute and normalized within the backend, "/" being the mount
+// point itself. Backends report bare error codes, the volume fills in the
+// syscall and the paths as seen through the volume.
+use crate::{
+    dirent::Dirent,
+    error::{FSError, FsError},
+    stats::Stats,
+};
+
+pub trait Backend {
+    // Follows symlinks, including one at `path`.
+    fn stat(&self, path: &str) -> Result<Stats, FsError>;
+
+    // Like `stat`, but does not follow a symlink at `path`.
+    fn lstat(&self, path: &str) -> Result<Stats, FsError>;
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, FsError>;
+
+    // Creates the file at `path` or replaces its contents.
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), FsError>;
+
+    // Entries of the directory at `path`, sorted by name.
+    fn readdir(&self, path: &str) -> Result<Vec<Dirent>, FsError>;
+
+    fn mkdir(&mut self, path: &str, mode: usize) -> Result<(), FsError>;
+
+    // Removes the empty directory at `path`.
+    fn rmdir(&mut self, path: &str) -> Result<(), FsError>;
+
+    fn rename(&mut self, old_path: &str, new_path: &str) -> Result<(), FsError>;
+
+    // Removes anything but a directory.
+    fn unlink(&mut self, path: &str) -> Result<(), FsError>;
+
+    // The operations below are optional, backends without them fail with
+    // ENOTSUP.
+
+    // `target` is stored as given.
+    fn symlink(&mut self, _target: &str, _path: &str) -> Result<(), FsError> {
+        Err(FsError::new(FSError::ENOTSUP))
+    }
+
+    fn readlink(&self, _path: &str) -> Result<String, FsError> {
+        Err(FsError::new(FSError::ENOTSUP))
+    }
+
+    // Sets the permission bits, following a symlink at `path`.
+    fn chmod(&mut self, _path: &str, _mode: usize) -> Result<(), FsError> {
+        Err(FsError::new(FSError::ENOTSUP))
+    }
+
+    fn link(&mut self, _existing_path: &str, _new_path: &str) -> Result<(), FsError> {
+        Err(FsError::new(FSError::ENOTSUP))
+    }
+
+    // `path` with all symlinks resolved, failing if it does not exist.
+    fn realpath(&self, _path: &str) -> Result<String, FsError> {
+        Err(FsError::new(FSError::ENOTSUP))
+    }
+}
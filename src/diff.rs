@@ -0,0 +1,303 @@
+// Differences between two volumes, see `Volume::diff` and
+// `Volume::apply_patch`.
+//
+// Entries are matched by path. An entry only one side has is paired up with
+// one of the other side as a rename if both are the same node of the same
+// kind, by `Node::generation`. Nodes are only shared between a volume and its
+// forks and snapshots, so renames are not found between unrelated volumes.
+use std::time::SystemTime;
+
+// Lines of context around the changes of a unified diff.
+const CONTEXT: usize = 3;
+
+// Number of edits after which `unified_diff` stops looking for the shortest
+// edit script and replaces the changed lines as a whole, bounding its memory.
+const MAX_EDITS: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DiffEntryKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+// An entry as it is after the change, enough to recreate it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffEntry {
+    pub kind: DiffEntryKind,
+    pub ino: usize,
+    pub mode: usize,
+    pub uid: usize,
+    pub gid: usize,
+    pub mtime: SystemTime,
+    // Contents of files, the target of symlinks.
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added {
+        path: String,
+        entry: DiffEntry,
+    },
+    Removed {
+        path: String,
+        kind: DiffEntryKind,
+    },
+    // `content` is set if the contents of a file or the target of a symlink
+    // changed, `metadata` if its mode, owner or mtime did. `text` is the
+    // unified diff of files whose versions are both text.
+    Modified {
+        path: String,
+        entry: DiffEntry,
+        content: bool,
+        metadata: bool,
+        text: Option<String>,
+    },
+    // The entry moved, along with everything below it. Other changes to it
+    // follow as a `Modified` of `to`. Entries moved along with a directory
+    // are not listed.
+    Renamed {
+        from: String,
+        to: String,
+    },
+    // The entry was replaced by one of another kind.
+    TypeChanged {
+        path: String,
+        from: DiffEntryKind,
+        entry: DiffEntry,
+    },
+}
+
+impl Change {
+    // Path of the entry after the change, or before for removals.
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Added { path, .. }
+            | Change::Removed { path, .. }
+            | Change::Modified { path, .. }
+            | Change::TypeChanged { path, .. } => path,
+            Change::Renamed { to, .. } => to,
+        }
+    }
+}
+
+// Changes sorted by `Change::path`, see `Volume::diff`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Diff {
+    pub changes: Vec<Change>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+// `data` if it reads as text: UTF-8 without NUL bytes.
+pub fn as_text(data: &[u8]) -> Option<&str> {
+    match data.contains(&0) {
+        true => None,
+        false => std::str::from_utf8(data).ok(),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+// Unified diff of `old` and `new` with the usual three lines of context,
+// headed by the paths. Empty if they are equal.
+pub fn unified_diff(old: &str, new: &str, old_path: &str, new_path: &str) -> String {
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = edit_script(&a, &b);
+    if edits.iter().all(|edit| *edit == Edit::Keep) {
+        return String::new();
+    }
+
+    // Positions in `a` and `b` before each edit.
+    let mut positions = Vec::with_capacity(edits.len());
+    let (mut x, mut y) = (0, 0);
+    for edit in &edits {
+        positions.push((x, y));
+        match edit {
+            Edit::Keep => (x, y) = (x + 1, y + 1),
+            Edit::Delete => x += 1,
+            Edit::Insert => y += 1,
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_path, new_path);
+    let mut i = 0;
+    while i < edits.len() {
+        if edits[i] == Edit::Keep {
+            i += 1;
+            continue;
+        }
+        // Changes less than two contexts apart share a hunk.
+        let start = i.saturating_sub(CONTEXT);
+        let mut end = i;
+        loop {
+            while end < edits.len() && edits[end] != Edit::Keep {
+                end += 1;
+            }
+            let next = edits[end..]
+                .iter()
+                .position(|edit| *edit != Edit::Keep)
+                .map(|keep| end + keep);
+            match next {
+                Some(next) if next - end <= 2 * CONTEXT => end = next,
+                _ => break,
+            }
+        }
+        let stop = (end + CONTEXT).min(edits.len());
+        let hunk = &edits[start..stop];
+        let old_len = hunk.iter().filter(|edit| **edit != Edit::Insert).count();
+        let new_len = hunk.iter().filter(|edit| **edit != Edit::Delete).count();
+        let (x, y) = positions[start];
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(x, old_len),
+            hunk_range(y, new_len)
+        ));
+        for (edit, (x, y)) in hunk.iter().zip(&positions[start..stop]) {
+            let (prefix, line) = match edit {
+                Edit::Keep => (' ', a[*x]),
+                Edit::Delete => ('-', a[*x]),
+                Edit::Insert => ('+', b[*y]),
+            };
+            out.push(prefix);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+        i = stop;
+    }
+    out
+}
+
+// `start,len` of a hunk in GNU style, where `start` counts lines from 1 and
+// empty ranges start at the line before them.
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+// Shortest edit script turning `a` into `b`, by Myers' algorithm on what is
+// left after the common prefix and suffix.
+fn edit_script(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut edits = vec![Edit::Keep; prefix];
+    match myers(a_mid, b_mid) {
+        Some(middle) => edits.extend(middle),
+        None => {
+            edits.extend(std::iter::repeat_n(Edit::Delete, a_mid.len()));
+            edits.extend(std::iter::repeat_n(Edit::Insert, b_mid.len()));
+        }
+    }
+    edits.extend(std::iter::repeat_n(Edit::Keep, suffix));
+    edits
+}
+
+// `None` if the script would take more than `MAX_EDITS` edits.
+fn myers(a: &[&str], b: &[&str]) -> Option<Vec<Edit>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m).min(MAX_EDITS as isize);
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    // Furthest x on each diagonal -d..=d before round d.
+    let mut trace = Vec::new();
+    let furthest = |v: &[isize], k: isize| v[(k + offset) as usize];
+
+    'search: {
+        for d in 0..=max {
+            trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+            for k in (-d..=d).step_by(2) {
+                let mut x = if k == -d || (k != d && furthest(&v, k - 1) < furthest(&v, k + 1)) {
+                    furthest(&v, k + 1)
+                } else {
+                    furthest(&v, k - 1) + 1
+                };
+                let mut y = x - k;
+                while x < n && y < m && a[x as usize] == b[y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                v[(k + offset) as usize] = x;
+                if x >= n && y >= m {
+                    break 'search;
+                }
+            }
+        }
+        return None;
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let furthest = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && furthest(k - 1) < furthest(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = if d == 0 { 0 } else { furthest(prev_k) };
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            edits.push(if x == prev_x {
+                Edit::Insert
+            } else {
+                Edit::Delete
+            });
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    edits.reverse();
+    Some(edits)
+}
+
+#[test]
+fn test_unified_diff() {
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\n";
+    let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\nn";
+    assert_eq!(
+        unified_diff(old, new, "a/x.txt", "b/x.txt"),
+        "--- a/x.txt\n+++ b/x.txt\n\
+         @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+         @@ -11,3 +11,4 @@\n k\n l\n m\n+n\n\\ No newline at end of file\n"
+    );
+    assert_eq!(
+        unified_diff("", "x\n", "/x", "/x"),
+        "--- /x\n+++ /x\n@@ -0,0 +1 @@\n+x\n"
+    );
+    assert_eq!(unified_diff(old, old, "/x", "/x"), "");
+
+    // Scripts too long to search for are replaced wholesale.
+    let old: String = (0..1500).map(|i| format!("{}\n", i)).collect();
+    let new: String = (0..1500).map(|i| format!("{}\n", i * 2 + 1)).collect();
+    let diff = unified_diff(&old, &new, "/x", "/x");
+    assert!(diff.starts_with("--- /x\n+++ /x\n@@ -1,1500 +1,1500 @@\n-0\n-1\n"));
+}
@@ -0,0 +1,209 @@
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Subset of Node's `BufferEncoding` that wasmfs knows how to convert.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Latin1,
+    Ascii,
+    Hex,
+    Base64,
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> Option<Encoding> {
+        match name.to_lowercase().as_str() {
+            "utf8" | "utf-8" => Some(Encoding::Utf8),
+            "latin1" | "binary" => Some(Encoding::Latin1),
+            "ascii" => Some(Encoding::Ascii),
+            "hex" => Some(Encoding::Hex),
+            "base64" => Some(Encoding::Base64),
+            _ => None,
+        }
+    }
+}
+
+pub fn base64_encode(buf: &[u8]) -> String {
+    let mut out = String::with_capacity(buf.len().div_ceil(3) * 4);
+    for chunk in buf.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub fn hex_encode(buf: &[u8]) -> String {
+    buf.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Lenient like Node: characters outside the alphabet are skipped and
+// padding is optional.
+pub fn base64_decode(string: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(string.len() / 4 * 3);
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for c in string.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => continue,
+        };
+        acc = (acc << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    out
+}
+
+// Decodes pairs of hex digits, stopping at the first invalid pair like Node.
+pub fn hex_decode(string: &str) -> Vec<u8> {
+    let digits = string.as_bytes();
+    let mut out = Vec::with_capacity(digits.len() / 2);
+    for pair in digits.chunks_exact(2) {
+        match std::str::from_utf8(pair)
+            .ok()
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+        {
+            Some(byte) => out.push(byte),
+            None => break,
+        }
+    }
+    out
+}
+
+// Converts a string to bytes, like `Buffer.from(string, encoding)`.
+pub fn string_to_buffer(string: &str, encoding: Encoding) -> Vec<u8> {
+    match encoding {
+        Encoding::Utf8 => string.as_bytes().to_vec(),
+        Encoding::Latin1 | Encoding::Ascii => string.chars().map(|c| c as u32 as u8).collect(),
+        Encoding::Hex => hex_decode(string),
+        Encoding::Base64 => base64_decode(string),
+    }
+}
+
+// Converts `buf` to a string in one go, like `buffer.toString(encoding)`.
+pub fn buffer_to_string(buf: &[u8], encoding: Encoding) -> String {
+    let mut decoder = StringDecoder::new(encoding);
+    let mut string = decoder.write(buf);
+    string.push_str(&decoder.end());
+    string
+}
+
+// Port of Node's `string_decoder`: decodes a sequence of chunks without
+// splitting multi-byte characters (or base64 quanta) across chunk boundaries.
+pub struct StringDecoder {
+    encoding: Encoding,
+    pending: Vec<u8>,
+}
+
+impl StringDecoder {
+    pub fn new(encoding: Encoding) -> Self {
+        StringDecoder {
+            encoding,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, buf: &[u8]) -> String {
+        match self.encoding {
+            Encoding::Utf8 => {
+                self.pending.extend_from_slice(buf);
+                let mut out = String::new();
+                loop {
+                    match std::str::from_utf8(&self.pending) {
+                        Ok(valid) => {
+                            out.push_str(valid);
+                            self.pending.clear();
+                            break;
+                        }
+                        Err(err) => {
+                            let valid_up_to = err.valid_up_to();
+                            out.push_str(
+                                std::str::from_utf8(&self.pending[..valid_up_to]).unwrap(),
+                            );
+                            match err.error_len() {
+                                Some(len) => {
+                                    out.push(char::REPLACEMENT_CHARACTER);
+                                    self.pending.drain(..valid_up_to + len);
+                                }
+                                None => {
+                                    // Incomplete character at the end, wait for more bytes.
+                                    self.pending.drain(..valid_up_to);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+                out
+            }
+            Encoding::Latin1 => buf.iter().map(|b| *b as char).collect(),
+            Encoding::Ascii => buf.iter().map(|b| (b & 0x7f) as char).collect(),
+            Encoding::Hex => hex_encode(buf),
+            Encoding::Base64 => {
+                self.pending.extend_from_slice(buf);
+                let complete = self.pending.len() - self.pending.len() % 3;
+                let out = base64_encode(&self.pending[..complete]);
+                self.pending.drain(..complete);
+                out
+            }
+        }
+    }
+
+    pub fn end(&mut self) -> String {
+        let pending = std::mem::take(&mut self.pending);
+        if pending.is_empty() {
+            return String::new();
+        }
+        match self.encoding {
+            Encoding::Utf8 => String::from(char::REPLACEMENT_CHARACTER),
+            Encoding::Base64 => base64_encode(&pending),
+            _ => String::new(),
+        }
+    }
+}
+
+#[test]
+fn test_string_decoder_utf8_split_character() {
+    let mut decoder = StringDecoder::new(Encoding::Utf8);
+    let euro = "€".as_bytes();
+    assert_eq!(decoder.write(&euro[..1]), "");
+    assert_eq!(decoder.write(&euro[1..]), "€");
+    assert_eq!(decoder.write(&[0xe2]), "");
+    assert_eq!(decoder.end(), "\u{fffd}");
+}
+
+#[test]
+fn test_string_decoder_base64() {
+    let mut decoder = StringDecoder::new(Encoding::Base64);
+    assert_eq!(decoder.write(b"hell"), "aGVs");
+    assert_eq!(decoder.write(b"o"), "");
+    assert_eq!(decoder.end(), "bG8=");
+    assert_eq!(buffer_to_string(b"hello", Encoding::Hex), "68656c6c6f");
+}
+
+#[test]
+fn test_string_to_buffer() {
+    assert_eq!(string_to_buffer("aGVsbG8=", Encoding::Base64), b"hello");
+    assert_eq!(string_to_buffer("68656c6c6fzz", Encoding::Hex), b"hello");
+    assert_eq!(string_to_buffer("é", Encoding::Latin1), vec![0xe9]);
+}
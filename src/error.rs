@@ -1,8 +1,14 @@
 #![allow(non_camel_case_types)]
 
+#[cfg(feature = "wasm")]
 use js_sys::{Error as JsError, Reflect};
-use std::fmt::{Display, Formatter, Result};
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result},
+    io,
+};
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FSError {
     ENOENT,
     EBADF,
@@ -16,6 +22,16 @@ pub enum FSError {
     EISDIR,
     ENOTEMPTY,
     ENOSYS,
+    ELOOP,
+    ENAMETOOLONG,
+    ENOSPC,
+    EXDEV,
+    EROFS,
+    EBUSY,
+    ENOTSUP,
+    EFBIG,
+    EAGAIN,
+    EIO,
     ERR_FS_EISDIR,
 }
 
@@ -34,6 +50,16 @@ impl From<FSError> for String {
             FSError::EISDIR => String::from("EISDIR"),
             FSError::ENOTEMPTY => String::from("ENOTEMPTY"),
             FSError::ENOSYS => String::from("ENOSYS"),
+            FSError::ELOOP => String::from("ELOOP"),
+            FSError::ENAMETOOLONG => String::from("ENAMETOOLONG"),
+            FSError::ENOSPC => String::from("ENOSPC"),
+            FSError::EXDEV => String::from("EXDEV"),
+            FSError::EROFS => String::from("EROFS"),
+            FSError::EBUSY => String::from("EBUSY"),
+            FSError::ENOTSUP => String::from("ENOTSUP"),
+            FSError::EFBIG => String::from("EFBIG"),
+            FSError::EAGAIN => String::from("EAGAIN"),
+            FSError::EIO => String::from("EIO"),
             FSError::ERR_FS_EISDIR => String::from("ERR_FS_EISDIR"),
         }
     }
@@ -54,17 +80,264 @@ impl Display for FSError {
             FSError::EISDIR => write!(f, "EISDIR: illegal operation on a directory"),
             FSError::ENOTEMPTY => write!(f, "ENOTEMPTY: directory not empty"),
             FSError::ENOSYS => write!(f, "ENOSYS: function not implemented"),
+            FSError::ELOOP => write!(f, "ELOOP: too many symbolic links encountered"),
+            FSError::ENAMETOOLONG => write!(f, "ENAMETOOLONG: name too long"),
+            FSError::ENOSPC => write!(f, "ENOSPC: no space left on device"),
+            FSError::EXDEV => write!(f, "EXDEV: cross-device link not permitted"),
+            FSError::EROFS => write!(f, "EROFS: read-only file system"),
+            FSError::EBUSY => write!(f, "EBUSY: resource busy or locked"),
+            FSError::ENOTSUP => write!(f, "ENOTSUP: operation not supported on socket"),
+            FSError::EFBIG => write!(f, "EFBIG: file too large"),
+            FSError::EAGAIN => write!(f, "EAGAIN: resource temporarily unavailable"),
+            FSError::EIO => write!(f, "EIO: i/o error"),
             FSError::ERR_FS_EISDIR => write!(f, "ERR_FS_EISDIR: illegal operation on a directory"),
         }
     }
 }
 
-pub fn create_error(code: FSError, func: Option<String>, paths: Option<Vec<String>>) -> JsError {
-    let func = func.unwrap_or(String::from("unknown"));
-    let paths = paths.unwrap_or(Vec::new());
-    let message = format!("{}@{}: {}", code, func, paths.join(", "));
-    let code = String::from(code);
-    let error = JsError::new(&message);
-    Reflect::set(&error, &"code".into(), &code.into()).unwrap();
-    error
+impl FSError {
+    // POSIX errno number, as used by Linux.
+    pub fn errno(&self) -> i32 {
+        match *self {
+            FSError::ENOENT => 2,
+            FSError::EBADF => 9,
+            FSError::EINVAL => 22,
+            FSError::EPERM => 1,
+            FSError::EPROTO => 71,
+            FSError::EEXIST => 17,
+            FSError::ENOTDIR => 20,
+            FSError::EMFILE => 24,
+            FSError::EACCES => 13,
+            FSError::EISDIR | FSError::ERR_FS_EISDIR => 21,
+            FSError::ENOTEMPTY => 39,
+            FSError::ENOSYS => 38,
+            FSError::ELOOP => 40,
+            FSError::ENAMETOOLONG => 36,
+            FSError::ENOSPC => 28,
+            FSError::EXDEV => 18,
+            FSError::EROFS => 30,
+            FSError::EBUSY => 16,
+            FSError::ENOTSUP => 95,
+            FSError::EFBIG => 27,
+            FSError::EAGAIN => 11,
+            FSError::EIO => 5,
+        }
+    }
+
+    // Code of the Linux errno number `errno`, if it is one of ours.
+    pub fn from_errno(errno: i32) -> Option<FSError> {
+        let code = match errno {
+            1 => FSError::EPERM,
+            2 => FSError::ENOENT,
+            5 => FSError::EIO,
+            9 => FSError::EBADF,
+            11 => FSError::EAGAIN,
+            13 => FSError::EACCES,
+            16 => FSError::EBUSY,
+            17 => FSError::EEXIST,
+            18 => FSError::EXDEV,
+            20 => FSError::ENOTDIR,
+            21 => FSError::EISDIR,
+            22 => FSError::EINVAL,
+            24 => FSError::EMFILE,
+            27 => FSError::EFBIG,
+            28 => FSError::ENOSPC,
+            30 => FSError::EROFS,
+            36 => FSError::ENAMETOOLONG,
+            38 => FSError::ENOSYS,
+            39 => FSError::ENOTEMPTY,
+            40 => FSError::ELOOP,
+            71 => FSError::EPROTO,
+            95 => FSError::ENOTSUP,
+            _ => return None,
+        };
+        Some(code)
+    }
+
+    pub fn kind(&self) -> io::ErrorKind {
+        match *self {
+            FSError::ENOENT => io::ErrorKind::NotFound,
+            FSError::EPERM | FSError::EACCES => io::ErrorKind::PermissionDenied,
+            FSError::EEXIST => io::ErrorKind::AlreadyExists,
+            FSError::EINVAL | FSError::ENAMETOOLONG => io::ErrorKind::InvalidInput,
+            FSError::ENOTDIR => io::ErrorKind::NotADirectory,
+            FSError::EISDIR | FSError::ERR_FS_EISDIR => io::ErrorKind::IsADirectory,
+            FSError::ENOTEMPTY => io::ErrorKind::DirectoryNotEmpty,
+            FSError::ENOSYS | FSError::ENOTSUP => io::ErrorKind::Unsupported,
+            FSError::ENOSPC => io::ErrorKind::StorageFull,
+            FSError::EROFS => io::ErrorKind::ReadOnlyFilesystem,
+            FSError::EBUSY => io::ErrorKind::ResourceBusy,
+            FSError::EFBIG => io::ErrorKind::FileTooLarge,
+            FSError::EAGAIN => io::ErrorKind::WouldBlock,
+            FSError::EBADF
+            | FSError::EPROTO
+            | FSError::EMFILE
+            | FSError::ELOOP
+            | FSError::EXDEV
+            | FSError::EIO => io::ErrorKind::Other,
+        }
+    }
+}
+
+// Error returned by all filesystem operations, carrying the same details as
+// Node's `SystemError`. Converted into a JS `Error` only when crossing into
+// the wasm bindings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsError {
+    pub code: FSError,
+    // Positive errno number, JS errors carry it negated like Node does.
+    pub errno: i32,
+    pub syscall: Option<String>,
+    pub path: Option<String>,
+    pub dest: Option<String>,
+}
+
+impl FsError {
+    pub fn new(code: FSError) -> Self {
+        FsError {
+            code,
+            errno: code.errno(),
+            syscall: None,
+            path: None,
+            dest: None,
+        }
+    }
+
+    pub fn kind(&self) -> io::ErrorKind {
+        self.code.kind()
+    }
+}
+
+// Formats like Node: "ENOENT: no such file or directory, open '/foo'".
+impl Display for FsError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{}", self.code)?;
+        if let Some(syscall) = &self.syscall {
+            write!(f, ", {}", syscall)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, " '{}'", path)?;
+        }
+        if let Some(dest) = &self.dest {
+            write!(f, " -> '{}'", dest)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for FsError {}
+
+impl From<FsError> for io::Error {
+    fn from(err: FsError) -> Self {
+        io::Error::new(err.kind(), err)
+    }
+}
+
+// Errors of readers and writers handed to the volume, e.g. when streaming an
+// archive. `FsError`s passed through `io::Error` come back unchanged.
+impl From<io::Error> for FsError {
+    fn from(err: io::Error) -> Self {
+        if let Some(err) = err.get_ref().and_then(|err| err.downcast_ref::<FsError>()) {
+            return err.clone();
+        }
+        // Errors of the host's filesystem, see `host::HostDirectory`. Their
+        // errno tells more than their kind, e.g. for ELOOP or EMFILE, but
+        // only matches our numbers on Linux.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let Some(code) = err.raw_os_error().and_then(FSError::from_errno) {
+            return FsError::new(code);
+        }
+        let code = match err.kind() {
+            io::ErrorKind::InvalidData
+            | io::ErrorKind::InvalidInput
+            | io::ErrorKind::UnexpectedEof => FSError::EINVAL,
+            io::ErrorKind::NotFound => FSError::ENOENT,
+            io::ErrorKind::PermissionDenied => FSError::EACCES,
+            io::ErrorKind::AlreadyExists => FSError::EEXIST,
+            io::ErrorKind::NotADirectory => FSError::ENOTDIR,
+            io::ErrorKind::IsADirectory => FSError::EISDIR,
+            io::ErrorKind::DirectoryNotEmpty => FSError::ENOTEMPTY,
+            io::ErrorKind::StorageFull => FSError::ENOSPC,
+            io::ErrorKind::ReadOnlyFilesystem => FSError::EROFS,
+            io::ErrorKind::ResourceBusy => FSError::EBUSY,
+            io::ErrorKind::FileTooLarge => FSError::EFBIG,
+            io::ErrorKind::Unsupported => FSError::ENOTSUP,
+            _ => FSError::EIO,
+        };
+        FsError::new(code)
+    }
+}
+
+// `paths` holds the path and, for two-path operations like `rename`, the
+// destination.
+pub fn create_error(code: FSError, func: Option<String>, paths: Option<Vec<String>>) -> FsError {
+    let mut paths = paths.unwrap_or_default().into_iter();
+    FsError {
+        syscall: func,
+        path: paths.next(),
+        dest: paths.next(),
+        ..FsError::new(code)
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl From<FsError> for JsError {
+    fn from(err: FsError) -> Self {
+        let error = JsError::new(&err.to_string());
+        let code = String::from(err.code);
+        Reflect::set(&error, &"code".into(), &code.into()).unwrap();
+        Reflect::set(&error, &"errno".into(), &(-err.errno).into()).unwrap();
+        if let Some(syscall) = err.syscall {
+            Reflect::set(&error, &"syscall".into(), &syscall.into()).unwrap();
+        }
+        if let Some(path) = err.path {
+            Reflect::set(&error, &"path".into(), &path.into()).unwrap();
+        }
+        if let Some(dest) = err.dest {
+            Reflect::set(&error, &"dest".into(), &dest.into()).unwrap();
+        }
+        error
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl From<FsError> for wasm_bindgen::JsValue {
+    fn from(err: FsError) -> Self {
+        JsError::from(err).into()
+    }
+}
+
+#[test]
+fn test_fs_error_display_and_io_conversion() {
+    let err = create_error(
+        FSError::ENOENT,
+        Some(String::from("rename")),
+        Some(vec![String::from("/a"), String::from("/b")]),
+    );
+    assert_eq!(err.errno, 2);
+    assert_eq!(
+        err.to_string(),
+        "ENOENT: no such file or directory, rename '/a' -> '/b'"
+    );
+
+    let io_err = io::Error::from(err.clone());
+    assert_eq!(io_err.kind(), io::ErrorKind::NotFound);
+    let inner = io_err.get_ref().unwrap().downcast_ref::<FsError>();
+    assert_eq!(inner, Some(&err));
+    assert_eq!(FsError::from(io_err), err);
+    let err = FsError::from(io::Error::from(io::ErrorKind::BrokenPipe));
+    assert_eq!(err.to_string(), "EIO: i/o error");
+    let err = FsError::from(io::Error::from(io::ErrorKind::DirectoryNotEmpty));
+    assert_eq!(err.code, FSError::ENOTEMPTY);
+    #[cfg(target_os = "linux")]
+    for (errno, code) in [(40, FSError::ELOOP), (24, FSError::EMFILE)] {
+        let err = FsError::from(io::Error::from_raw_os_error(errno));
+        assert_eq!(err.code, code);
+        assert_eq!(err.errno, errno);
+    }
+    assert_eq!(
+        FSError::from_errno(FSError::EAGAIN.errno()),
+        Some(FSError::EAGAIN)
+    );
+    assert_eq!(FSError::from_errno(1000), None);
 }
@@ -0,0 +1,64 @@
+use std::{cell::RefCell, sync::Arc};
+
+// Handle returned by `EventEmitter::on`, used to detach the listener again.
+pub type ListenerId = usize;
+
+pub type Listener<E> = Arc<RefCell<dyn FnMut(&E)>>;
+
+// A minimal, synchronous replacement for Node's `EventEmitter`. Listeners are
+// invoked in subscription order on the same call stack as `emit`.
+pub struct EventEmitter<E> {
+    next_id: ListenerId,
+    listeners: Vec<(ListenerId, Listener<E>)>,
+}
+
+impl<E> Default for EventEmitter<E> {
+    fn default() -> Self {
+        EventEmitter {
+            next_id: 0,
+            listeners: Vec::new(),
+        }
+    }
+}
+
+impl<E> EventEmitter<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on(&mut self, listener: impl FnMut(&E) + 'static) -> ListenerId {
+        self.next_id += 1;
+        self.listeners
+            .push((self.next_id, Arc::new(RefCell::new(listener))));
+        self.next_id
+    }
+
+    pub fn off(&mut self, id: ListenerId) -> bool {
+        let len = self.listeners.len();
+        self.listeners.retain(|(listener_id, _)| *listener_id != id);
+        self.listeners.len() != len
+    }
+
+    pub fn remove_all_listeners(&mut self) {
+        self.listeners.clear();
+    }
+
+    pub fn listener_count(&self) -> usize {
+        self.listeners.len()
+    }
+
+    pub fn emit(&mut self, event: &E) {
+        for (_, listener) in self.listeners.iter() {
+            (listener.borrow_mut())(event);
+        }
+    }
+
+    // Copies out the current listeners, so that they can be invoked after the
+    // owner of this emitter has been released and may be re-entered.
+    pub fn listeners(&self) -> Vec<Listener<E>> {
+        self.listeners
+            .iter()
+            .map(|(_, listener)| listener.clone())
+            .collect()
+    }
+}
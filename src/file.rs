@@ -1,103 +1,84 @@
-use crate::{link::Link, node::Node, stats::Stats};
-use std::{cell::RefCell, sync::Arc};
+use crate::{
+    arena::{InodeId, LinkId},
+    constants::constants,
+    node::Node,
+};
 
+// Advisory lock held by an open file description, see `Volume::flock_sync`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileLock {
+    Shared,
+    Exclusive,
+}
+
+// An open file description. The node it reads from and writes to is owned by
+// the volume and passed in by the caller. The node outlives its links while
+// the file is open.
 pub struct File {
     pub fd: usize,
-    pub link: Arc<RefCell<Link>>,
-    pub node: Arc<RefCell<Node>>,
+    pub node: InodeId,
     pub position: usize,
     pub flags: usize,
+    // Mount and path within its backend of a file of a mounted backend, its
+    // contents are kept in step with, see `Volume::reload_backing`.
+    pub backing: Option<(LinkId, String)>,
+    // The node is a private copy no link points to, freed when the file is
+    // closed.
+    pub detached: bool,
+    pub lock: Option<FileLock>,
 }
 
 impl File {
-    pub fn new(
-        link: Arc<RefCell<Link>>,
-        node: Arc<RefCell<Node>>,
-        flags: usize,
-        fd: usize,
-    ) -> Arc<RefCell<File>> {
-        Arc::new(RefCell::new(File {
+    pub fn new(node: InodeId, flags: usize, fd: usize) -> File {
+        File {
             fd,
-            link,
             node,
             position: 0,
             flags,
-        }))
-    }
-
-    pub fn get_string(&self, encoding: Option<String>) -> String {
-        let encoding = encoding.unwrap_or(String::from("utf8")).to_lowercase();
-        assert!(encoding == "utf8" || encoding == "utf-8");
-        let mut node = self.node.borrow_mut();
-        return node.get_string();
-    }
-
-    pub fn set_string(&mut self, str: String) {
-        let mut node = self.node.borrow_mut();
-        node.set_string(str);
-    }
-
-    pub fn get_buffer(&self) -> Vec<u8> {
-        let mut node = self.node.borrow_mut();
-        return node.get_buffer();
-    }
-
-    pub fn set_buffer(&mut self, buf: Vec<u8>) {
-        let mut node = self.node.borrow_mut();
-        node.set_buffer(buf);
-    }
-
-    pub fn get_size(&self) -> usize {
-        let mut node = self.node.borrow_mut();
-        return node.get_size();
-    }
-
-    pub fn truncate(&mut self, len: usize) {
-        let mut node = self.node.borrow_mut();
-        node.truncate(len);
+            backing: None,
+            detached: false,
+            lock: None,
+        }
     }
 
     pub fn seek_to(&mut self, pos: usize) {
         self.position = pos;
     }
 
-    pub fn stats(&self) -> Stats {
-        unimplemented!();
+    // Where a write at `position` actually starts, taking `O_APPEND` into
+    // account.
+    pub fn write_position(&self, node: &Node, position: Option<usize>) -> usize {
+        if self.flags & constants::O_APPEND != 0 {
+            return node.get_size();
+        }
+        position.unwrap_or(self.position)
     }
 
     pub fn write(
         &mut self,
-        buf: Vec<u8>,
+        node: &mut Node,
+        buf: &[u8],
         offset: Option<usize>,
         length: Option<usize>,
         position: Option<usize>,
     ) -> usize {
-        let mut node = self.node.borrow_mut();
-        let bytes = node.write(buf, offset, length, position);
-        self.position = position.unwrap_or(self.position) + bytes;
-        return bytes;
+        let position = self.write_position(node, position);
+        let bytes = node.write(buf, offset, length, Some(position));
+        self.position = position + bytes;
+        bytes
     }
 
     pub fn read(
         &mut self,
-        buf: Vec<u8>,
+        node: &mut Node,
+        buf: &mut [u8],
         offset: Option<usize>,
         length: Option<usize>,
         position: Option<usize>,
     ) -> usize {
-        let mut node = self.node.borrow_mut();
-        let bytes = node.read(buf, offset, length, position);
-        self.position = position.unwrap_or(self.position) + bytes;
-        return bytes;
-    }
-
-    pub fn chmod(&mut self, perm: usize) {
-        let mut node = self.node.borrow_mut();
-        node.chmod(perm);
-    }
-
-    pub fn chown(&mut self, uid: usize, gid: usize) {
-        let mut node = self.node.borrow_mut();
-        node.chown(uid, gid);
+        let position = position.unwrap_or(self.position);
+        let bytes = node.read(buf, offset, length, Some(position));
+        self.position = position + bytes;
+        bytes
     }
 }
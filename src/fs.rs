@@ -0,0 +1,577 @@
+// Mirror of `std::fs` operating on a `Volume`: every function takes the
+// volume as its first argument and otherwise has the signature of its `std`
+// counterpart, so native code can swap one for the other.
+use crate::{
+    constants::constants,
+    handle::FileHandle,
+    stats::Stats,
+    volume::{MkdirOptions, Volume},
+};
+use std::{
+    cell::RefCell,
+    ffi::OsString,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+fn path_string<P: AsRef<Path>>(path: P) -> String {
+    path.as_ref().to_string_lossy().into_owned()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileType {
+    mode: usize,
+}
+
+impl FileType {
+    pub fn is_dir(&self) -> bool {
+        self.mode & constants::S_IFMT == constants::S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & constants::S_IFMT == constants::S_IFREG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & constants::S_IFMT == constants::S_IFLNK
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Permissions {
+    mode: usize,
+}
+
+impl Permissions {
+    pub fn from_mode(mode: u32) -> Permissions {
+        Permissions {
+            mode: mode as usize & 0o777,
+        }
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.mode as u32
+    }
+
+    pub fn readonly(&self) -> bool {
+        self.mode & 0o222 == 0
+    }
+
+    pub fn set_readonly(&mut self, readonly: bool) {
+        if readonly {
+            self.mode &= !0o222;
+        } else {
+            self.mode |= 0o222;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Metadata {
+    stats: Stats,
+}
+
+impl Metadata {
+    pub fn file_type(&self) -> FileType {
+        FileType {
+            mode: self.stats.mode,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.stats.is_directory()
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.stats.is_file()
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.stats.is_symbolic_link()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.stats.size as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stats.size == 0
+    }
+
+    pub fn permissions(&self) -> Permissions {
+        Permissions::from_mode(self.stats.mode as u32)
+    }
+
+    pub fn modified(&self) -> io::Result<SystemTime> {
+        Ok(self.stats.mtime)
+    }
+
+    pub fn accessed(&self) -> io::Result<SystemTime> {
+        Ok(self.stats.atime)
+    }
+
+    pub fn created(&self) -> io::Result<SystemTime> {
+        Ok(self.stats.birthtime)
+    }
+
+    // The underlying Node-style stats, for `ino`, `nlink` and friends.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+}
+
+pub struct DirEntry {
+    vol: Arc<RefCell<Volume>>,
+    path: PathBuf,
+    mode: usize,
+}
+
+impl DirEntry {
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    pub fn file_name(&self) -> OsString {
+        self.path.file_name().unwrap_or_default().to_os_string()
+    }
+
+    pub fn file_type(&self) -> io::Result<FileType> {
+        Ok(FileType { mode: self.mode })
+    }
+
+    // Like `std`, does not follow a symlink entry.
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        symlink_metadata(&self.vol, &self.path)
+    }
+}
+
+pub struct ReadDir {
+    entries: std::vec::IntoIter<DirEntry>,
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(Ok)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+    mode: Option<usize>,
+}
+
+impl OpenOptions {
+    pub fn new() -> OpenOptions {
+        OpenOptions::default()
+    }
+
+    pub fn read(&mut self, read: bool) -> &mut OpenOptions {
+        self.read = read;
+        self
+    }
+
+    pub fn write(&mut self, write: bool) -> &mut OpenOptions {
+        self.write = write;
+        self
+    }
+
+    pub fn append(&mut self, append: bool) -> &mut OpenOptions {
+        self.append = append;
+        self
+    }
+
+    pub fn truncate(&mut self, truncate: bool) -> &mut OpenOptions {
+        self.truncate = truncate;
+        self
+    }
+
+    pub fn create(&mut self, create: bool) -> &mut OpenOptions {
+        self.create = create;
+        self
+    }
+
+    pub fn create_new(&mut self, create_new: bool) -> &mut OpenOptions {
+        self.create_new = create_new;
+        self
+    }
+
+    // Permissions of newly created files, like `OpenOptionsExt::mode`.
+    pub fn mode(&mut self, mode: u32) -> &mut OpenOptions {
+        self.mode = Some(mode as usize);
+        self
+    }
+
+    pub fn open<P: AsRef<Path>>(&self, vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<File> {
+        let flags = self.flags()?;
+        let handle =
+            vol.borrow_mut()
+                .open_handle_with_flags(path_string(path), flags, self.mode)?;
+        Ok(File { handle })
+    }
+
+    // Maps the options to open flags, rejecting the same combinations as
+    // `std`.
+    fn flags(&self) -> io::Result<usize> {
+        let invalid = || io::Error::from(io::ErrorKind::InvalidInput);
+        let write = self.write || self.append;
+        let mut flags = match (self.read, write) {
+            (true, false) => constants::O_RDONLY,
+            (false, true) => constants::O_WRONLY,
+            (true, true) => constants::O_RDWR,
+            (false, false) => return Err(invalid()),
+        };
+        if !write && (self.truncate || self.create || self.create_new) {
+            return Err(invalid());
+        }
+        if self.append {
+            if self.truncate && !self.create_new {
+                return Err(invalid());
+            }
+            flags |= constants::O_APPEND;
+        }
+        if self.create_new {
+            flags |= constants::O_CREAT | constants::O_EXCL;
+        } else {
+            if self.create {
+                flags |= constants::O_CREAT;
+            }
+            if self.truncate {
+                flags |= constants::O_TRUNC;
+            }
+        }
+        Ok(flags)
+    }
+}
+
+// An open file, like `std::fs::File`.
+pub struct File {
+    handle: FileHandle,
+}
+
+impl File {
+    pub fn open<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<File> {
+        OpenOptions::new().read(true).open(vol, path)
+    }
+
+    pub fn create<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<File> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(vol, path)
+    }
+
+    pub fn create_new<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(vol, path)
+    }
+
+    pub fn options() -> OpenOptions {
+        OpenOptions::new()
+    }
+
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        let stats = self.handle.stats()?;
+        Ok(Metadata { stats })
+    }
+
+    pub fn set_len(&self, size: u64) -> io::Result<()> {
+        self.handle.set_len(size as usize)?;
+        Ok(())
+    }
+
+    // Everything lives in memory, there is nothing to sync.
+    pub fn sync_all(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn sync_data(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn into_handle(self) -> FileHandle {
+        self.handle
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.handle.read(buf)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.handle.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.handle.flush()
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.handle.seek(pos)
+    }
+}
+
+pub fn read<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<Vec<u8>> {
+    let buf = vol.borrow_mut().read_file_sync(path_string(path))?;
+    Ok(buf)
+}
+
+pub fn read_to_string<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<String> {
+    String::from_utf8(read(vol, path)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(
+    vol: &Arc<RefCell<Volume>>,
+    path: P,
+    contents: C,
+) -> io::Result<()> {
+    vol.borrow_mut()
+        .write_file_sync(path_string(path), contents.as_ref(), None)?;
+    Ok(())
+}
+
+pub fn create_dir<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<()> {
+    vol.borrow_mut().mkdir_sync(path_string(path), None)?;
+    Ok(())
+}
+
+pub fn create_dir_all<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<()> {
+    let options = MkdirOptions {
+        recursive: Some(true),
+        ..Default::default()
+    };
+    vol.borrow_mut()
+        .mkdir_sync(path_string(path), Some(options))?;
+    Ok(())
+}
+
+pub fn read_dir<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<ReadDir> {
+    let dir = path.as_ref().to_path_buf();
+    let dirents = vol
+        .borrow_mut()
+        .readdir_with_file_types_sync(path_string(&dir))?;
+    let entries: Vec<DirEntry> = dirents
+        .into_iter()
+        .map(|dirent| DirEntry {
+            vol: vol.clone(),
+            path: dir.join(dirent.name),
+            mode: dirent.mode,
+        })
+        .collect();
+    Ok(ReadDir {
+        entries: entries.into_iter(),
+    })
+}
+
+pub fn metadata<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<Metadata> {
+    let stats = vol.borrow_mut().stat_sync(path_string(path), None)?;
+    Ok(Metadata { stats })
+}
+
+pub fn symlink_metadata<P: AsRef<Path>>(
+    vol: &Arc<RefCell<Volume>>,
+    path: P,
+) -> io::Result<Metadata> {
+    let stats = vol.borrow_mut().lstat_sync(path_string(path), None)?;
+    Ok(Metadata { stats })
+}
+
+pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(
+    vol: &Arc<RefCell<Volume>>,
+    from: P,
+    to: Q,
+) -> io::Result<()> {
+    vol.borrow_mut()
+        .rename_sync(path_string(from), path_string(to))?;
+    Ok(())
+}
+
+// Returns the number of bytes copied, like `std::fs::copy`.
+pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(
+    vol: &Arc<RefCell<Volume>>,
+    from: P,
+    to: Q,
+) -> io::Result<u64> {
+    let to = path_string(to);
+    let mut vol = vol.borrow_mut();
+    vol.copy_file_sync(path_string(from), to.clone(), None)?;
+    let stats = vol.stat_sync(to, None)?;
+    Ok(stats.size as u64)
+}
+
+pub fn remove_file<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<()> {
+    vol.borrow_mut().unlink_sync(path_string(path))?;
+    Ok(())
+}
+
+pub fn remove_dir<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<()> {
+    vol.borrow_mut().rmdir_sync(path_string(path), None)?;
+    Ok(())
+}
+
+// Like std, removes a symlink at `path` itself rather than what it points to.
+pub fn remove_dir_all<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<()> {
+    let path = path_string(path);
+    let mut vol = vol.borrow_mut();
+    if vol.lstat_sync(path.clone(), None)?.is_symbolic_link() {
+        vol.unlink_sync(path)?;
+        return Ok(());
+    }
+    vol.rmdir_sync(path, Some(true))?;
+    Ok(())
+}
+
+pub fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(
+    vol: &Arc<RefCell<Volume>>,
+    original: P,
+    link: Q,
+) -> io::Result<()> {
+    vol.borrow_mut()
+        .link_sync(path_string(original), path_string(link))?;
+    Ok(())
+}
+
+// Like `std::os::unix::fs::symlink`.
+pub fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(
+    vol: &Arc<RefCell<Volume>>,
+    original: P,
+    link: Q,
+) -> io::Result<()> {
+    vol.borrow_mut()
+        .symlink_sync(path_string(original), path_string(link))?;
+    Ok(())
+}
+
+pub fn read_link<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<PathBuf> {
+    let target = vol.borrow_mut().readlink_sync(path_string(path))?;
+    Ok(PathBuf::from(target))
+}
+
+pub fn canonicalize<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<PathBuf> {
+    let path = vol.borrow_mut().realpath_sync(path_string(path))?;
+    Ok(PathBuf::from(path))
+}
+
+pub fn set_permissions<P: AsRef<Path>>(
+    vol: &Arc<RefCell<Volume>>,
+    path: P,
+    perm: Permissions,
+) -> io::Result<()> {
+    vol.borrow_mut().chmod_sync(path_string(path), perm.mode)?;
+    Ok(())
+}
+
+pub fn exists<P: AsRef<Path>>(vol: &Arc<RefCell<Volume>>, path: P) -> io::Result<bool> {
+    Ok(vol.borrow_mut().exists_sync(path_string(path)))
+}
+
+#[test]
+fn test_fs_mirror() {
+    let vol = Volume::new();
+    create_dir_all(&vol, "/project/src").unwrap();
+    create_dir_all(&vol, "/project/src").unwrap();
+    write(&vol, "/project/src/main.rs", "fn main() {}").unwrap();
+    write(&vol, "/project/Cargo.toml", "[package]").unwrap();
+    symlink(&vol, "src/main.rs", "/project/entry").unwrap();
+
+    let names: Vec<(String, bool)> = read_dir(&vol, "/project")
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let name = entry.file_name().into_string().unwrap();
+            (name, entry.file_type().unwrap().is_dir())
+        })
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            (String::from("Cargo.toml"), false),
+            (String::from("entry"), false),
+            (String::from("src"), true),
+        ]
+    );
+
+    assert_eq!(
+        read_to_string(&vol, "/project/entry").unwrap(),
+        "fn main() {}"
+    );
+    assert!(symlink_metadata(&vol, "/project/entry")
+        .unwrap()
+        .is_symlink());
+    assert_eq!(metadata(&vol, "/project/entry").unwrap().len(), 12);
+    assert_eq!(
+        read_link(&vol, "/project/entry").unwrap(),
+        PathBuf::from("src/main.rs")
+    );
+    assert_eq!(
+        canonicalize(&vol, "/project/entry").unwrap(),
+        PathBuf::from("/project/src/main.rs")
+    );
+
+    symlink(&vol, "/project/src", "/src").unwrap();
+    remove_dir_all(&vol, "/src").unwrap();
+    assert!(symlink_metadata(&vol, "/src").is_err());
+    assert!(exists(&vol, "/project/src/main.rs").unwrap());
+
+    assert_eq!(copy(&vol, "/project/Cargo.toml", "/Cargo.toml").unwrap(), 9);
+    rename(&vol, "/project/src", "/project/lib").unwrap();
+    assert!(exists(&vol, "/project/lib/main.rs").unwrap());
+    assert!(!exists(&vol, "/project/entry").unwrap());
+
+    let err = remove_dir(&vol, "/project").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::DirectoryNotEmpty);
+    remove_dir_all(&vol, "/project").unwrap();
+    let err = metadata(&vol, "/project").err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_fs_open_options() {
+    let vol = Volume::new();
+    let mut file = File::create(&vol, "/log.txt").unwrap();
+    file.write_all(b"first\n").unwrap();
+    drop(file);
+
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(&vol, "/log.txt")
+        .unwrap();
+    file.write_all(b"second\n").unwrap();
+    assert_eq!(file.metadata().unwrap().len(), 13);
+    drop(file);
+
+    let err = File::create_new(&vol, "/log.txt").err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    let err = OpenOptions::new()
+        .write(true)
+        .open(&vol, "/missing.txt")
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    let mut contents = String::new();
+    File::open(&vol, "/log.txt")
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!(contents, "first\nsecond\n");
+}
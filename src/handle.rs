@@ -0,0 +1,133 @@
+use crate::{error::FsError, stats::Stats, volume::Volume};
+use std::{
+    cell::RefCell,
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    sync::Arc,
+};
+
+// An open file descriptor usable with `std::io`, so that in-memory files can
+// be handed to crates reading from or writing to `Read`/`Write`. Reads and
+// writes go through the descriptor's position, which `Seek` moves. The
+// descriptor is closed when the handle is dropped.
+pub struct FileHandle {
+    vol: Arc<RefCell<Volume>>,
+    fd: usize,
+    closed: bool,
+}
+
+impl FileHandle {
+    pub fn open(
+        vol: Arc<RefCell<Volume>>,
+        path: String,
+        flags: Option<String>,
+        mode: Option<usize>,
+    ) -> Result<FileHandle, FsError> {
+        let fd = vol.borrow_mut().open_sync(path, flags, mode)?;
+        Ok(FileHandle::from_fd(vol, fd))
+    }
+
+    pub(crate) fn from_fd(vol: Arc<RefCell<Volume>>, fd: usize) -> FileHandle {
+        FileHandle {
+            vol,
+            fd,
+            closed: false,
+        }
+    }
+
+    pub fn fd(&self) -> usize {
+        self.fd
+    }
+
+    pub fn stats(&self) -> Result<Stats, FsError> {
+        self.vol.borrow_mut().fstat_sync(self.fd, None)
+    }
+
+    // Truncates or zero-extends the file to `len` bytes.
+    pub fn set_len(&self, len: usize) -> Result<(), FsError> {
+        self.vol.borrow_mut().ftruncate_sync(self.fd, Some(len))
+    }
+
+    // Closes the descriptor, reporting errors that dropping would ignore.
+    pub fn close(mut self) -> Result<(), FsError> {
+        self.closed = true;
+        self.vol.borrow_mut().close_sync(self.fd)
+    }
+
+    // Wraps the handle for `BufRead`, e.g. to iterate over `lines`.
+    pub fn buffered(self) -> BufReader<FileHandle> {
+        BufReader::new(self)
+    }
+}
+
+impl Read for FileHandle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self
+            .vol
+            .borrow_mut()
+            .read_sync(self.fd, buf, None, None, None)?;
+        Ok(bytes)
+    }
+}
+
+impl Write for FileHandle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes = self
+            .vol
+            .borrow_mut()
+            .write_sync(self.fd, buf, None, None, None)?;
+        Ok(bytes)
+    }
+
+    // Writes land in the node right away, there is nothing to flush.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for FileHandle {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.vol.borrow_mut().seek_sync(self.fd, pos)?;
+        Ok(position as u64)
+    }
+}
+
+impl Drop for FileHandle {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        if let Ok(mut vol) = self.vol.try_borrow_mut() {
+            let _ = vol.close_sync(self.fd);
+        }
+    }
+}
+
+#[test]
+fn test_file_handle_read_write_seek() {
+    use std::io::BufRead;
+
+    let vol = Volume::new();
+    let mut handle = vol
+        .borrow_mut()
+        .open_handle(String::from("/lines.txt"), Some(String::from("w+")), None)
+        .unwrap();
+    handle.write_all(b"one\ntwo\nthree\n").unwrap();
+
+    assert_eq!(handle.seek(SeekFrom::End(-6)).unwrap(), 8);
+    let mut rest = String::new();
+    handle.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, "three\n");
+    assert!(handle.seek(SeekFrom::Current(-100)).is_err());
+
+    handle.rewind().unwrap();
+    let fd = handle.fd();
+    let lines: Vec<String> = handle
+        .buffered()
+        .lines()
+        .map(|line| line.unwrap())
+        .collect();
+    assert_eq!(lines, vec!["one", "two", "three"]);
+
+    let err = vol.borrow_mut().close_sync(fd).unwrap_err();
+    assert_eq!(err.code, crate::error::FSError::EBADF);
+}
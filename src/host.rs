@@ -0,0 +1,355 @@
+// A directory of the host's filesystem as a `Backend`, for native builds:
+// mount e.g. a project's sources into a volume while everything else stays
+// in memory.
+//
+// All paths are kept below the root: ".." steps past it and symlinks leading
+// out of it fail with EACCES. Checks and uses are not atomic, so a tree
+// changed concurrently by other processes can still be escaped through.
+use crate::{
+    backend::Backend,
+    dirent::Dirent,
+    error::{create_error, FSError, FsError},
+    platform,
+    stats::Stats,
+};
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    fs::{self, DirBuilder, Metadata},
+    os::unix::{
+        self,
+        fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+    },
+    path::{Component, Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+// Number of symlinks followed while resolving one path, like
+// `Volume`'s limit.
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+#[derive(Clone, Default)]
+pub struct HostDirectoryOptions {
+    // Fail all modifications with EROFS.
+    pub read_only: Option<bool>,
+}
+
+pub struct HostDirectory {
+    // Canonical path of the directory.
+    root: PathBuf,
+    read_only: bool,
+}
+
+impl HostDirectory {
+    // Fails if `root` is not an existing directory.
+    pub fn new(root: PathBuf, options: Option<HostDirectoryOptions>) -> Result<Self, FsError> {
+        let options = options.unwrap_or_default();
+        let paths = Some(vec![root.to_string_lossy().into_owned()]);
+        let with_details =
+            |err: FsError| create_error(err.code, Some(String::from("mount")), paths.clone());
+        let root = fs::canonicalize(&root).map_err(|err| with_details(err.into()))?;
+        if !root.is_dir() {
+            return Err(with_details(FsError::new(FSError::ENOTDIR)));
+        }
+        Ok(HostDirectory {
+            root,
+            read_only: options.read_only.unwrap_or(false),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // Host path of `path`, resolving symlinks on the way and, with
+    // `follow_last`, at `path` itself. Missing entries are left to the
+    // operation to report.
+    fn resolve(&self, path: &str, follow_last: bool) -> Result<PathBuf, FsError> {
+        let mut resolved: Vec<OsString> = Vec::new();
+        let mut pending: VecDeque<OsString> = components(Path::new(path))?;
+        let mut depth = 0;
+        while let Some(step) = pending.pop_front() {
+            if step == ".." {
+                if resolved.pop().is_none() {
+                    return Err(FsError::new(FSError::EACCES));
+                }
+                continue;
+            }
+            let host_path = self.host_path(&resolved).join(&step);
+            let is_last = pending.is_empty();
+            let is_symlink = fs::symlink_metadata(&host_path)
+                .is_ok_and(|metadata| metadata.file_type().is_symlink());
+            if !is_symlink || (is_last && !follow_last) {
+                resolved.push(step);
+                continue;
+            }
+            depth += 1;
+            if depth > MAX_SYMLINK_DEPTH {
+                return Err(FsError::new(FSError::ELOOP));
+            }
+            let target = fs::read_link(&host_path)?;
+            let mut steps = if target.is_absolute() {
+                resolved.clear();
+                let inside = target
+                    .strip_prefix(&self.root)
+                    .map_err(|_| FsError::new(FSError::EACCES))?;
+                components(inside)?
+            } else {
+                components(&target)?
+            };
+            steps.extend(pending);
+            pending = steps;
+        }
+        Ok(self.host_path(&resolved))
+    }
+
+    fn host_path(&self, steps: &[OsString]) -> PathBuf {
+        let mut path = self.root.clone();
+        path.extend(steps);
+        path
+    }
+
+    // Like `resolve`, for modifications.
+    fn resolve_writable(&self, path: &str) -> Result<PathBuf, FsError> {
+        if self.read_only {
+            return Err(FsError::new(FSError::EROFS));
+        }
+        self.resolve(path, false)
+    }
+}
+
+// Steps of `path`, ".." included, with everything else leading to the root
+// or the current directory dropped.
+fn components(path: &Path) -> Result<VecDeque<OsString>, FsError> {
+    let mut steps = VecDeque::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(step) => steps.push_back(step.to_os_string()),
+            Component::ParentDir => steps.push_back(OsString::from("..")),
+            Component::RootDir | Component::CurDir => {}
+            Component::Prefix(_) => return Err(FsError::new(FSError::EINVAL)),
+        }
+    }
+    Ok(steps)
+}
+
+fn to_stats(metadata: &Metadata) -> Stats {
+    let ctime =
+        UNIX_EPOCH + Duration::new(metadata.ctime().max(0) as u64, metadata.ctime_nsec() as u32);
+    let atime = metadata.accessed().unwrap_or(ctime);
+    let mtime = metadata.modified().unwrap_or(ctime);
+    let birthtime = metadata.created().unwrap_or(ctime);
+    Stats {
+        uid: metadata.uid() as usize,
+        gid: metadata.gid() as usize,
+        rdev: metadata.rdev() as usize,
+        blksize: metadata.blksize() as usize,
+        ino: metadata.ino() as usize,
+        size: metadata.size() as usize,
+        blocks: metadata.blocks() as usize,
+        atime_ms: platform::to_ms(atime),
+        mtime_ms: platform::to_ms(mtime),
+        ctime_ms: platform::to_ms(ctime),
+        birthtime_ms: platform::to_ms(birthtime),
+        atime,
+        mtime,
+        ctime,
+        birthtime,
+        dev: metadata.dev() as usize,
+        mode: metadata.mode() as usize,
+        nlink: metadata.nlink() as usize,
+        bigint: false,
+    }
+}
+
+impl Backend for HostDirectory {
+    fn stat(&self, path: &str) -> Result<Stats, FsError> {
+        Ok(to_stats(&fs::metadata(self.resolve(path, true)?)?))
+    }
+
+    fn lstat(&self, path: &str) -> Result<Stats, FsError> {
+        Ok(to_stats(&fs::symlink_metadata(self.resolve(path, false)?)?))
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, FsError> {
+        Ok(fs::read(self.resolve(path, true)?)?)
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), FsError> {
+        if self.read_only {
+            return Err(FsError::new(FSError::EROFS));
+        }
+        Ok(fs::write(self.resolve(path, true)?, data)?)
+    }
+
+    fn readdir(&self, path: &str) -> Result<Vec<Dirent>, FsError> {
+        let mut dirents = Vec::new();
+        for entry in fs::read_dir(self.resolve(path, true)?)? {
+            let entry = entry?;
+            dirents.push(Dirent {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                mode: entry.metadata()?.mode() as usize,
+            });
+        }
+        dirents.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(dirents)
+    }
+
+    fn mkdir(&mut self, path: &str, mode: usize) -> Result<(), FsError> {
+        let host_path = self.resolve_writable(path)?;
+        Ok(DirBuilder::new().mode(mode as u32).create(host_path)?)
+    }
+
+    fn rmdir(&mut self, path: &str) -> Result<(), FsError> {
+        let host_path = self.resolve_writable(path)?;
+        if host_path == self.root {
+            return Err(FsError::new(FSError::EBUSY));
+        }
+        Ok(fs::remove_dir(host_path)?)
+    }
+
+    fn rename(&mut self, old_path: &str, new_path: &str) -> Result<(), FsError> {
+        let old_host_path = self.resolve_writable(old_path)?;
+        let new_host_path = self.resolve_writable(new_path)?;
+        if old_host_path == self.root || new_host_path == self.root {
+            return Err(FsError::new(FSError::EBUSY));
+        }
+        Ok(fs::rename(old_host_path, new_host_path)?)
+    }
+
+    fn unlink(&mut self, path: &str) -> Result<(), FsError> {
+        let host_path = self.resolve_writable(path)?;
+        if fs::symlink_metadata(&host_path)?.is_dir() {
+            return Err(FsError::new(FSError::EISDIR));
+        }
+        Ok(fs::remove_file(host_path)?)
+    }
+
+    // Targets are only checked when followed.
+    fn symlink(&mut self, target: &str, path: &str) -> Result<(), FsError> {
+        Ok(unix::fs::symlink(target, self.resolve_writable(path)?)?)
+    }
+
+    fn readlink(&self, path: &str) -> Result<String, FsError> {
+        let target = fs::read_link(self.resolve(path, false)?)?;
+        Ok(target.to_string_lossy().into_owned())
+    }
+
+    fn chmod(&mut self, path: &str, mode: usize) -> Result<(), FsError> {
+        if self.read_only {
+            return Err(FsError::new(FSError::EROFS));
+        }
+        let permissions = fs::Permissions::from_mode(mode as u32 & 0o7777);
+        Ok(fs::set_permissions(self.resolve(path, true)?, permissions)?)
+    }
+
+    fn link(&mut self, existing_path: &str, new_path: &str) -> Result<(), FsError> {
+        let existing_host_path = self.resolve_writable(existing_path)?;
+        let new_host_path = self.resolve_writable(new_path)?;
+        Ok(fs::hard_link(existing_host_path, new_host_path)?)
+    }
+
+    fn realpath(&self, path: &str) -> Result<String, FsError> {
+        let host_path = self.resolve(path, true)?;
+        fs::symlink_metadata(&host_path)?;
+        let inside = host_path.strip_prefix(&self.root).unwrap();
+        Ok(format!("/{}", inside.to_string_lossy()))
+    }
+}
+
+// A fresh directory below the system's temp directory.
+#[cfg(test)]
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wasmfs-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_host_directory_stays_inside_root() {
+    use std::os::unix::fs::symlink;
+    let dir = temp_dir("jail");
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("inside")).unwrap();
+    fs::write(root.join("inside/file.txt"), "inside").unwrap();
+    fs::write(dir.join("secret.txt"), "outside").unwrap();
+    symlink("inside/file.txt", root.join("relative")).unwrap();
+    symlink(root.join("inside"), root.join("absolute")).unwrap();
+    symlink("../secret.txt", root.join("up")).unwrap();
+    symlink(dir.join("secret.txt"), root.join("escape")).unwrap();
+
+    let mut host = HostDirectory::new(root.clone(), None).unwrap();
+    assert_eq!(host.read("/relative").unwrap(), b"inside");
+    assert_eq!(host.read("/absolute/file.txt").unwrap(), b"inside");
+    assert_eq!(host.read("/inside/../relative").unwrap(), b"inside");
+    for path in [
+        "/up",
+        "/escape",
+        "/../secret.txt",
+        "/inside/../../secret.txt",
+    ] {
+        assert_eq!(
+            host.read(path).unwrap_err().code,
+            FSError::EACCES,
+            "{}",
+            path
+        );
+    }
+    assert!(host.lstat("/up").unwrap().is_symbolic_link());
+    assert!(host.stat("/absolute").unwrap().is_directory());
+    assert_eq!(host.read("/missing").unwrap_err().code, FSError::ENOENT);
+
+    host.mkdir("/inside/sub", 0o750).unwrap();
+    host.write("/inside/sub/new.txt", b"new").unwrap();
+    host.rename("/inside/sub/new.txt", "/moved.txt").unwrap();
+    assert_eq!(fs::read(root.join("moved.txt")).unwrap(), b"new");
+    let names: Vec<String> = host
+        .readdir("/")
+        .unwrap()
+        .into_iter()
+        .map(|dirent| dirent.name)
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "absolute",
+            "escape",
+            "inside",
+            "moved.txt",
+            "relative",
+            "up"
+        ]
+    );
+    assert_eq!(host.rmdir("/inside").unwrap_err().code, FSError::ENOTEMPTY);
+    assert_eq!(host.unlink("/inside").unwrap_err().code, FSError::EISDIR);
+    host.rmdir("/inside/sub").unwrap();
+    // Removes the symlink, not what it points to.
+    host.unlink("/up").unwrap();
+    assert!(dir.join("secret.txt").exists());
+    assert_eq!(host.rmdir("/").unwrap_err().code, FSError::EBUSY);
+
+    let options = HostDirectoryOptions {
+        read_only: Some(true),
+    };
+    let mut read_only = HostDirectory::new(root.clone(), Some(options)).unwrap();
+    assert_eq!(read_only.read("/moved.txt").unwrap(), b"new");
+    assert_eq!(
+        read_only.write("/moved.txt", b"").unwrap_err().code,
+        FSError::EROFS
+    );
+    assert_eq!(
+        read_only.unlink("/moved.txt").unwrap_err().code,
+        FSError::EROFS
+    );
+    assert_eq!(
+        read_only.mkdir("/dir", 0o755).unwrap_err().code,
+        FSError::EROFS
+    );
+    let err = HostDirectory::new(root.join("moved.txt"), None)
+        .err()
+        .unwrap();
+    assert_eq!(err.code, FSError::ENOTDIR);
+
+    fs::remove_dir_all(dir).unwrap();
+}
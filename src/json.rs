@@ -0,0 +1,135 @@
+// Plain Rust forms of memfs' JSON fixtures, along with their conversion from
+// and to JS values.
+#[cfg(feature = "wasm")]
+use crate::platform;
+#[cfg(feature = "wasm")]
+use js_sys::{Date, Reflect, Uint8Array};
+use std::{collections::BTreeMap, time::SystemTime};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::{JsCast, JsValue};
+
+// Files of a volume by path, `None` marking empty directories, like memfs'
+// `DirectoryJSON`.
+pub type DirectoryJson = BTreeMap<String, Option<Vec<u8>>>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JsonEntryKind {
+    File(Vec<u8>),
+    Directory,
+    Symlink(String),
+}
+
+// An entry of a `from_json` fixture, optionally with the metadata to give it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonEntry {
+    pub kind: JsonEntryKind,
+    pub mode: Option<usize>,
+    pub mtime: Option<SystemTime>,
+    pub uid: Option<usize>,
+    pub gid: Option<usize>,
+}
+
+impl JsonEntry {
+    pub fn new(kind: JsonEntryKind) -> JsonEntry {
+        JsonEntry {
+            kind,
+            mode: None,
+            mtime: None,
+            uid: None,
+            gid: None,
+        }
+    }
+}
+
+impl From<Option<Vec<u8>>> for JsonEntry {
+    fn from(data: Option<Vec<u8>>) -> JsonEntry {
+        match data {
+            Some(data) => JsonEntry::new(JsonEntryKind::File(data)),
+            None => JsonEntry::new(JsonEntryKind::Directory),
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+pub fn to_js_value(data: Option<Vec<u8>>, as_buffer: bool) -> JsValue {
+    match data {
+        Some(data) if as_buffer => Uint8Array::from(&data[..]).into(),
+        Some(data) => String::from_utf8_lossy(&data).into_owned().into(),
+        None => JsValue::null(),
+    }
+}
+
+#[cfg(feature = "wasm")]
+fn to_buffer(value: &JsValue) -> Option<Vec<u8>> {
+    match value.as_string() {
+        Some(data) => Some(data.into_bytes()),
+        None => Some(value.dyn_ref::<Uint8Array>()?.to_vec()),
+    }
+}
+
+// Whether `value` is an extended entry like `{ type: "symlink", target }`
+// rather than a directory of a nested fixture.
+#[cfg(feature = "wasm")]
+pub fn is_entry_object(value: &JsValue) -> bool {
+    if value.is_instance_of::<Uint8Array>() {
+        return true;
+    }
+    let kind = match Reflect::get(value, &"type".into()) {
+        Ok(kind) => kind.as_string(),
+        Err(_) => None,
+    };
+    matches!(kind.as_deref(), Some("file" | "directory" | "symlink"))
+}
+
+// Parses a value of a flat fixture: contents as a string or `Uint8Array`,
+// `null` for a directory, or an object with `type` ("file", "directory" or
+// "symlink"), `data`, `target`, `mode`, `mtime`, `uid` and `gid`. Without a
+// `type` the entry is a symlink if it has a `target`, a file if it has `data`
+// and a directory otherwise.
+#[cfg(feature = "wasm")]
+pub fn parse_entry(value: &JsValue) -> Option<JsonEntry> {
+    if value.is_null() || value.is_undefined() {
+        return Some(JsonEntry::new(JsonEntryKind::Directory));
+    }
+    if let Some(data) = to_buffer(value) {
+        return Some(JsonEntry::new(JsonEntryKind::File(data)));
+    }
+    if !value.is_object() {
+        return None;
+    }
+    let get = |key: &str| {
+        Reflect::get(value, &key.into())
+            .ok()
+            .filter(|value| !value.is_null() && !value.is_undefined())
+    };
+    let number = |key: &str| get(key).and_then(|value| value.as_f64());
+    let kind = match get("type").and_then(|kind| kind.as_string()) {
+        Some(kind) => kind,
+        None if get("target").is_some() => String::from("symlink"),
+        None if get("data").is_some() => String::from("file"),
+        None => String::from("directory"),
+    };
+    let kind = match kind.as_str() {
+        "file" => match get("data") {
+            Some(data) => JsonEntryKind::File(to_buffer(&data)?),
+            None => JsonEntryKind::File(Vec::new()),
+        },
+        "directory" => JsonEntryKind::Directory,
+        "symlink" => JsonEntryKind::Symlink(get("target")?.as_string()?),
+        _ => return None,
+    };
+    let mtime = match get("mtime") {
+        Some(mtime) => match mtime.dyn_ref::<Date>() {
+            Some(date) => Some(date.get_time()),
+            None => Some(mtime.as_f64()?),
+        },
+        None => None,
+    };
+    Some(JsonEntry {
+        kind,
+        mode: number("mode").map(|mode| mode as usize),
+        mtime: mtime.map(|ms| platform::from_ms(ms as usize)),
+        uid: number("uid").map(|uid| uid as usize),
+        gid: number("gid").map(|gid| gid as usize),
+    })
+}
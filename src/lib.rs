@@ -1,9 +1,28 @@
+pub mod arena;
+pub mod backend;
 pub mod constants;
+pub mod diff;
 pub mod dirent;
+pub mod encoding;
 pub mod error;
+pub mod events;
 pub mod file;
+pub mod fs;
+pub mod handle;
+#[cfg(unix)]
+pub mod host;
+pub mod json;
 pub mod link;
+pub mod memfs_snapshot;
 pub mod node;
+pub mod platform;
+pub mod snapshot;
 pub mod stats;
+pub mod stream;
+pub mod sync_volume;
+pub mod tar;
 pub mod util;
 pub mod volume;
+pub mod watcher;
+#[cfg(feature = "zip")]
+pub mod zip;
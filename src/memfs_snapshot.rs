@@ -0,0 +1,654 @@
+// Reader and writer for the snapshots of memfs' `snapshot` module, so that
+// fixtures made with `toSnapshotSync`, `toJsonSnapshotSync` and
+// `toBinarySnapshotSync` can be loaded into a `Volume` and the other way
+// round.
+//
+// A snapshot node is `[0, {}, { name: node, .. }]` for a folder, `[1, {}, data]`
+// for a file, `[2, { target }]` for a symlink and `null` for anything else.
+// The JSON encoding stores data as `data:application/octet-stream;base64,..`
+// strings, the binary encoding is CBOR with data as byte strings.
+use crate::{
+    encoding,
+    error::{create_error, FSError, FsError},
+    util,
+    volume::{MkdirOptions, Volume},
+};
+use std::collections::BTreeMap;
+
+const FOLDER: f64 = 0.0;
+const FILE: f64 = 1.0;
+const SYMLINK: f64 = 2.0;
+
+const DATA_URI_PREFIX: &str = "data:application/octet-stream;base64,";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MemfsNode {
+    Folder(BTreeMap<String, MemfsNode>),
+    File(Vec<u8>),
+    Symlink(String),
+    // `null`, e.g. for sockets or FIFOs.
+    Unknown,
+}
+
+// Snapshot of `path` (default "/"), without following symlinks.
+pub fn to_snapshot_sync(vol: &mut Volume, path: Option<String>) -> Result<MemfsNode, FsError> {
+    let path = path.unwrap_or_else(|| String::from("/"));
+    let stats = vol.lstat_sync(path.clone(), None)?;
+    if stats.is_directory() {
+        let mut entries = BTreeMap::new();
+        for name in vol.readdir_sync(path.clone())? {
+            let child = util::path_join(vec![path.clone(), name.clone()]);
+            entries.insert(name, to_snapshot_sync(vol, Some(child))?);
+        }
+        Ok(MemfsNode::Folder(entries))
+    } else if stats.is_file() {
+        Ok(MemfsNode::File(vol.read_file_sync(path)?))
+    } else if stats.is_symbolic_link() {
+        Ok(MemfsNode::Symlink(vol.readlink_sync(path)?))
+    } else {
+        Ok(MemfsNode::Unknown)
+    }
+}
+
+// Recreates `snapshot` at `path` (default "/"), creating missing parent
+// directories and overwriting existing files.
+pub fn from_snapshot_sync(
+    vol: &mut Volume,
+    snapshot: &MemfsNode,
+    path: Option<String>,
+) -> Result<(), FsError> {
+    let path = path.unwrap_or_else(|| String::from("/"));
+    match snapshot {
+        MemfsNode::Folder(entries) => {
+            let options = MkdirOptions {
+                recursive: Some(true),
+                ..Default::default()
+            };
+            vol.mkdir_sync(path.clone(), Some(options))?;
+            for (name, child) in entries {
+                let child_path = util::path_join(vec![path.clone(), name.clone()]);
+                from_snapshot_sync(vol, child, Some(child_path))?;
+            }
+        }
+        MemfsNode::File(data) => vol.write_file_sync(path, data, None)?,
+        MemfsNode::Symlink(target) => vol.symlink_sync(target.clone(), path)?,
+        MemfsNode::Unknown => {}
+    }
+    Ok(())
+}
+
+pub fn to_json_snapshot_sync(vol: &mut Volume, path: Option<String>) -> Result<Vec<u8>, FsError> {
+    let snapshot = to_snapshot_sync(vol, path)?;
+    let mut json = String::new();
+    write_json(&to_value(&snapshot), &mut json);
+    Ok(json.into_bytes())
+}
+
+// Fails with EINVAL if `json` is not a memfs JSON snapshot.
+pub fn from_json_snapshot_sync(
+    vol: &mut Volume,
+    json: &[u8],
+    path: Option<String>,
+) -> Result<(), FsError> {
+    let mut parser = JsonParser { json, pos: 0 };
+    let snapshot = parser
+        .value()
+        .filter(|_| parser.end())
+        .and_then(|value| from_value(&value))
+        .ok_or_else(|| invalid("fromJsonSnapshot"))?;
+    from_snapshot_sync(vol, &snapshot, path)
+}
+
+pub fn to_binary_snapshot_sync(vol: &mut Volume, path: Option<String>) -> Result<Vec<u8>, FsError> {
+    let snapshot = to_snapshot_sync(vol, path)?;
+    let mut cbor = Vec::new();
+    write_cbor(&to_value(&snapshot), &mut cbor);
+    Ok(cbor)
+}
+
+// Fails with EINVAL if `cbor` is not a memfs binary snapshot.
+pub fn from_binary_snapshot_sync(
+    vol: &mut Volume,
+    cbor: &[u8],
+    path: Option<String>,
+) -> Result<(), FsError> {
+    let mut reader = CborReader(cbor);
+    let snapshot = reader
+        .value()
+        .filter(|_| reader.0.is_empty())
+        .and_then(|value| from_value(&value))
+        .ok_or_else(|| invalid("fromBinarySnapshot"))?;
+    from_snapshot_sync(vol, &snapshot, path)
+}
+
+fn invalid(func_name: &str) -> FsError {
+    create_error(FSError::EINVAL, Some(String::from(func_name)), None)
+}
+
+// The data model shared by both encodings.
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+fn to_value(node: &MemfsNode) -> Value {
+    let meta = Value::Object(Vec::new());
+    match node {
+        MemfsNode::Folder(entries) => Value::Array(vec![
+            Value::Number(FOLDER),
+            meta,
+            Value::Object(
+                entries
+                    .iter()
+                    .map(|(name, child)| (name.clone(), to_value(child)))
+                    .collect(),
+            ),
+        ]),
+        MemfsNode::File(data) => {
+            Value::Array(vec![Value::Number(FILE), meta, Value::Bytes(data.clone())])
+        }
+        MemfsNode::Symlink(target) => Value::Array(vec![
+            Value::Number(SYMLINK),
+            Value::Object(vec![(
+                String::from("target"),
+                Value::String(target.clone()),
+            )]),
+        ]),
+        MemfsNode::Unknown => Value::Null,
+    }
+}
+
+fn from_value(value: &Value) -> Option<MemfsNode> {
+    let items = match value {
+        Value::Null => return Some(MemfsNode::Unknown),
+        Value::Array(items) => items,
+        _ => return None,
+    };
+    let meta = match items.get(1)? {
+        Value::Object(meta) => meta,
+        _ => return None,
+    };
+    match (items.first()?, items.get(2)) {
+        (Value::Number(kind), Some(Value::Object(entries))) if *kind == FOLDER => {
+            let mut folder = BTreeMap::new();
+            for (name, child) in entries {
+                folder.insert(name.clone(), from_value(child)?);
+            }
+            Some(MemfsNode::Folder(folder))
+        }
+        (Value::Number(kind), Some(Value::Bytes(data))) if *kind == FILE => {
+            Some(MemfsNode::File(data.clone()))
+        }
+        (Value::Number(kind), _) if *kind == SYMLINK => {
+            match meta.iter().find(|(key, _)| key == "target")? {
+                (_, Value::String(target)) => Some(MemfsNode::Symlink(target.clone())),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// Compact JSON like json-pack's `JsonEncoder`, which memfs uses.
+fn write_json(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+        Value::Number(value) => out.push_str(&value.to_string()),
+        Value::String(value) => write_json_string(value, out),
+        Value::Bytes(data) => write_json_string(
+            &format!("{}{}", DATA_URI_PREFIX, encoding::base64_encode(data)),
+            out,
+        ),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(entries) => {
+            out.push('{');
+            for (i, (key, item)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json_string(key, out);
+                out.push(':');
+                write_json(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_json_string(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct JsonParser<'a> {
+    json: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.json.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.pos == self.json.len()
+    }
+
+    // Consumes `token` if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.json[self.pos..].starts_with(token.as_bytes());
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.skip_whitespace();
+        match self.json.get(self.pos)? {
+            b'"' => {
+                // Binary data comes as data URIs, like json-pack's `JsonDecoder`
+                // reads it.
+                let string = self.string()?;
+                match string.strip_prefix(DATA_URI_PREFIX) {
+                    Some(data) => Some(Value::Bytes(encoding::base64_decode(data))),
+                    None => Some(Value::String(string)),
+                }
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if !self.eat("]") {
+                    loop {
+                        items.push(self.value()?);
+                        if self.eat("]") {
+                            break;
+                        }
+                        if !self.eat(",") {
+                            return None;
+                        }
+                    }
+                }
+                Some(Value::Array(items))
+            }
+            b'{' => {
+                self.pos += 1;
+                let mut entries = Vec::new();
+                if !self.eat("}") {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        if !self.eat(":") {
+                            return None;
+                        }
+                        entries.push((key, self.value()?));
+                        if self.eat("}") {
+                            break;
+                        }
+                        if !self.eat(",") {
+                            return None;
+                        }
+                    }
+                }
+                Some(Value::Object(entries))
+            }
+            _ if self.eat("null") => Some(Value::Null),
+            _ if self.eat("true") => Some(Value::Bool(true)),
+            _ if self.eat("false") => Some(Value::Bool(false)),
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Option<Value> {
+        let start = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.json.get(self.pos) {
+            self.pos += 1;
+        }
+        let number = std::str::from_utf8(&self.json[start..self.pos]).ok()?;
+        Some(Value::Number(number.parse().ok()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.json.get(self.pos) != Some(&b'"') {
+            return None;
+        }
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = *self.json.get(self.pos)?;
+            self.pos += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = *self.json.get(self.pos)?;
+                    self.pos += 1;
+                    let c = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return None,
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).ok()
+    }
+
+    // The `XXXX` of `\uXXXX`, combined with a following low surrogate.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let unit = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&unit) {
+            return char::from_u32(unit);
+        }
+        if !self.json[self.pos..].starts_with(b"\\u") {
+            return None;
+        }
+        self.pos += 2;
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return None;
+        }
+        char::from_u32(0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00))
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let hex = std::str::from_utf8(self.json.get(self.pos..self.pos + 4)?).ok()?;
+        self.pos += 4;
+        u32::from_str_radix(hex, 16).ok()
+    }
+}
+
+fn write_cbor_head(major: u8, len: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    if len < 24 {
+        out.push(major | len as u8);
+    } else if len <= 0xff {
+        out.push(major | 24);
+        out.push(len as u8);
+    } else if len <= 0xffff {
+        out.push(major | 25);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else if len <= 0xffff_ffff {
+        out.push(major | 26);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+fn write_cbor(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xf6),
+        Value::Bool(value) => out.push(if *value { 0xf5 } else { 0xf4 }),
+        Value::Number(value) if value.fract() == 0.0 && value.abs() < 2f64.powi(53) => {
+            if *value >= 0.0 {
+                write_cbor_head(0, *value as u64, out);
+            } else {
+                write_cbor_head(1, (-1.0 - value) as u64, out);
+            }
+        }
+        Value::Number(value) => {
+            out.push(0xfb);
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        Value::String(value) => write_cbor_string(value, out),
+        Value::Bytes(data) => {
+            write_cbor_head(2, data.len() as u64, out);
+            out.extend_from_slice(data);
+        }
+        Value::Array(items) => {
+            write_cbor_head(4, items.len() as u64, out);
+            for item in items {
+                write_cbor(item, out);
+            }
+        }
+        Value::Object(entries) => {
+            write_cbor_head(5, entries.len() as u64, out);
+            for (key, item) in entries {
+                write_cbor_string(key, out);
+                write_cbor(item, out);
+            }
+        }
+    }
+}
+
+// json-pack sizes the length of a text string for its worst case UTF-8
+// length, 4 bytes per UTF-16 unit, before encoding it. Doing the same keeps
+// the output byte for byte identical to memfs'.
+fn write_cbor_string(value: &str, out: &mut Vec<u8>) {
+    let max_len = value.encode_utf16().count() * 4;
+    let len = value.len();
+    if max_len <= 23 {
+        out.push(0x60 | len as u8);
+    } else if max_len <= 0xff {
+        out.extend_from_slice(&[0x78, len as u8]);
+    } else if max_len <= 0xffff {
+        out.push(0x79);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(0x7a);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+    out.extend_from_slice(value.as_bytes());
+}
+
+struct CborReader<'a>(&'a [u8]);
+
+impl<'a> CborReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn uint(&mut self, len: usize) -> Option<u64> {
+        Some(
+            self.take(len)?
+                .iter()
+                .fold(0, |value, byte| value << 8 | *byte as u64),
+        )
+    }
+
+    // Major type and argument of the next item, `None` as argument for
+    // indefinite lengths.
+    fn head(&mut self) -> Option<(u8, u8, Option<u64>)> {
+        let byte = self.take(1)?[0];
+        let info = byte & 0x1f;
+        let arg = match info {
+            0..=23 => Some(info as u64),
+            24 => Some(self.uint(1)?),
+            25 => Some(self.uint(2)?),
+            26 => Some(self.uint(4)?),
+            27 => Some(self.uint(8)?),
+            31 => None,
+            _ => return None,
+        };
+        Some((byte >> 5, info, arg))
+    }
+
+    // Consumes the "break" ending an indefinite length item if it comes next.
+    fn eat_break(&mut self) -> bool {
+        let found = self.0.first() == Some(&0xff);
+        if found {
+            self.0 = &self.0[1..];
+        }
+        found
+    }
+
+    // Byte or text string contents of major type `major`.
+    fn chunks(&mut self, major: u8, len: Option<u64>) -> Option<Vec<u8>> {
+        match len {
+            Some(len) => Some(self.take(usize::try_from(len).ok()?)?.to_vec()),
+            None => {
+                let mut data = Vec::new();
+                while !self.eat_break() {
+                    match self.head()? {
+                        (chunk_major, _, Some(len)) if chunk_major == major => {
+                            data.extend_from_slice(self.take(usize::try_from(len).ok()?)?)
+                        }
+                        _ => return None,
+                    }
+                }
+                Some(data)
+            }
+        }
+    }
+
+    // Calls `item` `len` times, or until a "break" for indefinite lengths.
+    fn items(
+        &mut self,
+        len: Option<u64>,
+        mut item: impl FnMut(&mut Self) -> Option<()>,
+    ) -> Option<()> {
+        match len {
+            Some(len) => (0..len).try_for_each(|_| item(self)),
+            None => {
+                while !self.eat_break() {
+                    item(self)?;
+                }
+                Some(())
+            }
+        }
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        let (major, info, arg) = self.head()?;
+        match (major, arg) {
+            (0, Some(value)) => Some(Value::Number(value as f64)),
+            (1, Some(value)) => Some(Value::Number(-1.0 - value as f64)),
+            (2, len) => Some(Value::Bytes(self.chunks(2, len)?)),
+            (3, len) => Some(Value::String(String::from_utf8(self.chunks(3, len)?).ok()?)),
+            (4, len) => {
+                let mut items = Vec::new();
+                self.items(len, |reader| {
+                    items.push(reader.value()?);
+                    Some(())
+                })?;
+                Some(Value::Array(items))
+            }
+            (5, len) => {
+                let mut entries = Vec::new();
+                self.items(len, |reader| {
+                    let key = match reader.value()? {
+                        Value::String(key) => key,
+                        _ => return None,
+                    };
+                    entries.push((key, reader.value()?));
+                    Some(())
+                })?;
+                Some(Value::Object(entries))
+            }
+            // Tags do not matter for snapshots, only the tagged value.
+            (6, Some(_)) => self.value(),
+            (7, Some(arg)) => match info {
+                20 => Some(Value::Bool(false)),
+                21 => Some(Value::Bool(true)),
+                22 | 23 => Some(Value::Null),
+                25 => Some(Value::Number(half_to_f64(arg as u16))),
+                26 => Some(Value::Number(f32::from_bits(arg as u32) as f64)),
+                27 => Some(Value::Number(f64::from_bits(arg))),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+fn half_to_f64(half: u16) -> f64 {
+    let exponent = (half >> 10) & 0x1f;
+    let mantissa = (half & 0x3ff) as f64;
+    let value = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (mantissa + 1024.0) * 2f64.powi(exponent as i32 - 25),
+    };
+    if half & 0x8000 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+// The fixtures hold the same tree in both encodings: /app/index.js,
+// /app/logo.bin, the symlink /current -> /app, the empty /empty and a file
+// whose name needs escaping.
+#[test]
+fn test_memfs_snapshot_fixtures() {
+    let json = include_bytes!("../fixtures/memfs/snapshot.json");
+    let cbor = include_bytes!("../fixtures/memfs/snapshot.cbor");
+
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    from_json_snapshot_sync(&mut vol, json, None).unwrap();
+    assert_eq!(
+        vol.read_file_sync(String::from("/current/logo.bin"))
+            .unwrap(),
+        [0, 255, 128, 10]
+    );
+    assert_eq!(
+        vol.read_file_sync(String::from("/héllo \"quoted\".txt"))
+            .unwrap(),
+        "grüße\n".as_bytes()
+    );
+    assert!(vol.readdir_sync(String::from("/empty")).unwrap().is_empty());
+    assert_eq!(to_json_snapshot_sync(&mut vol, None).unwrap(), json);
+    assert_eq!(to_binary_snapshot_sync(&mut vol, None).unwrap(), cbor);
+
+    let copy = Volume::new();
+    let mut copy = copy.borrow_mut();
+    from_binary_snapshot_sync(&mut copy, cbor, Some(String::from("/restored"))).unwrap();
+    assert_eq!(
+        to_snapshot_sync(&mut copy, Some(String::from("/restored/app"))).unwrap(),
+        to_snapshot_sync(&mut vol, Some(String::from("/app"))).unwrap()
+    );
+
+    let err = from_json_snapshot_sync(&mut copy, b"[0,{},{\"a\":[1,{}]}]", None).unwrap_err();
+    assert_eq!(err.code, FSError::EINVAL);
+    assert!(from_binary_snapshot_sync(&mut copy, &cbor[..cbor.len() - 1], None).is_err());
+}
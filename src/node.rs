@@ -1,17 +1,50 @@
-use crate::constants::constants;
-use js_sys::{Array, Date, JsString, Object, Reflect};
+use crate::{constants::constants, error::FsError, platform, snapshot::SnapshotNode};
+#[cfg(feature = "wasm")]
+use js_sys::{Array, JsString, Object, Reflect};
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+// Source of `Node::generation`, shared by all volumes.
+static NEXT_GENERATION: AtomicUsize = AtomicUsize::new(1);
+
+fn next_generation() -> usize {
+    NEXT_GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
+// Contents produced only when first needed, e.g. a compressed zip entry.
+// `load` may run more than once for nodes only read through `&Node`.
+#[derive(Clone)]
+pub struct LazyBuffer {
+    pub size: usize,
+    pub load: Arc<dyn Fn() -> Result<Vec<u8>, FsError> + Send + Sync>,
+}
 
+// Clones share their contents until either side writes, see `Volume::fork`.
+#[derive(Clone)]
 pub struct Node {
     pub ino: usize,
 
+    // Unique to each node created, even across volumes, while `ino` is
+    // reused once freed. Clones keep it, see `Volume::diff`.
+    pub generation: usize,
+
     _uid: usize,
     _gid: usize,
 
-    _atime: Date,
-    _mtime: Date,
-    _ctime: Date,
+    _atime: SystemTime,
+    _mtime: SystemTime,
+    _ctime: SystemTime,
+
+    pub buf: Option<Arc<Vec<u8>>>,
 
-    pub buf: Option<Vec<u8>>,
+    // Replaces `buf` until loaded, see `load`.
+    pub lazy: Option<LazyBuffer>,
 
     _perm: usize,
 
@@ -19,7 +52,11 @@ pub struct Node {
 
     _nlink: usize,
 
+    // Steps of the resolved target of a symlink, from the root.
     pub symlink: Vec<String>,
+
+    // Target of a symlink as it was given, what `readlink` returns.
+    pub target: String,
 }
 
 impl Node {
@@ -28,31 +65,34 @@ impl Node {
         let mode = constants::S_IFREG | perm;
         Self {
             ino,
+            generation: next_generation(),
             _uid: 0,
             _gid: 0,
-            _atime: Date::new_0(),
-            _mtime: Date::new_0(),
-            _ctime: Date::new_0(),
+            _atime: platform::now(),
+            _mtime: platform::now(),
+            _ctime: platform::now(),
             buf: None,
+            lazy: None,
             _perm: perm,
             mode,
             _nlink: 1,
             symlink: Vec::new(),
+            target: String::new(),
         }
     }
 
     // getter and setter for ctime
-    pub fn get_ctime(&self) -> Date {
-        self._ctime.clone()
+    pub fn get_ctime(&self) -> SystemTime {
+        self._ctime
     }
-    pub fn set_ctime(&mut self, ctime: Date) {
+    pub fn set_ctime(&mut self, ctime: SystemTime) {
         self._ctime = ctime;
     }
 
     // getter and setter for uid
     pub fn set_uid(&mut self, uid: usize) {
         self._uid = uid;
-        self.set_ctime(Date::new_0());
+        self.set_ctime(platform::now());
     }
     pub fn get_uid(&self) -> usize {
         self._uid
@@ -61,28 +101,28 @@ impl Node {
     // getter and setter for gid
     pub fn set_gid(&mut self, gid: usize) {
         self._gid = gid;
-        self.set_ctime(Date::new_0());
+        self.set_ctime(platform::now());
     }
     pub fn get_gid(&self) -> usize {
         self._gid
     }
 
     // getter and setter for atime
-    pub fn get_atime(&self) -> Date {
-        self._atime.clone()
+    pub fn get_atime(&self) -> SystemTime {
+        self._atime
     }
-    pub fn set_atime(&mut self, atime: Date) {
+    pub fn set_atime(&mut self, atime: SystemTime) {
         self._atime = atime;
-        self.set_ctime(Date::new_0());
+        self.set_ctime(platform::now());
     }
 
     // getter and setter for mtime
-    pub fn get_mtime(&self) -> Date {
-        self._mtime.clone()
+    pub fn get_mtime(&self) -> SystemTime {
+        self._mtime
     }
-    pub fn set_mtime(&mut self, mtime: Date) {
+    pub fn set_mtime(&mut self, mtime: SystemTime) {
         self._mtime = mtime;
-        self.set_ctime(Date::new_0());
+        self.set_ctime(platform::now());
     }
 
     // getter and setter for perm
@@ -91,7 +131,7 @@ impl Node {
     }
     pub fn set_perm(&mut self, perm: usize) {
         self._perm = perm;
-        self.set_ctime(Date::new_0());
+        self.set_ctime(platform::now());
     }
 
     // getter and setter for nlink
@@ -100,36 +140,40 @@ impl Node {
     }
     pub fn set_nlink(&mut self, nlink: usize) {
         self._nlink = nlink;
-        self.set_ctime(Date::new_0());
+        self.set_ctime(platform::now());
     }
     pub fn inc_nlink(&mut self) {
         self._nlink += 1;
-        self.set_ctime(Date::new_0());
+        self.set_ctime(platform::now());
     }
     pub fn dec_nlink(&mut self) {
-        self._nlink -= 1;
-        self.set_ctime(Date::new_0());
+        self._nlink = self._nlink.saturating_sub(1);
+        self.set_ctime(platform::now());
     }
 
+    // Watchers learn about the change through `Volume::node_changed`.
     pub fn touch(&mut self) {
-        self.set_mtime(Date::new_0());
-        // todo: this.emit('change', this);
+        self.set_mtime(platform::now());
     }
 
     pub fn get_string(&mut self) -> String {
-        self.set_atime(Date::new_0());
-        String::from_utf8(self.get_buffer()).unwrap()
+        self.set_atime(platform::now());
+        String::from_utf8_lossy(&self.get_buffer()).into_owned()
     }
 
     pub fn set_string(&mut self, string: String) {
-        self.buf = Some(string.into_bytes());
+        self.lazy = None;
+        self.buf = Some(Arc::new(string.into_bytes()));
         self.touch();
     }
 
+    // Reading does not update the access time, the volume takes care of it.
     pub fn get_buffer(&mut self) -> Vec<u8> {
-        self.set_atime(Date::new_0());
+        if self.lazy.is_some() {
+            self.ensure_buffer();
+        }
         match &self.buf {
-            Some(buf) => buf.clone(),
+            Some(buf) => buf.to_vec(),
             None => {
                 let buf = Vec::new();
                 self.set_buffer(buf.clone());
@@ -139,11 +183,15 @@ impl Node {
     }
 
     pub fn set_buffer(&mut self, buffer: Vec<u8>) {
-        self.buf = Some(buffer);
+        self.lazy = None;
+        self.buf = Some(Arc::new(buffer));
         self.touch();
     }
 
-    pub fn get_size(&mut self) -> usize {
+    pub fn get_size(&self) -> usize {
+        if let Some(lazy) = &self.lazy {
+            return lazy.size;
+        }
         match &self.buf {
             Some(buf) => buf.len(),
             None => 0,
@@ -178,20 +226,21 @@ impl Node {
         (self.mode & constants::S_IFMT) == constants::S_IFLNK
     }
 
-    pub fn make_symlink(&mut self, steps: Vec<String>) {
+    pub fn make_symlink(&mut self, target: String, steps: Vec<String>) {
         self.set_is_symlink();
         self.symlink = steps;
+        self.target = target;
     }
 
     pub fn write(
         &mut self,
-        buf: Vec<u8>,
+        buf: &[u8],
         off: Option<usize>,
         len: Option<usize>,
         pos: Option<usize>,
     ) -> usize {
         let off = off.unwrap_or(0);
-        let len = len.unwrap_or(buf.len());
+        let len = len.unwrap_or(buf.len() - off);
         let pos = pos.unwrap_or(0);
         let this_buf = self.ensure_buffer();
 
@@ -199,43 +248,45 @@ impl Node {
             this_buf.resize(pos + len, 0);
         }
 
-        this_buf.splice(pos..pos + len, buf[off..off + len].iter().cloned());
+        this_buf[pos..pos + len].copy_from_slice(&buf[off..off + len]);
         self.touch();
         len
     }
 
     pub fn read(
         &mut self,
-        mut buf: Vec<u8>,
+        buf: &mut [u8],
         off: Option<usize>,
         len: Option<usize>,
         pos: Option<usize>,
     ) -> usize {
-        self._atime = Date::new_0();
-
         let off = off.unwrap_or(0);
-        let len = len.unwrap_or(buf.len());
+        let len = len.unwrap_or(buf.len() - off);
         let pos = pos.unwrap_or(0);
-        let this_buf = self.ensure_buffer();
+        if self.lazy.is_some() && self.load().is_err() {
+            self.lazy = None;
+        }
+        let this_buf = self.buf.as_deref().map_or(&[][..], Vec::as_slice);
 
         let mut actual_len = len;
-        if actual_len > buf.len() {
-            actual_len = buf.len();
+        if actual_len > buf.len() - off {
+            actual_len = buf.len() - off;
+        }
+        if pos >= this_buf.len() {
+            return 0;
         }
         if actual_len + pos > this_buf.len() {
             actual_len = this_buf.len() - pos;
         }
 
-        buf.splice(
-            off..off + actual_len,
-            this_buf[pos..pos + actual_len].iter().cloned(),
-        );
+        buf[off..off + actual_len].copy_from_slice(&this_buf[pos..pos + actual_len]);
         actual_len
     }
 
     pub fn truncate(&mut self, len: usize) {
         if len == 0 {
-            self.buf = Some(Vec::new());
+            self.lazy = None;
+            self.buf = Some(Arc::default());
         } else {
             let this_buf = self.ensure_buffer();
             this_buf.resize(len, 0);
@@ -263,16 +314,12 @@ impl Node {
             return true;
         }
 
-        if gid == self.get_gid() {
-            if self.get_perm() & constants::S_IRGRP != 0 {
-                return true;
-            }
+        if gid == self.get_gid() && self.get_perm() & constants::S_IRGRP != 0 {
+            return true;
         }
 
-        if uid == self.get_uid() {
-            if self.get_perm() & constants::S_IRUSR != 0 {
-                return true;
-            }
+        if uid == self.get_uid() && self.get_perm() & constants::S_IRUSR != 0 {
+            return true;
         }
 
         false
@@ -286,54 +333,151 @@ impl Node {
             return true;
         }
 
-        if gid == self.get_gid() {
-            if self.get_perm() & constants::S_IWGRP != 0 {
-                return true;
-            }
+        if gid == self.get_gid() && self.get_perm() & constants::S_IWGRP != 0 {
+            return true;
         }
 
-        if uid == self.get_uid() {
-            if self.get_perm() & constants::S_IWUSR != 0 {
-                return true;
-            }
+        if uid == self.get_uid() && self.get_perm() & constants::S_IWUSR != 0 {
+            return true;
         }
 
         false
     }
 
-    pub fn del(&mut self) {
-        // todo: this.emit('delete', this);
+    pub fn to_snapshot(&self) -> SnapshotNode {
+        SnapshotNode {
+            ino: self.ino,
+            mode: self.mode,
+            perm: self._perm,
+            uid: self._uid,
+            gid: self._gid,
+            atime: self._atime,
+            mtime: self._mtime,
+            ctime: self._ctime,
+            nlink: self._nlink,
+            symlink: self.symlink.clone(),
+            target: self.target.clone(),
+            buf: match self.lazy {
+                Some(_) => self.contents().ok().map(Cow::into_owned),
+                None => self.buf.as_deref().cloned(),
+            },
+        }
     }
 
+    pub fn from_snapshot(snapshot: SnapshotNode) -> Self {
+        Self {
+            ino: snapshot.ino,
+            generation: next_generation(),
+            _uid: snapshot.uid,
+            _gid: snapshot.gid,
+            _atime: snapshot.atime,
+            _mtime: snapshot.mtime,
+            _ctime: snapshot.ctime,
+            buf: snapshot.buf.map(Arc::new),
+            lazy: None,
+            _perm: snapshot.perm,
+            mode: snapshot.mode,
+            _nlink: snapshot.nlink,
+            symlink: snapshot.symlink,
+            target: snapshot.target,
+        }
+    }
+
+    #[cfg(feature = "wasm")]
     pub fn to_json(&mut self) -> Object {
-        let mut json = Object::new();
-        Reflect::set(&mut json, &"ino".into(), &self.ino.into()).unwrap();
-        Reflect::set(&mut json, &"uid".into(), &self.get_uid().into()).unwrap();
-        Reflect::set(&mut json, &"gid".into(), &self.get_gid().into()).unwrap();
-        Reflect::set(&mut json, &"atime".into(), &self.get_atime().into()).unwrap();
-        Reflect::set(&mut json, &"mtime".into(), &self.get_mtime().into()).unwrap();
-        Reflect::set(&mut json, &"ctime".into(), &self.get_ctime().into()).unwrap();
-        Reflect::set(&mut json, &"perm".into(), &self.get_perm().into()).unwrap();
-        Reflect::set(&mut json, &"mode".into(), &self.mode.into()).unwrap();
-        Reflect::set(&mut json, &"nlink".into(), &self.get_nlink().into()).unwrap();
+        let json = Object::new();
+        Reflect::set(&json, &"ino".into(), &self.ino.into()).unwrap();
+        Reflect::set(&json, &"uid".into(), &self.get_uid().into()).unwrap();
+        Reflect::set(&json, &"gid".into(), &self.get_gid().into()).unwrap();
+        Reflect::set(
+            &json,
+            &"atime".into(),
+            &platform::to_date(self.get_atime()).into(),
+        )
+        .unwrap();
+        Reflect::set(
+            &json,
+            &"mtime".into(),
+            &platform::to_date(self.get_mtime()).into(),
+        )
+        .unwrap();
+        Reflect::set(
+            &json,
+            &"ctime".into(),
+            &platform::to_date(self.get_ctime()).into(),
+        )
+        .unwrap();
+        Reflect::set(&json, &"perm".into(), &self.get_perm().into()).unwrap();
+        Reflect::set(&json, &"mode".into(), &self.mode.into()).unwrap();
+        Reflect::set(&json, &"nlink".into(), &self.get_nlink().into()).unwrap();
         let symlinks_arr = Array::new();
         for symlink in self.symlink.iter() {
             symlinks_arr.push(&symlink.into());
         }
-        Reflect::set(&mut json, &"symlink".into(), &symlinks_arr.into()).unwrap();
-        Reflect::set(
-            &mut json,
-            &"data".into(),
-            &JsString::from(self.get_string().clone()),
-        )
-        .unwrap();
+        Reflect::set(&json, &"symlink".into(), &symlinks_arr.into()).unwrap();
+        Reflect::set(&json, &"data".into(), &JsString::from(self.get_string())).unwrap();
         json
     }
 
+    // A copy numbered `ino`, with lazy contents loaded.
+    pub fn copy(&self, ino: usize) -> Result<Self, FsError> {
+        let buf = match self.lazy {
+            Some(_) => Some(Arc::new(self.contents()?.into_owned())),
+            None => self.buf.clone(),
+        };
+        Ok(Self {
+            ino,
+            generation: next_generation(),
+            _uid: self._uid,
+            _gid: self._gid,
+            _atime: self._atime,
+            _mtime: self._mtime,
+            _ctime: self._ctime,
+            buf,
+            lazy: None,
+            _perm: self._perm,
+            mode: self.mode,
+            _nlink: self._nlink,
+            symlink: self.symlink.clone(),
+            target: self.target.clone(),
+        })
+    }
+
+    // Materialises lazy contents, failing if they cannot be produced. The
+    // volume does so before handing out contents, so that the error reaches
+    // the caller.
+    pub fn load(&mut self) -> Result<(), FsError> {
+        if let Some(lazy) = &self.lazy {
+            self.buf = Some(Arc::new((lazy.load)()?));
+            self.lazy = None;
+        }
+        Ok(())
+    }
+
+    // Whether both have the very same contents loaded, as clones do until
+    // either writes.
+    pub fn shares_buffer(&self, other: &Node) -> bool {
+        match (&self.lazy, &self.buf, &other.lazy, &other.buf) {
+            (None, Some(a), None, Some(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    // Contents without loading them into the node.
+    pub fn contents(&self) -> Result<Cow<'_, [u8]>, FsError> {
+        match (&self.lazy, &self.buf) {
+            (Some(lazy), _) => Ok(Cow::Owned((lazy.load)()?)),
+            (None, Some(buf)) => Ok(Cow::Borrowed(buf.as_slice())),
+            (None, None) => Ok(Cow::Borrowed(&[])),
+        }
+    }
+
+    // Contents that fail to load read as empty here, see `load`. Contents
+    // shared with a clone are copied first.
     fn ensure_buffer(&mut self) -> &mut Vec<u8> {
-        if self.buf.is_none() {
-            self.buf = Some(Vec::new());
+        if self.lazy.is_some() && self.load().is_err() {
+            self.lazy = None;
         }
-        self.buf.as_mut().unwrap()
+        Arc::make_mut(self.buf.get_or_insert_with(Arc::default))
     }
 }
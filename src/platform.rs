@@ -0,0 +1,56 @@
+// Host services the core relies on: the wall clock and a source of
+// randomness. Inside a JS host they are backed by `Date.now` and
+// `Math.random`, since `SystemTime::now` is unavailable on wasm32.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub fn now() -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(js_sys::Date::now() as u64)
+}
+
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+pub fn now() -> SystemTime {
+    SystemTime::now()
+}
+
+// Milliseconds since the Unix epoch, like `Date.prototype.getTime`.
+pub fn to_ms(time: SystemTime) -> usize {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis() as usize
+}
+
+pub fn from_ms(ms: usize) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(ms as u64)
+}
+
+#[cfg(feature = "wasm")]
+pub fn to_date(time: SystemTime) -> js_sys::Date {
+    js_sys::Date::new(&(to_ms(time) as f64).into())
+}
+
+// Returns a number in `[0, 1)`, like `Math.random`.
+pub type Rng = Box<dyn FnMut() -> f64>;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub fn default_rng() -> Rng {
+    Box::new(js_sys::Math::random)
+}
+
+// xorshift64*, seeded from the clock. Good enough for temp file names, not
+// meant for anything security related.
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+pub fn default_rng() -> Rng {
+    let nanos = now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_nanos() as u64;
+    let mut state = nanos | 1;
+    Box::new(move || {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        let n = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (n >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
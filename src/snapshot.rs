@@ -0,0 +1,200 @@
+// Lossless binary snapshots of whole volumes, see `Volume::to_snapshot`.
+//
+// Layout: the magic `WFSS`, a version byte, then the nodes followed by the
+// links. Integers are unsigned LEB128, strings and buffers are prefixed with
+// their length, times are seconds and nanoseconds since the Unix epoch. Links
+// come in tree order, the root first, each referring to its parent by
+// position, so multiply-linked nodes are simply referenced more than once.
+use crate::error::{create_error, FSError, FsError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAGIC: &[u8] = b"WFSS";
+const VERSION: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotNode {
+    pub ino: usize,
+    pub mode: usize,
+    pub perm: usize,
+    pub uid: usize,
+    pub gid: usize,
+    pub atime: SystemTime,
+    pub mtime: SystemTime,
+    pub ctime: SystemTime,
+    pub nlink: usize,
+    pub symlink: Vec<String>,
+    pub target: String,
+    pub buf: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotLink {
+    // Position of the parent in `Snapshot::links`, `None` for the root.
+    pub parent: Option<usize>,
+    pub name: String,
+    pub ino: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub nodes: Vec<SnapshotNode>,
+    pub links: Vec<SnapshotLink>,
+}
+
+impl Snapshot {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer(Vec::new());
+        writer.0.extend_from_slice(MAGIC);
+        writer.0.push(VERSION);
+        writer.uint(self.nodes.len());
+        for node in &self.nodes {
+            writer.uint(node.ino);
+            writer.uint(node.mode);
+            writer.uint(node.perm);
+            writer.uint(node.uid);
+            writer.uint(node.gid);
+            writer.time(node.atime);
+            writer.time(node.mtime);
+            writer.time(node.ctime);
+            writer.uint(node.nlink);
+            writer.uint(node.symlink.len());
+            for step in &node.symlink {
+                writer.bytes(step.as_bytes());
+            }
+            writer.bytes(node.target.as_bytes());
+            match &node.buf {
+                Some(buf) => {
+                    writer.uint(1);
+                    writer.bytes(buf);
+                }
+                None => writer.uint(0),
+            }
+        }
+        writer.uint(self.links.len());
+        for link in &self.links {
+            // Parents precede their children, so 0 can stand for no parent.
+            writer.uint(link.parent.map_or(0, |parent| parent + 1));
+            writer.bytes(link.name.as_bytes());
+            writer.uint(link.ino);
+        }
+        writer.0
+    }
+
+    // Fails with EINVAL if `bytes` is not a snapshot of a version this crate
+    // can read.
+    pub fn decode(bytes: &[u8]) -> Result<Snapshot, FsError> {
+        Snapshot::read(&mut Reader(bytes))
+            .ok_or_else(|| create_error(FSError::EINVAL, Some(String::from("from_snapshot")), None))
+    }
+
+    fn read(reader: &mut Reader) -> Option<Snapshot> {
+        if reader.take(MAGIC.len())? != MAGIC || reader.take(1)? != [VERSION] {
+            return None;
+        }
+        let mut snapshot = Snapshot::default();
+        for _ in 0..reader.uint()? {
+            snapshot.nodes.push(SnapshotNode {
+                ino: reader.uint()?,
+                mode: reader.uint()?,
+                perm: reader.uint()?,
+                uid: reader.uint()?,
+                gid: reader.uint()?,
+                atime: reader.time()?,
+                mtime: reader.time()?,
+                ctime: reader.time()?,
+                nlink: reader.uint()?,
+                symlink: (0..reader.uint()?)
+                    .map(|_| reader.string())
+                    .collect::<Option<_>>()?,
+                target: reader.string()?,
+                buf: match reader.uint()? {
+                    0 => None,
+                    1 => Some(reader.bytes()?.to_vec()),
+                    _ => return None,
+                },
+            });
+        }
+        for _ in 0..reader.uint()? {
+            let parent = reader.uint()?.checked_sub(1);
+            if parent.is_some_and(|parent| parent >= snapshot.links.len()) {
+                return None;
+            }
+            snapshot.links.push(SnapshotLink {
+                parent,
+                name: reader.string()?,
+                ino: reader.uint()?,
+            });
+        }
+        if !reader.0.is_empty() {
+            return None;
+        }
+        Some(snapshot)
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn uint(&mut self, value: usize) {
+        let mut value = value as u64;
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.uint(bytes.len());
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn time(&mut self, time: SystemTime) {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+        self.uint(since_epoch.as_secs() as usize);
+        self.uint(since_epoch.subsec_nanos() as usize);
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn uint(&mut self) -> Option<usize> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return usize::try_from(value).ok();
+            }
+        }
+        None
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.uint()?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+
+    fn time(&mut self) -> Option<SystemTime> {
+        let secs = self.uint()? as u64;
+        let nanos = u32::try_from(self.uint()?).ok()?;
+        if nanos >= 1_000_000_000 {
+            return None;
+        }
+        UNIX_EPOCH.checked_add(Duration::new(secs, nanos))
+    }
+}
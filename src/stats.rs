@@ -1,34 +1,92 @@
 #![allow(dead_code)] // todo: remove this once all functions are implemented
-use crate::constants::constants;
-use js_sys::Date;
+use crate::{constants::constants, node::Node, platform};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+#[derive(Clone)]
 pub struct Stats {
     // https://github.com/streamich/memfs/blob/9aba94322789d85da41905e1aed1e20e8ffe75ec/src/Stats.ts#L12
-    uid: usize,
-    gid: usize,
-
-    rdev: usize,
-    blksize: usize,
-    ino: usize,
-    size: usize,
-    blocks: usize,
-
-    atime: Date,
-    mtime: Date,
-    ctime: Date,
-    birthtime: Date,
-
-    atime_ms: usize,
-    mtime_ms: usize,
-    ctime_ms: usize,
-    birthtime_ms: usize,
-
-    dev: usize,
-    mode: usize,
-    nlink: usize,
+    pub uid: usize,
+    pub gid: usize,
+
+    pub rdev: usize,
+    pub blksize: usize,
+    pub ino: usize,
+    pub size: usize,
+    pub blocks: usize,
+
+    pub atime: SystemTime,
+    pub mtime: SystemTime,
+    pub ctime: SystemTime,
+    pub birthtime: SystemTime,
+
+    pub atime_ms: usize,
+    pub mtime_ms: usize,
+    pub ctime_ms: usize,
+    pub birthtime_ms: usize,
+
+    pub dev: usize,
+    pub mode: usize,
+    pub nlink: usize,
+
+    // Whether these stats were requested as `BigIntStats`.
+    pub bigint: bool,
 }
 
 impl Stats {
+    // Copies all values from `node`, so that later changes of the node are
+    // not reflected in the returned stats, just like in Node's fs.
+    pub fn build(node: &Node, bigint: bool) -> Stats {
+        let atime = node.get_atime();
+        let mtime = node.get_mtime();
+        let ctime = node.get_ctime();
+        Stats {
+            uid: node.get_uid(),
+            gid: node.get_gid(),
+            rdev: 0,
+            blksize: 4096,
+            ino: node.ino,
+            size: node.get_size(),
+            blocks: 1,
+            atime_ms: platform::to_ms(atime),
+            mtime_ms: platform::to_ms(mtime),
+            ctime_ms: platform::to_ms(ctime),
+            birthtime_ms: platform::to_ms(ctime),
+            atime,
+            mtime,
+            ctime,
+            birthtime: ctime,
+            dev: 0,
+            mode: node.mode,
+            nlink: node.get_nlink(),
+            bigint,
+        }
+    }
+
+    // All-zero stats, reported by `watchFile` for files that do not exist.
+    pub fn zeroed(bigint: bool) -> Stats {
+        Stats {
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: 0,
+            ino: 0,
+            size: 0,
+            blocks: 0,
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            birthtime: UNIX_EPOCH,
+            atime_ms: 0,
+            mtime_ms: 0,
+            ctime_ms: 0,
+            birthtime_ms: 0,
+            dev: 0,
+            mode: 0,
+            nlink: 0,
+            bigint,
+        }
+    }
+
     fn _check_mode_property(&self, property: usize) -> bool {
         self.mode & constants::S_IFMT == property
     }
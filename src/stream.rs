@@ -0,0 +1,843 @@
+use crate::{
+    encoding::{string_to_buffer, Encoding, StringDecoder},
+    error::{create_error, FSError, FsError},
+    events::{EventEmitter, ListenerId},
+    volume::Volume,
+};
+#[cfg(feature = "wasm")]
+use js_sys::{Error as JsError, Object, Reflect, Uint8Array};
+use std::{cell::RefCell, sync::Arc};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::{closure::Closure, JsValue};
+#[cfg(feature = "wasm")]
+use web_sys::{ReadableStream, ReadableStreamDefaultController, WritableStream};
+
+const DEFAULT_HIGH_WATER_MARK: usize = 64 * 1024;
+const DEFAULT_WRITE_HIGH_WATER_MARK: usize = 16 * 1024;
+
+// A piece of data flowing through a stream: raw bytes, or a string when the
+// stream has an `encoding` set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreamChunk {
+    Buffer(Vec<u8>),
+    String(String),
+}
+
+pub enum ReadStreamEvent {
+    Open(usize),
+    Ready,
+    Data(StreamChunk),
+    End,
+    Close,
+    Error(FsError),
+}
+
+#[derive(Clone, Default)]
+pub struct ReadStreamOptions {
+    // https://nodejs.org/api/fs.html#fscreatereadstreampath-options
+    pub flags: Option<String>,
+    pub encoding: Option<String>,
+    pub fd: Option<usize>,
+    pub mode: Option<usize>,
+    pub auto_close: Option<bool>,
+    pub emit_close: Option<bool>,
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+    pub high_water_mark: Option<usize>,
+}
+
+pub struct ReadStream {
+    // https://github.com/nodejs/node/blob/v18.15.0/lib/internal/fs/streams.js#L144
+    vol: Arc<RefCell<Volume>>,
+
+    pub path: String,
+    pub fd: Option<usize>,
+    pub flags: String,
+    pub mode: usize,
+
+    // Inclusive byte range to read, `end` of `None` reads until EOF.
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+    pub pos: Option<usize>,
+
+    pub bytes_read: usize,
+    pub high_water_mark: usize,
+    pub auto_close: bool,
+    pub emit_close: bool,
+
+    pub closed: bool,
+    pub destroyed: bool,
+    pub ended: bool,
+
+    decoder: Option<StringDecoder>,
+    events: EventEmitter<ReadStreamEvent>,
+}
+
+impl ReadStream {
+    pub fn new(
+        vol: Arc<RefCell<Volume>>,
+        path: String,
+        options: ReadStreamOptions,
+    ) -> Result<ReadStream, FsError> {
+        let decoder = match options.encoding {
+            Some(encoding) => match Encoding::parse(&encoding) {
+                Some(encoding) => Some(StringDecoder::new(encoding)),
+                None => {
+                    return Err(create_error(
+                        FSError::EINVAL,
+                        Some(String::from("createReadStream")),
+                        Some(vec![encoding]),
+                    ))
+                }
+            },
+            None => None,
+        };
+        if let (Some(start), Some(end)) = (options.start, options.end) {
+            if start > end {
+                return Err(create_error(
+                    FSError::EINVAL,
+                    Some(String::from("createReadStream")),
+                    Some(vec![path]),
+                ));
+            }
+        }
+        Ok(ReadStream {
+            vol,
+            path,
+            fd: options.fd,
+            flags: options.flags.unwrap_or(String::from("r")),
+            mode: options.mode.unwrap_or(0o666),
+            start: options.start,
+            end: options.end,
+            pos: options.start,
+            bytes_read: 0,
+            high_water_mark: options
+                .high_water_mark
+                .unwrap_or(DEFAULT_HIGH_WATER_MARK)
+                .max(1),
+            auto_close: options.auto_close.unwrap_or(true),
+            emit_close: options.emit_close.unwrap_or(true),
+            closed: false,
+            destroyed: false,
+            ended: false,
+            decoder,
+            events: EventEmitter::new(),
+        })
+    }
+
+    // True until the underlying file descriptor has been opened.
+    pub fn pending(&self) -> bool {
+        self.fd.is_none()
+    }
+
+    pub fn on(&mut self, listener: impl FnMut(&ReadStreamEvent) + 'static) -> ListenerId {
+        self.events.on(listener)
+    }
+
+    pub fn off(&mut self, id: ListenerId) -> bool {
+        self.events.off(id)
+    }
+
+    pub fn open(&mut self) -> Result<(), FsError> {
+        if self.fd.is_some() {
+            return Ok(());
+        }
+        let fd = self.vol.borrow_mut().open_sync(
+            self.path.clone(),
+            Some(self.flags.clone()),
+            Some(self.mode),
+        );
+        match fd {
+            Ok(fd) => {
+                self.fd = Some(fd);
+                self.events.emit(&ReadStreamEvent::Open(fd));
+                self.events.emit(&ReadStreamEvent::Ready);
+                Ok(())
+            }
+            Err(err) => Err(self.error(err)),
+        }
+    }
+
+    // Reads the next chunk of at most `high_water_mark` bytes, returns `None`
+    // once the end of the file (or of the requested range) has been reached.
+    pub fn read(&mut self) -> Result<Option<StreamChunk>, FsError> {
+        if self.destroyed || self.ended {
+            return Ok(None);
+        }
+        self.open()?;
+        let fd = self.fd.unwrap();
+
+        let to_read = match self.end {
+            Some(end) => {
+                let from = self.pos.unwrap_or(self.bytes_read);
+                if from > end {
+                    0
+                } else {
+                    self.high_water_mark.min(end - from + 1)
+                }
+            }
+            None => self.high_water_mark,
+        };
+        if to_read == 0 {
+            return self.finish();
+        }
+
+        let mut buf = vec![0; to_read];
+        let bytes = self
+            .vol
+            .borrow_mut()
+            .read_sync(fd, &mut buf, None, Some(to_read), self.pos);
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(err) => return Err(self.error(err)),
+        };
+        if bytes == 0 {
+            return self.finish();
+        }
+        buf.truncate(bytes);
+        if let Some(pos) = self.pos.as_mut() {
+            *pos += bytes;
+        }
+        self.bytes_read += bytes;
+
+        let chunk = match self.decoder.as_mut() {
+            Some(decoder) => {
+                let string = decoder.write(&buf);
+                if string.is_empty() {
+                    // Only part of a multi-byte character so far.
+                    return self.read();
+                }
+                StreamChunk::String(string)
+            }
+            None => StreamChunk::Buffer(buf),
+        };
+        Ok(Some(self.emit_data(chunk)))
+    }
+
+    // Writes every remaining chunk into `dest` and ends it afterwards.
+    pub fn pipe(&mut self, dest: &mut WriteStream) -> Result<(), FsError> {
+        while let Some(chunk) = self.read()? {
+            dest.write(chunk)?;
+        }
+        dest.end(None)
+    }
+
+    // Switches the stream into flowing mode: reads and emits every remaining
+    // chunk until the end of the stream.
+    pub fn resume(&mut self) -> Result<(), FsError> {
+        while self.read()?.is_some() {}
+        Ok(())
+    }
+
+    pub fn close(&mut self) -> Result<(), FsError> {
+        self.destroy()
+    }
+
+    pub fn destroy(&mut self) -> Result<(), FsError> {
+        if self.destroyed {
+            return Ok(());
+        }
+        self.destroyed = true;
+        if let Some(fd) = self.fd.take() {
+            self.vol.borrow_mut().close_sync(fd)?;
+        }
+        self.closed = true;
+        if self.emit_close {
+            self.events.emit(&ReadStreamEvent::Close);
+        }
+        Ok(())
+    }
+
+    // Exposes the stream as a WHATWG `ReadableStream` which pulls one chunk
+    // from the file per request of the consumer.
+    #[cfg(feature = "wasm")]
+    #[allow(clippy::arc_with_non_send_sync)] // Only shared with JS callbacks.
+    pub fn into_web_stream(self) -> ReadableStream {
+        let stream = Arc::new(RefCell::new(self));
+        let source = Object::new();
+
+        let puller = stream.clone();
+        let pull = Closure::wrap(
+            Box::new(move |controller: ReadableStreamDefaultController| {
+                let result = match puller.borrow_mut().read() {
+                    Ok(Some(StreamChunk::Buffer(buf))) => {
+                        controller.enqueue_with_chunk(&Uint8Array::from(&buf[..]))
+                    }
+                    Ok(Some(StreamChunk::String(string))) => {
+                        controller.enqueue_with_chunk(&JsValue::from(string))
+                    }
+                    Ok(None) => controller.close(),
+                    Err(err) => {
+                        controller.error_with_e(&JsError::from(err));
+                        Ok(())
+                    }
+                };
+                result.unwrap();
+            }) as Box<dyn FnMut(ReadableStreamDefaultController)>,
+        );
+
+        let cancel = Closure::wrap(Box::new(move |_reason: JsValue| {
+            let _ = stream.borrow_mut().destroy();
+        }) as Box<dyn FnMut(JsValue)>);
+
+        Reflect::set(&source, &"pull".into(), &pull.into_js_value()).unwrap();
+        Reflect::set(&source, &"cancel".into(), &cancel.into_js_value()).unwrap();
+        ReadableStream::new_with_underlying_source(&source).unwrap()
+    }
+
+    fn emit_data(&mut self, chunk: StreamChunk) -> StreamChunk {
+        let event = ReadStreamEvent::Data(chunk);
+        self.events.emit(&event);
+        match event {
+            ReadStreamEvent::Data(chunk) => chunk,
+            _ => unreachable!(),
+        }
+    }
+
+    fn finish(&mut self) -> Result<Option<StreamChunk>, FsError> {
+        self.ended = true;
+        let rest = self
+            .decoder
+            .as_mut()
+            .map(|decoder| decoder.end())
+            .filter(|rest| !rest.is_empty())
+            .map(|rest| self.emit_data(StreamChunk::String(rest)));
+        self.events.emit(&ReadStreamEvent::End);
+        if self.auto_close {
+            self.destroy()?;
+        }
+        Ok(rest)
+    }
+
+    fn error(&mut self, err: FsError) -> FsError {
+        self.events.emit(&ReadStreamEvent::Error(err.clone()));
+        if self.auto_close {
+            let _ = self.destroy();
+        }
+        err
+    }
+}
+
+impl Iterator for ReadStream {
+    type Item = Result<StreamChunk, FsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+pub enum WriteStreamEvent {
+    Open(usize),
+    Ready,
+    Drain,
+    Finish,
+    Close,
+    Error(FsError),
+}
+
+#[derive(Clone, Default)]
+pub struct WriteStreamOptions {
+    // https://nodejs.org/api/fs.html#fscreatewritestreampath-options
+    pub flags: Option<String>,
+    pub encoding: Option<String>,
+    pub fd: Option<usize>,
+    pub mode: Option<usize>,
+    pub auto_close: Option<bool>,
+    pub emit_close: Option<bool>,
+    pub start: Option<usize>,
+    pub high_water_mark: Option<usize>,
+}
+
+pub struct WriteStream {
+    // https://github.com/nodejs/node/blob/v18.15.0/lib/internal/fs/streams.js#L299
+    vol: Arc<RefCell<Volume>>,
+
+    pub path: String,
+    pub fd: Option<usize>,
+    pub flags: String,
+    pub mode: usize,
+
+    // Position of the next write, `None` writes at the file position (which
+    // is the end of the file for "a" flags).
+    pub start: Option<usize>,
+    pub pos: Option<usize>,
+
+    pub bytes_written: usize,
+    pub high_water_mark: usize,
+    pub auto_close: bool,
+    pub emit_close: bool,
+
+    pub closed: bool,
+    pub destroyed: bool,
+    pub ended: bool,
+
+    encoding: Encoding,
+
+    // Chunks held back by `cork` until the matching `uncork`.
+    corked: usize,
+    buffered: Vec<Vec<u8>>,
+    buffered_len: usize,
+    need_drain: bool,
+
+    events: EventEmitter<WriteStreamEvent>,
+}
+
+impl WriteStream {
+    pub fn new(
+        vol: Arc<RefCell<Volume>>,
+        path: String,
+        options: WriteStreamOptions,
+    ) -> Result<WriteStream, FsError> {
+        let encoding = options.encoding.unwrap_or(String::from("utf8"));
+        let encoding = match Encoding::parse(&encoding) {
+            Some(encoding) => encoding,
+            None => {
+                return Err(create_error(
+                    FSError::EINVAL,
+                    Some(String::from("createWriteStream")),
+                    Some(vec![encoding]),
+                ))
+            }
+        };
+        Ok(WriteStream {
+            vol,
+            path,
+            fd: options.fd,
+            flags: options.flags.unwrap_or(String::from("w")),
+            mode: options.mode.unwrap_or(0o666),
+            start: options.start,
+            pos: options.start,
+            bytes_written: 0,
+            high_water_mark: options
+                .high_water_mark
+                .unwrap_or(DEFAULT_WRITE_HIGH_WATER_MARK),
+            auto_close: options.auto_close.unwrap_or(true),
+            emit_close: options.emit_close.unwrap_or(true),
+            closed: false,
+            destroyed: false,
+            ended: false,
+            encoding,
+            corked: 0,
+            buffered: Vec::new(),
+            buffered_len: 0,
+            need_drain: false,
+            events: EventEmitter::new(),
+        })
+    }
+
+    // True until the underlying file descriptor has been opened.
+    pub fn pending(&self) -> bool {
+        self.fd.is_none()
+    }
+
+    pub fn on(&mut self, listener: impl FnMut(&WriteStreamEvent) + 'static) -> ListenerId {
+        self.events.on(listener)
+    }
+
+    pub fn off(&mut self, id: ListenerId) -> bool {
+        self.events.off(id)
+    }
+
+    pub fn open(&mut self) -> Result<(), FsError> {
+        if self.fd.is_some() {
+            return Ok(());
+        }
+        let fd = self.vol.borrow_mut().open_sync(
+            self.path.clone(),
+            Some(self.flags.clone()),
+            Some(self.mode),
+        );
+        match fd {
+            Ok(fd) => {
+                self.fd = Some(fd);
+                self.events.emit(&WriteStreamEvent::Open(fd));
+                self.events.emit(&WriteStreamEvent::Ready);
+                Ok(())
+            }
+            Err(err) => Err(self.error(err)),
+        }
+    }
+
+    // Writes `chunk` straight through to the file, or buffers it while the
+    // stream is corked. Returns `false` once the buffered data reaches
+    // `high_water_mark`, a `Drain` event follows when it has been flushed.
+    pub fn write(&mut self, chunk: StreamChunk) -> Result<bool, FsError> {
+        if self.ended || self.destroyed {
+            let err = create_error(
+                FSError::EBADF,
+                Some(String::from("write")),
+                Some(vec![self.path.clone()]),
+            );
+            return Err(self.error(err));
+        }
+        let buf = match chunk {
+            StreamChunk::Buffer(buf) => buf,
+            StreamChunk::String(string) => string_to_buffer(&string, self.encoding),
+        };
+        if self.corked > 0 {
+            self.buffered_len += buf.len();
+            self.buffered.push(buf);
+            let ok = self.buffered_len < self.high_water_mark;
+            self.need_drain |= !ok;
+            return Ok(ok);
+        }
+        self.flush(vec![buf])?;
+        Ok(true)
+    }
+
+    pub fn cork(&mut self) {
+        self.corked += 1;
+    }
+
+    // Flushes everything written since the first `cork` with a single
+    // `writev`, once `uncork` has been called as many times as `cork`.
+    pub fn uncork(&mut self) -> Result<(), FsError> {
+        if self.corked == 0 {
+            return Ok(());
+        }
+        self.corked -= 1;
+        if self.corked > 0 {
+            return Ok(());
+        }
+        self.flush_buffered()
+    }
+
+    // Flushes buffered chunks, optionally writes a final `chunk`, and closes
+    // the file descriptor if `auto_close` is set.
+    pub fn end(&mut self, chunk: Option<StreamChunk>) -> Result<(), FsError> {
+        if self.ended {
+            return Ok(());
+        }
+        if let Some(chunk) = chunk {
+            self.write(chunk)?;
+        }
+        self.corked = 0;
+        self.flush_buffered()?;
+        self.open()?;
+        self.ended = true;
+        self.events.emit(&WriteStreamEvent::Finish);
+        if self.auto_close {
+            self.destroy()?;
+        }
+        Ok(())
+    }
+
+    pub fn close(&mut self) -> Result<(), FsError> {
+        self.end(None)?;
+        self.destroy()
+    }
+
+    pub fn destroy(&mut self) -> Result<(), FsError> {
+        if self.destroyed {
+            return Ok(());
+        }
+        self.destroyed = true;
+        self.buffered.clear();
+        self.buffered_len = 0;
+        if let Some(fd) = self.fd.take() {
+            self.vol.borrow_mut().close_sync(fd)?;
+        }
+        self.closed = true;
+        if self.emit_close {
+            self.events.emit(&WriteStreamEvent::Close);
+        }
+        Ok(())
+    }
+
+    // Exposes the stream as a WHATWG `WritableStream`. Strings are encoded
+    // with the stream's encoding, any other chunk is read as a `Uint8Array`.
+    #[cfg(feature = "wasm")]
+    #[allow(clippy::arc_with_non_send_sync)] // Only shared with JS callbacks.
+    pub fn into_web_stream(self) -> WritableStream {
+        let stream = Arc::new(RefCell::new(self));
+        let sink = Object::new();
+
+        let writer = stream.clone();
+        let write = Closure::wrap(Box::new(move |chunk: JsValue| -> Result<(), JsValue> {
+            let chunk = match chunk.as_string() {
+                Some(string) => StreamChunk::String(string),
+                None => StreamChunk::Buffer(Uint8Array::new(&chunk).to_vec()),
+            };
+            writer.borrow_mut().write(chunk)?;
+            Ok(())
+        }) as Box<dyn FnMut(JsValue) -> Result<(), JsValue>>);
+
+        let closer = stream.clone();
+        let close = Closure::wrap(Box::new(move || -> Result<(), JsValue> {
+            closer.borrow_mut().end(None)?;
+            Ok(())
+        }) as Box<dyn FnMut() -> Result<(), JsValue>>);
+
+        let abort = Closure::wrap(Box::new(move |_reason: JsValue| {
+            let _ = stream.borrow_mut().destroy();
+        }) as Box<dyn FnMut(JsValue)>);
+
+        Reflect::set(&sink, &"write".into(), &write.into_js_value()).unwrap();
+        Reflect::set(&sink, &"close".into(), &close.into_js_value()).unwrap();
+        Reflect::set(&sink, &"abort".into(), &abort.into_js_value()).unwrap();
+        WritableStream::new_with_underlying_sink(&sink).unwrap()
+    }
+
+    fn flush_buffered(&mut self) -> Result<(), FsError> {
+        let buffered = std::mem::take(&mut self.buffered);
+        self.buffered_len = 0;
+        self.flush(buffered)?;
+        if self.need_drain {
+            self.need_drain = false;
+            self.events.emit(&WriteStreamEvent::Drain);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, chunks: Vec<Vec<u8>>) -> Result<(), FsError> {
+        if chunks.is_empty() {
+            return Ok(());
+        }
+        self.open()?;
+        let fd = self.fd.unwrap();
+        let bytes = self.vol.borrow_mut().writev_sync(fd, &chunks, self.pos);
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(err) => return Err(self.error(err)),
+        };
+        if let Some(pos) = self.pos.as_mut() {
+            *pos += bytes;
+        }
+        self.bytes_written += bytes;
+        Ok(())
+    }
+
+    fn error(&mut self, err: FsError) -> FsError {
+        self.events.emit(&WriteStreamEvent::Error(err.clone()));
+        if self.auto_close {
+            let _ = self.destroy();
+        }
+        err
+    }
+}
+
+#[test]
+fn test_read_stream_range_and_chunks() {
+    use std::rc::Rc;
+    let vol = Volume::new();
+    vol.borrow_mut()
+        .write_file_sync(String::from("/digits.txt"), b"0123456789", None)
+        .unwrap();
+    let options = ReadStreamOptions {
+        start: Some(2),
+        end: Some(7),
+        high_water_mark: Some(4),
+        ..Default::default()
+    };
+    let mut stream = vol
+        .borrow_mut()
+        .create_read_stream(String::from("/digits.txt"), Some(options))
+        .unwrap();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let log = events.clone();
+    stream.on(move |event| {
+        log.borrow_mut().push(match event {
+            ReadStreamEvent::Open(_) => String::from("open"),
+            ReadStreamEvent::Ready => String::from("ready"),
+            ReadStreamEvent::Data(StreamChunk::Buffer(buf)) => {
+                String::from_utf8(buf.clone()).unwrap()
+            }
+            ReadStreamEvent::Data(StreamChunk::String(string)) => string.clone(),
+            ReadStreamEvent::End => String::from("end"),
+            ReadStreamEvent::Close => String::from("close"),
+            ReadStreamEvent::Error(err) => err.to_string(),
+        })
+    });
+    assert!(stream.pending());
+    let chunks: Vec<StreamChunk> = stream.by_ref().map(Result::unwrap).collect();
+    assert_eq!(
+        chunks,
+        [
+            StreamChunk::Buffer(b"2345".to_vec()),
+            StreamChunk::Buffer(b"67".to_vec()),
+        ]
+    );
+    assert_eq!(
+        *events.borrow(),
+        ["open", "ready", "2345", "67", "end", "close"]
+    );
+    assert_eq!(stream.bytes_read, 6);
+    assert!(stream.closed && stream.fd.is_none());
+
+    // Without an end the file is read to its end, chunk by chunk.
+    let options = ReadStreamOptions {
+        encoding: Some(String::from("utf8")),
+        high_water_mark: Some(3),
+        ..Default::default()
+    };
+    let stream = vol
+        .borrow_mut()
+        .create_read_stream(String::from("/digits.txt"), Some(options))
+        .unwrap();
+    let chunks: Vec<StreamChunk> = stream.map(Result::unwrap).collect();
+    assert_eq!(chunks.len(), 4);
+    assert_eq!(chunks[3], StreamChunk::String(String::from("9")));
+
+    let options = ReadStreamOptions {
+        start: Some(5),
+        end: Some(4),
+        ..Default::default()
+    };
+    let err = vol
+        .borrow_mut()
+        .create_read_stream(String::from("/digits.txt"), Some(options))
+        .err()
+        .unwrap();
+    assert_eq!(err.code, FSError::EINVAL);
+}
+
+#[test]
+fn test_read_stream_close_and_destroy() {
+    use std::rc::Rc;
+    let vol = Volume::new();
+    vol.borrow_mut()
+        .write_file_sync(String::from("/a.txt"), b"abcdef", None)
+        .unwrap();
+    let options = ReadStreamOptions {
+        high_water_mark: Some(2),
+        ..Default::default()
+    };
+    let mut stream = vol
+        .borrow_mut()
+        .create_read_stream(String::from("/a.txt"), Some(options.clone()))
+        .unwrap();
+    let closes = Rc::new(RefCell::new(0));
+    let count = closes.clone();
+    stream.on(move |event| {
+        if let ReadStreamEvent::Close = event {
+            *count.borrow_mut() += 1;
+        }
+    });
+    assert_eq!(
+        stream.read().unwrap(),
+        Some(StreamChunk::Buffer(b"ab".to_vec()))
+    );
+    let fd = stream.fd.unwrap();
+    stream.destroy().unwrap();
+    stream.close().unwrap();
+    assert!(stream.destroyed && stream.closed);
+    assert_eq!(*closes.borrow(), 1);
+    assert_eq!(stream.read().unwrap(), None);
+    let err = vol.borrow_mut().close_sync(fd).unwrap_err();
+    assert_eq!(err.code, FSError::EBADF);
+
+    // Without auto_close the descriptor stays open after the end.
+    let options = ReadStreamOptions {
+        auto_close: Some(false),
+        emit_close: Some(false),
+        ..options
+    };
+    let mut stream = vol
+        .borrow_mut()
+        .create_read_stream(String::from("/a.txt"), Some(options))
+        .unwrap();
+    stream.resume().unwrap();
+    assert!(stream.ended && !stream.closed);
+    let fd = stream.fd.unwrap();
+    vol.borrow_mut().close_sync(fd).unwrap();
+
+    let mut stream = vol
+        .borrow_mut()
+        .create_read_stream(String::from("/missing.txt"), None)
+        .unwrap();
+    let err = stream.read().unwrap_err();
+    assert_eq!(err.code, FSError::ENOENT);
+}
+
+#[test]
+fn test_write_stream_start_and_cork() {
+    use std::rc::Rc;
+    let vol = Volume::new();
+    vol.borrow_mut()
+        .write_file_sync(String::from("/a.txt"), b"0123456789", None)
+        .unwrap();
+    let options = WriteStreamOptions {
+        flags: Some(String::from("r+")),
+        start: Some(3),
+        high_water_mark: Some(4),
+        ..Default::default()
+    };
+    let mut stream = vol
+        .borrow_mut()
+        .create_write_stream(String::from("/a.txt"), Some(options))
+        .unwrap();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let log = events.clone();
+    stream.on(move |event| {
+        log.borrow_mut().push(match event {
+            WriteStreamEvent::Open(_) => "open",
+            WriteStreamEvent::Ready => "ready",
+            WriteStreamEvent::Drain => "drain",
+            WriteStreamEvent::Finish => "finish",
+            WriteStreamEvent::Close => "close",
+            WriteStreamEvent::Error(_) => "error",
+        })
+    });
+    let read = |vol: &Arc<RefCell<Volume>>| {
+        vol.borrow_mut()
+            .read_file_sync(String::from("/a.txt"))
+            .unwrap()
+    };
+
+    assert!(stream
+        .write(StreamChunk::String(String::from("ab")))
+        .unwrap());
+    assert_eq!(read(&vol), b"012ab56789");
+
+    // Corked chunks wait for the last uncork, past the high water mark they
+    // ask the writer to wait for a drain.
+    stream.cork();
+    stream.cork();
+    assert!(stream.write(StreamChunk::Buffer(b"cd".to_vec())).unwrap());
+    assert!(!stream.write(StreamChunk::Buffer(b"ef".to_vec())).unwrap());
+    stream.uncork().unwrap();
+    assert_eq!(read(&vol), b"012ab56789");
+    stream.uncork().unwrap();
+    assert_eq!(read(&vol), b"012abcdef9");
+    assert_eq!(stream.bytes_written, 6);
+    assert_eq!(*events.borrow(), ["open", "ready", "drain"]);
+}
+
+#[test]
+fn test_write_stream_end_and_close() {
+    let vol = Volume::new();
+    let mut stream = vol
+        .borrow_mut()
+        .create_write_stream(String::from("/a.txt"), None)
+        .unwrap();
+    stream.cork();
+    stream
+        .write(StreamChunk::Buffer(b"corked ".to_vec()))
+        .unwrap();
+    stream
+        .end(Some(StreamChunk::String(String::from("end"))))
+        .unwrap();
+    assert!(stream.ended && stream.closed);
+    assert_eq!(
+        vol.borrow_mut()
+            .read_file_sync(String::from("/a.txt"))
+            .unwrap(),
+        b"corked end"
+    );
+    let err = stream
+        .write(StreamChunk::Buffer(b"late".to_vec()))
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EBADF);
+
+    // Ending opens the file even if nothing was written.
+    let mut stream = vol
+        .borrow_mut()
+        .create_write_stream(String::from("/empty.txt"), None)
+        .unwrap();
+    stream.close().unwrap();
+    assert!(vol.borrow_mut().exists_sync(String::from("/empty.txt")));
+    let err = stream
+        .write(StreamChunk::Buffer(b"x".to_vec()))
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EBADF);
+}
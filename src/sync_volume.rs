@@ -0,0 +1,486 @@
+use crate::{
+    backend::Backend,
+    diff::Diff,
+    dirent::Dirent,
+    error::{create_error, FSError, FsError},
+    file::FileLock,
+    stats::Stats,
+    volume::{MkdirOptions, MountInfo, MountOptions, RmOptions, Volume, WriteFileOptions},
+};
+use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+// A volume is shared through `Arc<RefCell<..>>` and holds listeners that are
+// not `Send`, which is sound to move between threads as long as only one
+// thread at a time can reach it: `SyncVolume` keeps it behind a mutex and only
+// ever hands out owned data.
+struct Inner(Arc<RefCell<Volume>>);
+
+unsafe impl Send for Inner {}
+
+// A `Volume` that is `Send + Sync`, so it can be shared between threads (or
+// wasm threads backed by a `SharedArrayBuffer`). Every operation locks the
+// whole volume. Operations handing out references into the volume, like
+// streams, watchers and `FileHandle`s, are only available on `Volume`, as are
+// the JSON and archive imports and exports. Volumes mounted or stacked below
+// an overlay are taken over, so that nothing else can reach them.
+pub struct SyncVolume {
+    inner: Mutex<Inner>,
+}
+
+impl Default for SyncVolume {
+    fn default() -> Self {
+        SyncVolume {
+            inner: Mutex::new(Inner(Volume::new())),
+        }
+    }
+}
+
+impl SyncVolume {
+    pub fn new() -> SyncVolume {
+        SyncVolume::default()
+    }
+
+    // `vol` must not be reachable from anywhere else.
+    fn wrap(vol: Arc<RefCell<Volume>>) -> SyncVolume {
+        SyncVolume {
+            inner: Mutex::new(Inner(vol)),
+        }
+    }
+
+    fn into_volume(self) -> Arc<RefCell<Volume>> {
+        let inner = self
+            .inner
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        inner.0
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Must stay private: anything `f` returns escapes the lock.
+    fn with<T>(&self, f: impl FnOnce(&mut Volume) -> T) -> T {
+        let inner = self.lock();
+        let mut vol = inner.0.borrow_mut();
+        f(&mut vol)
+    }
+
+    // See `Volume::overlay`.
+    pub fn overlay(lower: SyncVolume) -> Result<SyncVolume, FsError> {
+        Volume::overlay(lower.into_volume()).map(SyncVolume::wrap)
+    }
+
+    // See `Volume::fork`. Fails with EINVAL for overlays, whose lower volume
+    // both would share.
+    pub fn fork(&self) -> Result<SyncVolume, FsError> {
+        self.with(|vol| match vol.lower() {
+            Some(_) => Err(create_error(
+                FSError::EINVAL,
+                Some(String::from("fork")),
+                None,
+            )),
+            None => Ok(SyncVolume::wrap(vol.fork())),
+        })
+    }
+
+    pub fn snapshot(&self) -> usize {
+        self.with(|vol| vol.snapshot())
+    }
+
+    pub fn restore(&self, id: usize) -> Result<(), FsError> {
+        self.with(|vol| vol.restore(id))
+    }
+
+    pub fn delete_snapshot(&self, id: usize) -> Result<(), FsError> {
+        self.with(|vol| vol.delete_snapshot(id))
+    }
+
+    // See `Volume::diff`. Both volumes are locked in the order of their
+    // addresses, so diffing two volumes both ways at once does not deadlock.
+    pub fn diff(&self, other: &SyncVolume) -> Result<Diff, FsError> {
+        if std::ptr::eq(self, other) {
+            return self.with(|vol| vol.diff(vol));
+        }
+        let (old, new) = match std::ptr::from_ref(self) < std::ptr::from_ref(other) {
+            true => {
+                let old = self.lock();
+                (old, other.lock())
+            }
+            false => {
+                let new = other.lock();
+                (self.lock(), new)
+            }
+        };
+        let diff = old.0.borrow().diff(&new.0.borrow());
+        diff
+    }
+
+    pub fn diff_snapshot(&self, id: usize) -> Result<Diff, FsError> {
+        self.with(|vol| vol.diff_snapshot(id))
+    }
+
+    pub fn apply_patch(&self, diff: &Diff) -> Result<(), FsError> {
+        self.with(|vol| vol.apply_patch(diff))
+    }
+
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        self.with(|vol| vol.to_snapshot())
+    }
+
+    pub fn from_snapshot(bytes: &[u8]) -> Result<SyncVolume, FsError> {
+        Volume::from_snapshot(bytes).map(SyncVolume::wrap)
+    }
+
+    pub fn mount_backend(
+        &self,
+        path: String,
+        backend: Box<dyn Backend + Send>,
+        options: Option<MountOptions>,
+    ) -> Result<usize, FsError> {
+        self.with(|vol| vol.mount_backend(path, backend, options))
+    }
+
+    pub fn mount(
+        &self,
+        path: String,
+        volume: SyncVolume,
+        options: Option<MountOptions>,
+    ) -> Result<usize, FsError> {
+        self.with(|vol| vol.mount(path, volume.into_volume(), options))
+    }
+
+    // See `Volume::umount`. The backend is dropped, it may not be `Send`.
+    pub fn umount(&self, path: String) -> Result<(), FsError> {
+        self.with(|vol| vol.umount(path).map(drop))
+    }
+
+    pub fn remount(&self, path: String, options: MountOptions) -> Result<(), FsError> {
+        self.with(|vol| vol.remount(path, options))
+    }
+
+    pub fn mounts(&self) -> Vec<MountInfo> {
+        self.with(|vol| vol.mounts())
+    }
+
+    pub fn set_rng(&self, rng: impl FnMut() -> f64 + Send + 'static) {
+        self.with(|vol| vol.set_rng(rng))
+    }
+
+    pub fn gen_rand_str(&self) -> String {
+        self.with(|vol| vol.gen_rand_str())
+    }
+
+    pub fn set_quota(&self, quota: Option<usize>) {
+        self.with(|vol| vol.set_quota(quota))
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.with(|vol| vol.used_bytes())
+    }
+
+    pub fn open_sync(
+        &self,
+        path: String,
+        flags: Option<String>,
+        mode: Option<usize>,
+    ) -> Result<usize, FsError> {
+        self.with(|vol| vol.open_sync(path, flags, mode))
+    }
+
+    pub fn close_sync(&self, fd: usize) -> Result<(), FsError> {
+        self.with(|vol| vol.close_sync(fd))
+    }
+
+    pub fn read_sync(
+        &self,
+        fd: usize,
+        buffer: &mut [u8],
+        offset: Option<usize>,
+        length: Option<usize>,
+        position: Option<usize>,
+    ) -> Result<usize, FsError> {
+        self.with(|vol| vol.read_sync(fd, buffer, offset, length, position))
+    }
+
+    pub fn write_sync(
+        &self,
+        fd: usize,
+        buffer: &[u8],
+        offset: Option<usize>,
+        length: Option<usize>,
+        position: Option<usize>,
+    ) -> Result<usize, FsError> {
+        self.with(|vol| vol.write_sync(fd, buffer, offset, length, position))
+    }
+
+    pub fn writev_sync(
+        &self,
+        fd: usize,
+        buffers: &[Vec<u8>],
+        position: Option<usize>,
+    ) -> Result<usize, FsError> {
+        self.with(|vol| vol.writev_sync(fd, buffers, position))
+    }
+
+    pub fn stat_sync(&self, path: String, bigint: Option<bool>) -> Result<Stats, FsError> {
+        self.with(|vol| vol.stat_sync(path, bigint))
+    }
+
+    pub fn lstat_sync(&self, path: String, bigint: Option<bool>) -> Result<Stats, FsError> {
+        self.with(|vol| vol.lstat_sync(path, bigint))
+    }
+
+    pub fn fstat_sync(&self, fd: usize, bigint: Option<bool>) -> Result<Stats, FsError> {
+        self.with(|vol| vol.fstat_sync(fd, bigint))
+    }
+
+    pub fn flock_sync(&self, fd: usize, lock: Option<FileLock>) -> Result<(), FsError> {
+        self.with(|vol| vol.flock_sync(fd, lock))
+    }
+
+    pub fn ftruncate_sync(&self, fd: usize, len: Option<usize>) -> Result<(), FsError> {
+        self.with(|vol| vol.ftruncate_sync(fd, len))
+    }
+
+    pub fn mkdir_sync(
+        &self,
+        path: String,
+        options: Option<MkdirOptions>,
+    ) -> Result<Option<String>, FsError> {
+        self.with(|vol| vol.mkdir_sync(path, options))
+    }
+
+    pub fn readdir_sync(&self, path: String) -> Result<Vec<String>, FsError> {
+        self.with(|vol| vol.readdir_sync(path))
+    }
+
+    pub fn readdir_with_file_types_sync(&self, path: String) -> Result<Vec<Dirent>, FsError> {
+        self.with(|vol| vol.readdir_with_file_types_sync(path))
+    }
+
+    pub fn unlink_sync(&self, path: String) -> Result<(), FsError> {
+        self.with(|vol| vol.unlink_sync(path))
+    }
+
+    pub fn rmdir_sync(&self, path: String, recursive: Option<bool>) -> Result<(), FsError> {
+        self.with(|vol| vol.rmdir_sync(path, recursive))
+    }
+
+    pub fn rm_sync(&self, path: String, options: Option<RmOptions>) -> Result<(), FsError> {
+        self.with(|vol| vol.rm_sync(path, options))
+    }
+
+    pub fn rename_sync(&self, old_path: String, new_path: String) -> Result<(), FsError> {
+        self.with(|vol| vol.rename_sync(old_path, new_path))
+    }
+
+    pub fn symlink_sync(&self, target: String, path: String) -> Result<(), FsError> {
+        self.with(|vol| vol.symlink_sync(target, path))
+    }
+
+    pub fn readlink_sync(&self, path: String) -> Result<String, FsError> {
+        self.with(|vol| vol.readlink_sync(path))
+    }
+
+    pub fn link_sync(&self, existing_path: String, new_path: String) -> Result<(), FsError> {
+        self.with(|vol| vol.link_sync(existing_path, new_path))
+    }
+
+    pub fn copy_file_sync(
+        &self,
+        src: String,
+        dest: String,
+        flags: Option<usize>,
+    ) -> Result<(), FsError> {
+        self.with(|vol| vol.copy_file_sync(src, dest, flags))
+    }
+
+    pub fn read_file_sync(&self, path: String) -> Result<Vec<u8>, FsError> {
+        self.with(|vol| vol.read_file_sync(path))
+    }
+
+    pub fn write_file_sync(
+        &self,
+        path: String,
+        data: &[u8],
+        options: Option<WriteFileOptions>,
+    ) -> Result<(), FsError> {
+        self.with(|vol| vol.write_file_sync(path, data, options))
+    }
+
+    pub fn exists_sync(&self, path: String) -> bool {
+        self.with(|vol| vol.exists_sync(path))
+    }
+
+    pub fn realpath_sync(&self, path: String) -> Result<String, FsError> {
+        self.with(|vol| vol.realpath_sync(path))
+    }
+
+    pub fn chmod_sync(&self, path: String, mode: usize) -> Result<(), FsError> {
+        self.with(|vol| vol.chmod_sync(path, mode))
+    }
+
+    pub fn chown_sync(&self, path: String, uid: usize, gid: usize) -> Result<(), FsError> {
+        self.with(|vol| vol.chown_sync(path, uid, gid))
+    }
+
+    pub fn utimes_sync(
+        &self,
+        path: String,
+        atime: SystemTime,
+        mtime: SystemTime,
+    ) -> Result<(), FsError> {
+        self.with(|vol| vol.utimes_sync(path, atime, mtime))
+    }
+
+    pub fn lutimes_sync(
+        &self,
+        path: String,
+        atime: SystemTime,
+        mtime: SystemTime,
+    ) -> Result<(), FsError> {
+        self.with(|vol| vol.lutimes_sync(path, atime, mtime))
+    }
+
+    pub fn futimes_sync(
+        &self,
+        fd: usize,
+        atime: SystemTime,
+        mtime: SystemTime,
+    ) -> Result<(), FsError> {
+        self.with(|vol| vol.futimes_sync(fd, atime, mtime))
+    }
+}
+
+#[test]
+fn test_sync_volume_from_many_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<SyncVolume>();
+
+    let vol = Arc::new(SyncVolume::new());
+    let log_fd = vol
+        .open_sync(String::from("/log"), Some(String::from("a")), None)
+        .unwrap();
+    let threads: Vec<_> = (0..8)
+        .map(|t| {
+            let vol = vol.clone();
+            std::thread::spawn(move || {
+                let dir = format!("/thread-{}/nested", t);
+                let options = MkdirOptions {
+                    recursive: Some(true),
+                    ..Default::default()
+                };
+                vol.mkdir_sync(dir.clone(), Some(options)).unwrap();
+                for i in 0..100 {
+                    let path = format!("{}/{}.txt", dir, i);
+                    let data = format!("{}:{}", t, i);
+                    vol.write_file_sync(path.clone(), data.as_bytes(), None)
+                        .unwrap();
+                    vol.rename_sync(path.clone(), format!("{}.done", path))
+                        .unwrap();
+                    vol.write_sync(log_fd, b"x", None, None, None).unwrap();
+                }
+                for i in 0..100 {
+                    let path = format!("{}/{}.txt.done", dir, i);
+                    let data = vol.read_file_sync(path).unwrap();
+                    assert_eq!(data, format!("{}:{}", t, i).into_bytes());
+                }
+                assert_eq!(vol.readdir_sync(dir).unwrap().len(), 100);
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(vol.fstat_sync(log_fd, None).unwrap().size, 800);
+    assert_eq!(vol.readdir_sync(String::from("/")).unwrap().len(), 9);
+    vol.rm_sync(
+        String::from("/thread-0"),
+        Some(RmOptions {
+            recursive: Some(true),
+            ..Default::default()
+        }),
+    )
+    .unwrap();
+    assert!(!vol.exists_sync(String::from("/thread-0/nested/0.txt.done")));
+}
+
+#[test]
+fn test_independent_sync_volumes_on_threads() {
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            std::thread::spawn(move || {
+                let vol = SyncVolume::new();
+                for i in 0..200 {
+                    let path = format!("/{}", i);
+                    vol.write_file_sync(path.clone(), &[t; 4], None).unwrap();
+                    assert_eq!(vol.read_file_sync(path).unwrap(), vec![t; 4]);
+                }
+                vol.used_bytes()
+            })
+        })
+        .collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), 800);
+    }
+}
+
+#[test]
+fn test_sync_volume_mounts_forks_and_snapshots() {
+    let vol = Arc::new(SyncVolume::new());
+    vol.mkdir_sync(String::from("/mnt"), None).unwrap();
+    let mounted = SyncVolume::new();
+    mounted
+        .write_file_sync(String::from("/readme"), b"hi", None)
+        .unwrap();
+    vol.mount(String::from("/mnt"), mounted, None).unwrap();
+    let before = vol.snapshot();
+
+    // Each thread patches the volume with the changes made on a fork.
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let vol = vol.clone();
+            std::thread::spawn(move || {
+                let fork = vol.fork().unwrap();
+                let base = fork.snapshot();
+                fork.write_file_sync(format!("/{}.txt", t), b"fork", None)
+                    .unwrap();
+                let diff = fork.diff_snapshot(base).unwrap();
+                assert!(!vol.diff(&fork).unwrap().is_empty());
+                assert!(!fork.diff(&vol).unwrap().is_empty());
+                assert!(fork.diff(&fork).unwrap().is_empty());
+                vol.apply_patch(&diff).unwrap();
+                vol.write_file_sync(format!("/mnt/{}.txt", t), b"mounted", None)
+                    .unwrap();
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(vol.readdir_sync(String::from("/")).unwrap().len(), 5);
+    assert_eq!(vol.readdir_sync(String::from("/mnt")).unwrap().len(), 5);
+    let diff = vol.diff_snapshot(before).unwrap();
+    let paths: Vec<&str> = diff.changes.iter().map(|change| change.path()).collect();
+    assert_eq!(paths, ["/", "/0.txt", "/1.txt", "/2.txt", "/3.txt"]);
+    assert_eq!(vol.mounts()[0].path, "/mnt");
+    let err = vol.restore(before).unwrap_err();
+    assert_eq!(err.code, FSError::EBUSY);
+    vol.umount(String::from("/mnt")).unwrap();
+    vol.restore(before).unwrap();
+    assert!(!vol.exists_sync(String::from("/0.txt")));
+
+    let copy = SyncVolume::from_snapshot(&vol.to_snapshot()).unwrap();
+    let overlay = SyncVolume::overlay(copy).unwrap();
+    assert!(overlay.exists_sync(String::from("/mnt")));
+    assert_eq!(overlay.fork().err().unwrap().code, FSError::EINVAL);
+}
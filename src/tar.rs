@@ -0,0 +1,458 @@
+// Streaming reader and writer for tar archives, see `Volume::import_tar` and
+// `Volume::export_tar`.
+//
+// Reads ustar and GNU headers along with PAX extended headers (`x` and `g`)
+// and GNU long names. Writes ustar, falling back to a PAX header for paths,
+// link targets and numbers that do not fit. Times are whole seconds unless a
+// PAX header says otherwise.
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const BLOCK_SIZE: usize = 512;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TarEntryKind {
+    File,
+    Directory,
+    Symlink(String),
+    // Another name for the file archived earlier at the given path.
+    HardLink(String),
+    // Devices, FIFOs and the like, identified by their type flag.
+    Other(u8),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TarEntry {
+    // Path as stored, without the trailing slash of directories.
+    pub path: String,
+    pub kind: TarEntryKind,
+    pub mode: usize,
+    pub uid: usize,
+    pub gid: usize,
+    pub mtime: SystemTime,
+    pub data: Vec<u8>,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Reads entries one at a time, keeping only the current entry's data in
+// memory.
+pub struct TarReader<R> {
+    reader: R,
+    // Attributes of `g` headers, applying to all following entries.
+    globals: HashMap<String, String>,
+    done: bool,
+}
+
+impl<R: Read> TarReader<R> {
+    pub fn new(reader: R) -> Self {
+        TarReader {
+            reader,
+            globals: HashMap::new(),
+            done: false,
+        }
+    }
+
+    // `None` once the end of the archive is reached.
+    pub fn next_entry(&mut self) -> io::Result<Option<TarEntry>> {
+        let mut attributes = self.globals.clone();
+        let mut long_path = None;
+        let mut long_target = None;
+        loop {
+            if self.done {
+                return Ok(None);
+            }
+            let mut header = [0; BLOCK_SIZE];
+            self.reader.read_exact(&mut header)?;
+            if header.iter().all(|byte| *byte == 0) {
+                self.done = true;
+                return Ok(None);
+            }
+            if checksum(&header) != parse_number(&header[148..156])? {
+                return Err(invalid_data("tar header checksum mismatch"));
+            }
+            let size = match attributes.get("size") {
+                Some(size) => size.parse().map_err(|_| invalid_data("bad PAX size"))?,
+                None => parse_number(&header[124..136])?,
+            };
+            let data = self.read_data(size)?;
+            let kind = match header[156] {
+                b'x' => {
+                    attributes.extend(parse_pax(&data)?);
+                    continue;
+                }
+                b'g' => {
+                    let globals = parse_pax(&data)?;
+                    self.globals.extend(globals.clone());
+                    attributes.extend(globals);
+                    continue;
+                }
+                b'L' => {
+                    long_path = Some(c_string(&data));
+                    continue;
+                }
+                b'K' => {
+                    long_target = Some(c_string(&data));
+                    continue;
+                }
+                b'0' | 0 | b'7' => TarEntryKind::File,
+                b'5' => TarEntryKind::Directory,
+                kind @ (b'1' | b'2') => {
+                    let target = attributes
+                        .remove("linkpath")
+                        .or(long_target.take())
+                        .unwrap_or_else(|| c_string(&header[157..257]));
+                    if kind == b'1' {
+                        TarEntryKind::HardLink(target)
+                    } else {
+                        TarEntryKind::Symlink(target)
+                    }
+                }
+                kind => TarEntryKind::Other(kind),
+            };
+
+            let mut path = match (attributes.remove("path"), long_path.take()) {
+                (Some(path), _) | (None, Some(path)) => path,
+                (None, None) => {
+                    let name = c_string(&header[0..100]);
+                    let prefix = if &header[257..263] == b"ustar\0" {
+                        c_string(&header[345..500])
+                    } else {
+                        String::new()
+                    };
+                    if prefix.is_empty() {
+                        name
+                    } else {
+                        format!("{}/{}", prefix, name)
+                    }
+                }
+            };
+            while path.len() > 1 && path.ends_with('/') {
+                path.pop();
+            }
+            let number = |key: &str, field: &[u8]| match attributes.get(key) {
+                Some(value) => value.parse().map_err(|_| invalid_data("bad PAX number")),
+                None => parse_number(field),
+            };
+            let mtime = match attributes.get("mtime") {
+                Some(mtime) => {
+                    let secs = mtime
+                        .parse()
+                        .ok()
+                        .and_then(|secs: f64| Duration::try_from_secs_f64(secs.max(0.0)).ok())
+                        .ok_or_else(|| invalid_data("bad PAX mtime"))?;
+                    UNIX_EPOCH + secs
+                }
+                None => UNIX_EPOCH + Duration::from_secs(parse_number(&header[136..148])? as u64),
+            };
+            return Ok(Some(TarEntry {
+                path,
+                kind,
+                mode: parse_number(&header[100..108])?,
+                uid: number("uid", &header[108..116])?,
+                gid: number("gid", &header[116..124])?,
+                mtime,
+                data,
+            }));
+        }
+    }
+
+    // Data of `size` bytes followed by padding up to the next block.
+    fn read_data(&mut self, size: usize) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        (&mut self.reader)
+            .take(size as u64)
+            .read_to_end(&mut data)?;
+        if data.len() < size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let padding = (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE;
+        self.reader.read_exact(&mut [0; BLOCK_SIZE][..padding])?;
+        Ok(data)
+    }
+}
+
+impl<R: Read> Iterator for TarReader<R> {
+    type Item = io::Result<TarEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+fn c_string(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+// Octal, or big-endian base-256 when the high bit of the first byte is set
+// (a GNU extension for large numbers).
+fn parse_number(field: &[u8]) -> io::Result<usize> {
+    if field[0] & 0x80 != 0 {
+        return field[1..]
+            .iter()
+            .try_fold(field[0] as usize & 0x7f, |value, byte| {
+                value
+                    .checked_mul(256)
+                    .map(|value| value | *byte as usize)
+                    .ok_or_else(|| invalid_data("tar number overflow"))
+            });
+    }
+    let digits = c_string(field);
+    let digits = digits.trim_matches(' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(digits, 8).map_err(|_| invalid_data("bad tar number"))
+}
+
+// Sum of all header bytes, with the checksum field counted as spaces.
+fn checksum(header: &[u8; BLOCK_SIZE]) -> usize {
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| if (148..156).contains(&i) { b' ' } else { *byte } as usize)
+        .sum()
+}
+
+// Records of the form "<length> <key>=<value>\n".
+fn parse_pax(data: &[u8]) -> io::Result<HashMap<String, String>> {
+    let mut attributes = HashMap::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest
+            .iter()
+            .position(|byte| *byte == b' ')
+            .ok_or_else(|| invalid_data("bad PAX record"))?;
+        let len: usize = std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|len| len.parse().ok())
+            .filter(|len| *len > space && *len <= rest.len())
+            .ok_or_else(|| invalid_data("bad PAX record"))?;
+        let record = std::str::from_utf8(&rest[space + 1..len])
+            .map_err(|_| invalid_data("bad PAX record"))?;
+        let (key, value) = record
+            .strip_suffix('\n')
+            .and_then(|record| record.split_once('='))
+            .ok_or_else(|| invalid_data("bad PAX record"))?;
+        attributes.insert(String::from(key), String::from(value));
+        rest = &rest[len..];
+    }
+    Ok(attributes)
+}
+
+fn pax_record(key: &str, value: &str) -> String {
+    // The length includes its own digits.
+    let base = key.len() + value.len() + 3;
+    let mut len = base + 1;
+    while len != base + len.to_string().len() {
+        len = base + len.to_string().len();
+    }
+    format!("{} {}={}\n", len, key, value)
+}
+
+pub struct TarWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(writer: W) -> Self {
+        TarWriter { writer }
+    }
+
+    // Writes `entry`, the data of anything but files is ignored.
+    pub fn append(&mut self, entry: &TarEntry) -> io::Result<()> {
+        let (type_flag, target, data): (u8, &str, &[u8]) = match &entry.kind {
+            TarEntryKind::File => (b'0', "", &entry.data),
+            TarEntryKind::Directory => (b'5', "", &[]),
+            TarEntryKind::Symlink(target) => (b'2', target, &[]),
+            TarEntryKind::HardLink(target) => (b'1', target, &[]),
+            TarEntryKind::Other(kind) => (*kind, "", &[]),
+        };
+        let mut path = entry.path.clone();
+        if type_flag == b'5' && !path.ends_with('/') {
+            path.push('/');
+        }
+        let mtime = entry
+            .mtime
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs() as usize;
+
+        let mut pax = String::new();
+        let (prefix, name) = split_path(&path).unwrap_or_else(|| {
+            pax.push_str(&pax_record("path", &path));
+            ("", truncate(&path, 100))
+        });
+        if target.len() > 100 {
+            pax.push_str(&pax_record("linkpath", target));
+        }
+        for (key, value, field_len) in [
+            ("uid", entry.uid, 8),
+            ("gid", entry.gid, 8),
+            ("size", data.len(), 12),
+            ("mtime", mtime, 12),
+        ] {
+            if !fits_octal(value, field_len) {
+                pax.push_str(&pax_record(key, &value.to_string()));
+            }
+        }
+        if !pax.is_empty() {
+            let pax_name = format!("PaxHeaders/{}", truncate(name, 89));
+            let header = ustar_header(&pax_name, "", b'x', entry, pax.len(), 0, "");
+            self.writer.write_all(&header)?;
+            self.write_data(pax.as_bytes())?;
+        }
+
+        let header = ustar_header(name, prefix, type_flag, entry, data.len(), mtime, target);
+        self.writer.write_all(&header)?;
+        self.write_data(data)
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> io::Result<()> {
+        self.writer.write_all(data)?;
+        let padding = (BLOCK_SIZE - data.len() % BLOCK_SIZE) % BLOCK_SIZE;
+        self.writer.write_all(&[0; BLOCK_SIZE][..padding])
+    }
+
+    // Writes the end-of-archive marker and hands back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&[0; 2 * BLOCK_SIZE])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// Longest prefix of `value` that fits `len` bytes, on a char boundary.
+fn truncate(value: &str, len: usize) -> &str {
+    let mut end = value.len().min(len);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    &value[..end]
+}
+
+// Splits `path` into the ustar prefix and name fields, if it fits them.
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= 100 {
+        return Some(("", path));
+    }
+    // A directory's trailing slash must stay with the name.
+    let search = &path[..path.len() - 1];
+    search
+        .char_indices()
+        .filter(|(i, c)| *c == '/' && *i <= 155 && path.len() - i - 1 <= 100)
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .next()
+}
+
+fn fits_octal(value: usize, field_len: usize) -> bool {
+    (value as u128) < 1u128 << (3 * (field_len - 1))
+}
+
+fn write_octal(field: &mut [u8], value: usize) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    // Numbers that do not fit are carried by a PAX header.
+    let digits = &digits.as_bytes()[digits.len() - (field.len() - 1)..];
+    field[..digits.len()].copy_from_slice(digits);
+}
+
+fn ustar_header(
+    name: &str,
+    prefix: &str,
+    type_flag: u8,
+    entry: &TarEntry,
+    size: usize,
+    mtime: usize,
+    target: &str,
+) -> [u8; BLOCK_SIZE] {
+    let mut header = [0; BLOCK_SIZE];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut header[100..108], entry.mode & 0o7777);
+    write_octal(&mut header[108..116], entry.uid);
+    write_octal(&mut header[116..124], entry.gid);
+    write_octal(&mut header[124..136], size);
+    write_octal(&mut header[136..148], mtime);
+    header[156] = type_flag;
+    let target = truncate(target, 100);
+    header[157..157 + target.len()].copy_from_slice(target.as_bytes());
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    let sum = checksum(&header);
+    write_octal(&mut header[148..155], sum);
+    header[155] = b' ';
+    header
+}
+
+// `site.tar` was written by GNU tar in PAX format: a directory with a hard
+// link, a relative symlink and a file below a path longer than 100 bytes.
+#[test]
+fn test_read_gnu_pax_archive() {
+    let archive = include_bytes!("../fixtures/site.tar");
+    let entries: Vec<TarEntry> = TarReader::new(&archive[..])
+        .collect::<io::Result<_>>()
+        .unwrap();
+    let deep = format!("site/{}/{}/file.txt", "d".repeat(60), "e".repeat(60));
+    let paths: Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(paths[0], "site");
+    assert!(paths.contains(&deep.as_str()));
+
+    let find = |path: &str| entries.iter().find(|entry| entry.path == path).unwrap();
+    let copy = find("site/copy.html");
+    assert_eq!((copy.mode, copy.uid, copy.gid), (0o640, 1000, 100));
+    assert_eq!(copy.mtime, UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    assert_eq!(copy.data, b"hello\n");
+    assert_eq!(find("site").kind, TarEntryKind::Directory);
+    assert_eq!(
+        find("site/index.html").kind,
+        TarEntryKind::HardLink(String::from("site/copy.html"))
+    );
+    assert_eq!(
+        find("site/link").kind,
+        TarEntryKind::Symlink(String::from("index.html"))
+    );
+}
+
+#[test]
+fn test_write_long_paths_and_numbers() {
+    let entry = TarEntry {
+        path: format!("{}/file", "x".repeat(300)),
+        kind: TarEntryKind::File,
+        mode: 0o644,
+        uid: 1 << 30,
+        gid: 0,
+        mtime: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        data: vec![7; 1000],
+    };
+    let link = TarEntry {
+        path: format!("{}/link", "y".repeat(120)),
+        kind: TarEntryKind::Symlink(format!("/{}", "z".repeat(200))),
+        data: Vec::new(),
+        ..entry.clone()
+    };
+    let mut writer = TarWriter::new(Vec::new());
+    writer.append(&entry).unwrap();
+    writer.append(&link).unwrap();
+    let archive = writer.finish().unwrap();
+    assert_eq!(archive.len() % BLOCK_SIZE, 0);
+
+    let mut reader = TarReader::new(&archive[..]);
+    assert_eq!(reader.next_entry().unwrap(), Some(entry));
+    assert_eq!(reader.next_entry().unwrap(), Some(link));
+    assert_eq!(reader.next_entry().unwrap(), None);
+
+    let mut corrupt = archive.clone();
+    corrupt[0] ^= 1;
+    let err = TarReader::new(&corrupt[..]).next_entry().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(TarReader::new(&archive[..700]).nth(1).unwrap().is_err());
+}
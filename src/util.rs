@@ -1,7 +1,18 @@
+#[cfg(feature = "wasm")]
+use crate::json;
+use crate::{
+    constants::constants,
+    error::{create_error, FSError, FsError},
+};
+#[cfg(feature = "wasm")]
 use js_sys::{Object, Reflect};
 use std::env;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::JsValue;
 
+// Extended entries (see `json::parse_entry`) are kept as they are, other
+// objects are directories.
+#[cfg(feature = "wasm")]
 pub fn flatten_json(nested_json: Object) -> Object {
     let mut flat_json = Object::new();
 
@@ -11,15 +22,15 @@ pub fn flatten_json(nested_json: Object) -> Object {
             let key = key.as_string().unwrap();
             let content_or_node = Reflect::get(&node, &key.clone().into()).unwrap();
             let joined_path = path_join(vec![path_prefix.clone(), key.clone()]);
-            if content_or_node.is_string() {
-                Reflect::set(&flat_json, &joined_path.into(), &content_or_node).unwrap();
+            if content_or_node.is_string() || json::is_entry_object(&content_or_node) {
+                Reflect::set(flat_json, &joined_path.into(), &content_or_node).unwrap();
             } else if content_or_node.is_object() && !content_or_node.is_null() {
                 let content_or_node = Object::from(content_or_node);
                 if Object::keys(&content_or_node).length() > 0 {
                     flatten(joined_path, content_or_node, flat_json);
                 }
             } else {
-                Reflect::set(&flat_json, &joined_path.into(), &JsValue::null()).unwrap();
+                Reflect::set(flat_json, &joined_path.into(), &JsValue::null()).unwrap();
             }
         }
     }
@@ -29,6 +40,46 @@ pub fn flatten_json(nested_json: Object) -> Object {
     flat_json
 }
 
+// Converts Node's string open flags (`"r"`, `"w+"`, `"ax"`, ...) to their numeric form.
+pub fn flags_to_number(flags: String) -> Result<usize, FsError> {
+    let flags_num = match flags.as_str() {
+        "r" => constants::O_RDONLY,
+        "r+" => constants::O_RDWR,
+        "rs" | "sr" => constants::O_RDONLY | constants::O_SYNC,
+        "rs+" | "sr+" => constants::O_RDWR | constants::O_SYNC,
+        "w" => constants::O_TRUNC | constants::O_CREAT | constants::O_WRONLY,
+        "wx" | "xw" => {
+            constants::O_TRUNC | constants::O_CREAT | constants::O_WRONLY | constants::O_EXCL
+        }
+        "w+" => constants::O_TRUNC | constants::O_CREAT | constants::O_RDWR,
+        "wx+" | "xw+" => {
+            constants::O_TRUNC | constants::O_CREAT | constants::O_RDWR | constants::O_EXCL
+        }
+        "a" => constants::O_APPEND | constants::O_CREAT | constants::O_WRONLY,
+        "ax" | "xa" => {
+            constants::O_APPEND | constants::O_CREAT | constants::O_WRONLY | constants::O_EXCL
+        }
+        "as" | "sa" => {
+            constants::O_APPEND | constants::O_CREAT | constants::O_WRONLY | constants::O_SYNC
+        }
+        "a+" => constants::O_APPEND | constants::O_CREAT | constants::O_RDWR,
+        "ax+" | "xa+" => {
+            constants::O_APPEND | constants::O_CREAT | constants::O_RDWR | constants::O_EXCL
+        }
+        "as+" | "sa+" => {
+            constants::O_APPEND | constants::O_CREAT | constants::O_RDWR | constants::O_SYNC
+        }
+        _ => {
+            return Err(create_error(
+                FSError::EINVAL,
+                Some(String::from("open")),
+                Some(vec![flags]),
+            ))
+        }
+    };
+    Ok(flags_num)
+}
+
 pub fn path_join(paths: Vec<String>) -> String {
     let mut path = String::new();
     for part in paths {
@@ -40,11 +91,11 @@ pub fn path_join(paths: Vec<String>) -> String {
 pub fn path_relative(from: String, to: String, cwd: Option<String>) -> String {
     let cwd = cwd.unwrap_or(process_cwd());
     let to = path_resolve(
-        vec![if to == "" { cwd.clone() } else { to }],
+        vec![if to.is_empty() { cwd.clone() } else { to }],
         Some(cwd.clone()),
     );
     let from = path_resolve(
-        vec![if from == "" { cwd.clone() } else { from }],
+        vec![if from.is_empty() { cwd.clone() } else { from }],
         Some(cwd.clone()),
     );
     if from == to {
@@ -53,15 +104,10 @@ pub fn path_relative(from: String, to: String, cwd: Option<String>) -> String {
     let from = filename_to_steps(from, Some(cwd.clone()));
     let to = filename_to_steps(to, Some(cwd.clone()));
     let mut length = 0;
-    let mut same = true;
-    for i in 0..from.len() {
-        if from[i] != to[i] {
-            same = false;
-            break;
-        }
+    while length < from.len() && length < to.len() && from[length] == to[length] {
         length += 1;
     }
-    if same {
+    if length == from.len() {
         return to[length..].join("/");
     }
     let mut up = from.len() - length;
@@ -77,7 +123,7 @@ pub fn path_relative(from: String, to: String, cwd: Option<String>) -> String {
 pub fn filename_to_steps(filename: String, base: Option<String>) -> Vec<String> {
     let full_path = path_resolve(vec![filename], base);
     let full_path_sans_slash = full_path.trim_start_matches("/");
-    if full_path_sans_slash == "" {
+    if full_path_sans_slash.is_empty() {
         return vec![];
     }
     full_path_sans_slash
@@ -89,7 +135,7 @@ pub fn filename_to_steps(filename: String, base: Option<String>) -> Vec<String>
 pub fn path_resolve(paths: Vec<String>, cwd: Option<String>) -> String {
     let cwd = cwd.unwrap_or(process_cwd());
     assert!(cwd.starts_with("/"), "cwd must be absolute");
-    if paths.len() == 0 {
+    if paths.is_empty() {
         return path_normalize(cwd);
     }
     let mut path = String::new();
@@ -112,7 +158,7 @@ pub fn path_normalize(path: String) -> String {
         return path;
     }
     for step in path.split("/") {
-        if step == "." || step == "" {
+        if step == "." || step.is_empty() {
             continue;
         }
         if step == ".." {
@@ -121,19 +167,19 @@ pub fn path_normalize(path: String) -> String {
         }
         normalized_path.push(step);
     }
-    let mut normalized_path = normalized_path.join("/");
-    if normalized_path == "" {
-        normalized_path = String::from("/");
-    }
+    let normalized_path = normalized_path.join("/");
     if path.starts_with("/") {
-        normalized_path = format!("/{}", normalized_path);
+        return format!("/{}", normalized_path);
+    }
+    if normalized_path.is_empty() {
+        return String::from(".");
     }
     normalized_path
 }
 
 pub fn process_cwd() -> String {
     let mut cwd = env::var("CWD").unwrap_or(env::var("PWD").unwrap_or(String::from("")));
-    if cwd == "" {
+    if cwd.is_empty() {
         eprintln!("current working directory not set, falling back to '/'.");
         eprintln!("set either CWD or PWD environment variables.");
         cwd = String::from("/");
@@ -151,6 +197,15 @@ fn test_path_relative() {
         ),
         String::from("../../impl/bbb")
     );
+
+    assert_eq!(
+        path_relative(
+            String::from("/foo/bar"),
+            String::from("/foo"),
+            Some(String::from("/"))
+        ),
+        String::from("..")
+    );
 }
 
 #[test]
@@ -195,4 +250,9 @@ fn test_path_resolve() {
         ),
         String::from("/qux/foo/baz")
     );
+
+    assert_eq!(
+        path_resolve(vec![String::from("/foo/..")], None),
+        String::from("/")
+    );
 }
@@ -1,37 +1,137 @@
 #![allow(dead_code, unused)] // todo: remove this
 
+#[cfg(feature = "zip")]
+use crate::zip::{ZipArchive, ZipEntry, ZipEntryKind, ZipOptions, ZipWriter};
 use crate::{
-    file::{self, File},
+    arena::{Arena, ArenaId, InodeId, LinkId},
+    backend::Backend,
+    constants::constants,
+    diff::{self, Change, Diff, DiffEntry, DiffEntryKind},
+    dirent::Dirent,
+    error::{create_error, FSError, FsError},
+    events::{EventEmitter, ListenerId},
+    file::{self, File, FileLock},
+    handle::FileHandle,
+    json::{self, DirectoryJson, JsonEntry, JsonEntryKind},
     link::Link,
-    node::Node,
+    node::{LazyBuffer, Node},
+    platform::{self, Rng},
+    snapshot::{Snapshot, SnapshotLink},
+    stats::Stats,
+    stream::{ReadStream, ReadStreamOptions, WriteStream, WriteStreamOptions},
+    tar::{TarEntry, TarEntryKind, TarReader, TarWriter},
     util,
+    watcher::{
+        FSWatcher, FSWatcherOptions, StatListener, StatWatcher, StatWatcherOptions, WatchListener,
+    },
 };
 use radix_fmt::radix_36;
 use std::{
-    cell::{Ref, RefCell},
-    collections::HashMap,
-    sync::Arc,
+    borrow::Cow,
+    cell::{Ref, RefCell, RefMut},
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{Read, SeekFrom, Write},
+    sync::{Arc, Weak},
+    time::SystemTime,
 };
 
-static mut FD_COUNTER: usize = 0x7fffffff;
-static mut INO_COUNTER: usize = 0;
+// Number of symlinks followed while resolving one path before giving up with
+// ELOOP, the same limit Linux uses.
+const MAX_SYMLINK_DEPTH: usize = 40;
+// Longest accepted file name, like `NAME_MAX`.
+const MAX_NAME_LENGTH: usize = 255;
+// Largest size a file can grow to.
+const MAX_FILE_SIZE: usize = isize::MAX as usize;
+// Device number of the entries an overlay shows from its lower volume, so
+// that their I-node numbers do not collide with its own. Mounts are numbered
+// from 1 up.
+const LOWER_DEV: usize = u32::MAX as usize;
+// I-node numbers a snapshot of `n` nodes may use are below
+// `SNAPSHOT_INO_FACTOR * n + SNAPSHOT_INO_SLACK`, leaving room for the slots
+// freed before it was taken while bounding what restoring it allocates.
+const SNAPSHOT_INO_FACTOR: usize = 4;
+const SNAPSHOT_INO_SLACK: usize = 65536;
+
+#[derive(Clone, Default)]
+pub struct MkdirOptions {
+    // https://nodejs.org/api/fs.html#fsmkdirsyncpath-options
+    pub recursive: Option<bool>,
+    pub mode: Option<usize>,
+}
+
+#[derive(Clone, Default)]
+pub struct RmOptions {
+    // https://nodejs.org/api/fs.html#fsrmsyncpath-options
+    pub force: Option<bool>,
+    pub recursive: Option<bool>,
+}
+
+#[derive(Clone, Default)]
+pub struct WriteFileOptions {
+    // https://nodejs.org/api/fs.html#fswritefilesyncfile-data-options
+    pub flag: Option<String>,
+    pub mode: Option<usize>,
+}
+
+#[derive(Clone, Default)]
+pub struct MountOptions {
+    // Modifications fail with EROFS.
+    pub read_only: Option<bool>,
+    // Reads leave access times alone, by default only when read-only. Only
+    // applies to the volume itself, mounted volumes and backends have options
+    // of their own.
+    pub noatime: Option<bool>,
+}
+
+// An entry of the tree below some link, see `Volume::tree_entries`.
+#[derive(Clone)]
+pub struct TreeEntry {
+    pub path: String,
+    pub ino: InodeId,
+    pub is_directory: bool,
+}
+
+pub enum VolumeEvent {
+    // Contents or metadata of a node were modified.
+    Change {
+        ino: InodeId,
+    },
+    // `name` was added to the directory at `dir`. `entries` lists the new
+    // entry followed by everything below it.
+    ChildAdd {
+        dir: String,
+        name: String,
+        entries: Vec<TreeEntry>,
+    },
+    // `name` was removed from the directory at `dir`.
+    ChildDelete {
+        dir: String,
+        name: String,
+    },
+}
+
+// First file descriptor handed out, counting down from here like memfs.
+const FIRST_FD: usize = 0x7fffffff;
 
 pub struct Volume {
-    // this where every allocated Link is stored
-    storage: HashMap<usize, Arc<RefCell<Link>>>,
+    // Shared handle of this volume, handed to streams, handles and watchers.
+    this: Weak<RefCell<Volume>>,
 
-    // Hard link to the root of this volume.
-    root: Option<Arc<RefCell<Link>>>,
+    // All I-nodes, their ids double as I-node numbers and are reused once
+    // freed.
+    nodes: Arena<InodeId, Node>,
 
-    // A map of I-node numbers to I-nodes.
-    inodes: HashMap<usize, Arc<RefCell<Node>>>,
+    // All hard links, directories refer to their entries by id.
+    links: Arena<LinkId, Link>,
 
-    // A list of reusable I-node numbers that should be
-    // used first before creating a new file descriptor.
-    released_inos: Vec<usize>,
+    // Hard link to the root of this volume.
+    root: LinkId,
+
+    // Next file descriptor to allocate, once `released_fds` is exhausted.
+    fd_counter: usize,
 
     // A map of file descriptors to files.
-    fds: HashMap<usize, Arc<RefCell<File>>>,
+    fds: HashMap<usize, File>,
 
     // A list of reusable (opened and closed) file descriptors, that should be
     // used first before creating a new file descriptor.
@@ -42,411 +142,5072 @@ pub struct Volume {
 
     // Current number of open files.
     open_files: usize,
-    // todo
-    // StatWatcher: new () => StatWatcher;
-    // ReadStream: new (...args) => IReadStream;
-    // WriteStream: new (...args) => IWriteStream;
-    // FSWatcher: new () => FSWatcher;
+
+    // A map of filenames to their `watchFile` watchers.
+    stat_watchers: HashMap<String, StatWatcher>,
+
+    // Changes to the tree, consumed by `FSWatcher`s.
+    events: EventEmitter<VolumeEvent>,
+
+    // Source of randomness for generated names, see `set_rng`.
+    rng: Rng,
+
+    // Max number of bytes all files may occupy together, `None` if unlimited.
+    quota: Option<usize>,
+
+    // Bytes occupied by the contents of all files, see `resized`. Copies
+    // held by detached files are not counted.
+    used: usize,
+
+    // Backends mounted on directories, see `mount_backend`.
+    mounts: HashMap<LinkId, Mount>,
+
+    // Device number of the next mount, the volume itself is device 0.
+    next_dev: usize,
+
+    // The read-only volume below this one if it is an overlay, see `overlay`.
+    lower: Option<Arc<RefCell<Volume>>>,
+
+    // Modifications fail with EROFS, see `remount`.
+    read_only: bool,
+
+    // Reads do not update access times.
+    noatime: bool,
+
+    // Trees saved with `snapshot`, by id.
+    snapshots: HashMap<usize, Tree>,
+
+    // Id of the next snapshot.
+    next_snapshot: usize,
+}
+
+// What `Volume::set_metadata` gives an entry, fields left `None` are kept.
+#[derive(Default)]
+struct EntryMetadata {
+    mode: Option<usize>,
+    uid: Option<usize>,
+    gid: Option<usize>,
+    mtime: Option<SystemTime>,
+}
+
+// Nodes and links of a volume, sharing unchanged ones with it, see `fork`.
+struct Tree {
+    nodes: Arena<InodeId, Node>,
+    links: Arena<LinkId, Link>,
+    root: LinkId,
+    used: usize,
+}
+
+// A backend mounted on a directory.
+struct Mount {
+    backend: Box<dyn Backend>,
+    // Reported as `dev` in the stats of its entries.
+    dev: usize,
+    read_only: bool,
+}
+
+// An entry of `Volume::mounts`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MountInfo {
+    pub path: String,
+    pub dev: usize,
+    pub read_only: bool,
+}
+
+// Where a path leads, see `Volume::resolve_steps`.
+enum Lookup {
+    Found(LinkId),
+    Missing,
+    // The rest of the path is up to the backend mounted at the link.
+    Mounted(LinkId, String),
+    // Only the lower volume of an overlay has the entry, `link` is its link
+    // there. The path left this volume at the entry `name` of `dir`, which is
+    // `source` in the lower volume.
+    Lower {
+        link: LinkId,
+        dir: LinkId,
+        name: String,
+        source: LinkId,
+    },
 }
 
 impl Default for Volume {
     fn default() -> Self {
-        Volume {
-            storage: HashMap::new(),
-            root: None,
-            inodes: HashMap::new(),
-            released_inos: Vec::new(),
+        let mut volume = Volume {
+            this: Weak::new(),
+            nodes: Arena::new(),
+            links: Arena::new(),
+            root: LinkId::from_index(0),
+            fd_counter: FIRST_FD,
             fds: HashMap::new(),
             released_fds: Vec::new(),
             max_files: 10000,
             open_files: 0,
-        }
+            stat_watchers: HashMap::new(),
+            events: EventEmitter::new(),
+            rng: platform::default_rng(),
+            quota: None,
+            used: 0,
+            mounts: HashMap::new(),
+            next_dev: 1,
+            lower: None,
+            read_only: false,
+            noatime: false,
+            snapshots: HashMap::new(),
+            next_snapshot: 1,
+        };
+        volume.root = volume.create_root();
+        volume
     }
 }
 
 impl Volume {
     pub fn new() -> Arc<RefCell<Volume>> {
-        let volume = Arc::new(RefCell::new(Volume::default()));
-        let link = Link::new(volume.clone(), None, "".to_string());
-        let node = volume.borrow_mut().create_node(Some(true), None);
-        link.borrow_mut().set_node(node.clone());
-        link.borrow_mut().get_node().borrow_mut().inc_nlink();
-        link.borrow_mut()
-            .set_child(".".to_string(), Some(link.clone()));
-        link.borrow_mut().get_node().borrow_mut().inc_nlink();
-        link.borrow_mut()
-            .set_child("..".to_string(), Some(link.clone()));
-        link.borrow_mut().get_node().borrow_mut().inc_nlink();
-        volume.borrow_mut().root = Some(link);
-        volume
-        // todo: Links should register themselves in volume's storage instead of their own static map
+        Arc::new_cyclic(|this| {
+            RefCell::new(Volume {
+                this: this.clone(),
+                ..Volume::default()
+            })
+        })
     }
 
-    pub fn create_link(
-        &mut self,
-        parent: Arc<RefCell<Link>>,
-        name: String,
-        is_directory: Option<bool>,
-        perm: Option<usize>,
-    ) -> Arc<RefCell<Link>> {
-        parent
-            .borrow_mut()
-            .create_child(name, Some(self.create_node(is_directory, perm)))
+    // Stacks a new, empty volume on top of `lower`, which shows through until
+    // its entries are modified: writes copy entries up to the new volume
+    // first, removals hide them behind whiteouts and directory listings merge
+    // both. `lower` is remounted read-only, as changes made to it would show
+    // through, and must not be borrowed mutably while the overlay is in use.
+    // It can not be an overlay itself (EINVAL). Entries shown from `lower`
+    // keep their I-node numbers on a device of their own.
+    pub fn overlay(lower: Arc<RefCell<Volume>>) -> Result<Arc<RefCell<Volume>>, FsError> {
+        if lower.borrow().lower.is_some() {
+            return Err(create_error(
+                FSError::EINVAL,
+                Some(String::from("overlay")),
+                None,
+            ));
+        }
+        lower.borrow_mut().read_only = true;
+        let volume = Volume::new();
+        volume.borrow_mut().lower = Some(lower);
+        Ok(volume)
     }
 
-    pub fn delete_link(&mut self, link: Arc<RefCell<Link>>) -> bool {
-        if let Some(parent) = link.borrow_mut().get_parent() {
-            parent.borrow_mut().delete_child(link.clone());
-            return true;
+    // The volume below this one if it is an overlay.
+    pub fn lower(&self) -> Option<Arc<RefCell<Volume>>> {
+        self.lower.clone()
+    }
+
+    // An independent copy of this volume. Both share all nodes, contents and
+    // directories until either side changes them, so a fork costs memory in
+    // proportion to the changes made afterwards. Open files, watchers,
+    // mounts and snapshots stay with this volume; the lower volume of an
+    // overlay is shared.
+    pub fn fork(&self) -> Arc<RefCell<Volume>> {
+        Arc::new_cyclic(|this| {
+            RefCell::new(Volume {
+                this: this.clone(),
+                nodes: self.nodes.clone(),
+                links: self.links.clone(),
+                root: self.root,
+                quota: self.quota,
+                used: self.used,
+                lower: self.lower.clone(),
+                read_only: self.read_only,
+                noatime: self.noatime,
+                ..Volume::default()
+            })
+        })
+    }
+
+    // Saves the current tree like `fork` does and returns an id to `restore`
+    // it by. Snapshots are kept until deleted with `delete_snapshot`.
+    pub fn snapshot(&mut self) -> usize {
+        let id = self.next_snapshot;
+        self.next_snapshot += 1;
+        self.snapshots.insert(
+            id,
+            Tree {
+                nodes: self.nodes.clone(),
+                links: self.links.clone(),
+                root: self.root,
+                used: self.used,
+            },
+        );
+        id
+    }
+
+    // Brings back the tree saved as snapshot `id`, dropping all changes made
+    // since. Fails with EINVAL for unknown ids, with EROFS if the volume is
+    // read-only and with EBUSY while files are open or anything is mounted,
+    // as those refer to the current tree.
+    pub fn restore(&mut self, id: usize) -> Result<(), FsError> {
+        let func_name = Some(String::from("restore"));
+        let Some(tree) = self.snapshots.get(&id) else {
+            return Err(create_error(FSError::EINVAL, func_name, None));
+        };
+        if self.read_only {
+            return Err(create_error(FSError::EROFS, func_name, None));
         }
-        false
+        if !self.fds.is_empty() || !self.mounts.is_empty() {
+            return Err(create_error(FSError::EBUSY, func_name, None));
+        }
+        self.nodes = tree.nodes.clone();
+        self.links = tree.links.clone();
+        self.root = tree.root;
+        self.used = tree.used;
+        Ok(())
     }
 
-    fn new_ino_number(&mut self) -> usize {
-        if let Some(ino) = self.released_inos.pop() {
-            return ino;
-        } else {
-            unsafe {
-                let my_ino = INO_COUNTER;
-                INO_COUNTER = (INO_COUNTER + 1) % usize::MAX;
-                return my_ino;
-            }
+    // Frees snapshot `id`, failing with EINVAL if there is none.
+    pub fn delete_snapshot(&mut self, id: usize) -> Result<(), FsError> {
+        match self.snapshots.remove(&id) {
+            Some(_) => Ok(()),
+            None => Err(create_error(
+                FSError::EINVAL,
+                Some(String::from("delete_snapshot")),
+                None,
+            )),
         }
     }
 
-    fn new_fd_number(&mut self) -> usize {
-        if let Some(fd) = self.released_fds.pop() {
-            return fd;
-        } else {
-            unsafe {
-                let my_fd = FD_COUNTER;
-                FD_COUNTER = FD_COUNTER - 1;
-                return FD_COUNTER;
+    // Changes turning this volume into `other`, see the `diff` module. Only
+    // the volumes' own entries are compared, not those of mounted backends
+    // or the lower volume of an overlay. Access and change times are
+    // ignored.
+    pub fn diff(&self, other: &Volume) -> Result<Diff, FsError> {
+        let old = self.diff_paths();
+        let new = other.diff_paths();
+
+        // Nodes that left their paths, by the paths they left.
+        let mut gone: HashMap<(usize, DiffEntryKind), Vec<&String>> = HashMap::new();
+        for (path, ino) in &old {
+            if !new.contains_key(path) {
+                let node = &self.nodes[*ino];
+                let key = (node.generation, diff_entry_kind(node));
+                gone.entry(key).or_default().push(path);
             }
         }
-    }
 
-    pub fn create_node(
-        &mut self,
-        is_directory: Option<bool>,
-        perm: Option<usize>,
-    ) -> Arc<RefCell<Node>> {
-        let is_directory = is_directory.unwrap_or(false);
-        let ino_number = self.new_ino_number();
-        let mut node = Node::new(ino_number, perm);
-        if is_directory {
-            node.set_is_directory();
-            self.inodes.insert(node.ino, Arc::new(RefCell::new(node)));
+        let mut changes = Vec::new();
+        let mut renamed = HashMap::new();
+        for (path, ino) in &new {
+            let node = &other.nodes[*ino];
+            let kind = diff_entry_kind(node);
+            if let Some(old_ino) = old.get(path) {
+                let from = diff_entry_kind(&self.nodes[*old_ino]);
+                if from != kind {
+                    let entry = other.diff_entry(*ino)?;
+                    changes.push(Change::TypeChanged {
+                        path: path.clone(),
+                        from,
+                        entry,
+                    });
+                } else {
+                    changes.extend(self.diff_modified(*old_ino, path, other, *ino, path)?);
+                }
+                continue;
+            }
+            let from = gone
+                .get_mut(&(node.generation, kind))
+                .filter(|paths| !paths.is_empty())
+                .map(|paths| paths.remove(0));
+            let Some(from) = from else {
+                let entry = other.diff_entry(*ino)?;
+                changes.push(Change::Added {
+                    path: path.clone(),
+                    entry,
+                });
+                continue;
+            };
+            // Entries moved along with their directory are left implied.
+            let (from_dir, from_name) = split_path(from);
+            let (to_dir, to_name) = split_path(path);
+            if from_name != to_name || renamed.get(&from_dir) != Some(&to_dir) {
+                changes.push(Change::Renamed {
+                    from: from.clone(),
+                    to: path.clone(),
+                });
+            }
+            renamed.insert(from.clone(), path.clone());
+            changes.extend(self.diff_modified(old[from], from, other, *ino, path)?);
+        }
+        for (path, ino) in &old {
+            if !new.contains_key(path) && !renamed.contains_key(path) {
+                changes.push(Change::Removed {
+                    path: path.clone(),
+                    kind: diff_entry_kind(&self.nodes[*ino]),
+                });
+            }
         }
-        self.inodes.get(&ino_number).unwrap().clone()
+        changes.sort_by(|a, b| a.path().cmp(b.path()));
+        Ok(Diff { changes })
     }
 
-    fn get_node(&mut self, ino: usize) -> Option<Arc<RefCell<Node>>> {
-        self.inodes.get(&ino).map(|node| node.clone())
+    // Changes made since snapshot `id`, failing with EINVAL for unknown ids.
+    pub fn diff_snapshot(&self, id: usize) -> Result<Diff, FsError> {
+        let Some(tree) = self.snapshots.get(&id) else {
+            return Err(create_error(
+                FSError::EINVAL,
+                Some(String::from("diff_snapshot")),
+                None,
+            ));
+        };
+        let saved = Volume {
+            nodes: tree.nodes.clone(),
+            links: tree.links.clone(),
+            root: tree.root,
+            ..Volume::default()
+        };
+        saved.diff(self)
     }
 
-    fn delete_node(&mut self, node: Arc<RefCell<Node>>) {
-        node.borrow_mut().del();
-        self.inodes.remove(&node.borrow().ino);
-        self.released_inos.push(node.borrow().ino);
-    }
+    // Replays `diff` onto this volume, which need not be the volume it was
+    // made from: entries are created or replaced as the diff has them after
+    // the change, whatever they are now. Renames come first, then additions
+    // and modifications, then removals and last the metadata, which the
+    // others would touch again. Stops at the first error.
+    pub fn apply_patch(&mut self, diff: &Diff) -> Result<(), FsError> {
+        let func_name = Some(String::from("apply_patch"));
+        let recursive = MkdirOptions {
+            recursive: Some(true),
+            ..Default::default()
+        };
+        // Paths renamed so far, to find entries that moved along with them.
+        let mut moved: Vec<(&String, &String)> = Vec::new();
+        let current = |moved: &[(&String, &String)], path: &String| {
+            for (from, to) in moved.iter().rev() {
+                if is_inside(from, path) {
+                    return format!("{}{}", to, &path[from.len()..]);
+                }
+            }
+            path.clone()
+        };
 
-    // Generates 6 character long random string, used by `mkdtemp`.
-    pub fn gen_rand_str(&self) -> String {
-        let rand = format!("{}", radix_36((js_sys::Math::random() + 1.0) as usize));
-        if rand.len() < 8 {
-            return self.gen_rand_str();
-        } else {
-            return rand[2..8].to_string();
+        for change in &diff.changes {
+            if let Change::Renamed { from, to } = change {
+                let (to_dir, _) = split_path(to);
+                self.mkdir_sync(to_dir, Some(recursive.clone()))?;
+                self.rename_sync(current(&moved, from), to.clone())?;
+                moved.push((from, to));
+            }
+        }
+        let mut metadata = Vec::new();
+        for change in &diff.changes {
+            match change {
+                Change::Added { path, entry }
+                | Change::TypeChanged { path, entry, .. }
+                | Change::Modified {
+                    path,
+                    entry,
+                    content: true,
+                    ..
+                } => {
+                    self.put_diff_entry(path, entry, func_name.clone())?;
+                    metadata.push((path, entry));
+                }
+                Change::Modified { path, entry, .. } => metadata.push((path, entry)),
+                Change::Removed { .. } | Change::Renamed { .. } => {}
+            }
+        }
+        let force = RmOptions {
+            force: Some(true),
+            recursive: Some(true),
+        };
+        for change in diff.changes.iter().rev() {
+            if let Change::Removed { path, .. } = change {
+                self.rm_sync(current(&moved, path), Some(force.clone()))?;
+            }
         }
+        for (path, entry) in metadata {
+            let metadata = EntryMetadata {
+                mode: Some(entry.mode),
+                uid: Some(entry.uid),
+                gid: Some(entry.gid),
+                mtime: Some(entry.mtime),
+            };
+            self.set_metadata(path.clone(), metadata, func_name.clone())?;
+        }
+        Ok(())
     }
 
-    // Returns a `Link` (hard link) referenced by path "split" into steps.
-    pub fn get_link(&mut self, steps: Vec<String>) -> Option<Arc<RefCell<Link>>> {
-        self.root.as_ref()?.borrow_mut().walk(steps, None, None)
+    // Paths of all entries below the root, with their nodes.
+    fn diff_paths(&self) -> BTreeMap<String, InodeId> {
+        self.tree_entries(self.root, None)
+            .into_iter()
+            .map(|entry| (entry.path, entry.ino))
+            .collect()
     }
 
-    pub fn get_link_or_throw(
-        &mut self,
-        filename: String,
-        func_name: Option<String>,
-    ) -> Result<Arc<RefCell<Link>>, js_sys::Error> {
-        let steps = util::filename_to_steps(filename.clone(), None);
-        let link = self.get_link(steps.clone());
-        if link.is_none() {
-            return Err(crate::error::create_error(
-                crate::error::FSError::ENOENT,
-                func_name,
-                Some(vec![filename]),
-            ));
+    fn diff_entry(&self, ino: InodeId) -> Result<DiffEntry, FsError> {
+        let node = &self.nodes[ino];
+        let kind = diff_entry_kind(node);
+        let data = match kind {
+            DiffEntryKind::File => node.contents()?.into_owned(),
+            DiffEntryKind::Directory => Vec::new(),
+            DiffEntryKind::Symlink => node.target.clone().into_bytes(),
+        };
+        Ok(DiffEntry {
+            kind,
+            ino: ino.index(),
+            mode: node.mode & 0o7777,
+            uid: node.get_uid(),
+            gid: node.get_gid(),
+            mtime: node.get_mtime(),
+            data,
+        })
+    }
+
+    // A `Modified` change if the node `ino` at `path` of this volume differs
+    // from the same kind of node `other_ino` at `other_path` of `other`.
+    fn diff_modified(
+        &self,
+        ino: InodeId,
+        path: &str,
+        other: &Volume,
+        other_ino: InodeId,
+        other_path: &str,
+    ) -> Result<Option<Change>, FsError> {
+        let (a, b) = (&self.nodes[ino], &other.nodes[other_ino]);
+        let content = match diff_entry_kind(a) {
+            DiffEntryKind::File => !a.shares_buffer(b) && a.contents()? != b.contents()?,
+            DiffEntryKind::Directory => false,
+            DiffEntryKind::Symlink => a.target != b.target,
+        };
+        let metadata = (a.mode & 0o7777, a.get_uid(), a.get_gid(), a.get_mtime())
+            != (b.mode & 0o7777, b.get_uid(), b.get_gid(), b.get_mtime());
+        if !content && !metadata {
+            return Ok(None);
         }
-        Ok(link.unwrap())
+        let entry = other.diff_entry(other_ino)?;
+        let text = match (entry.kind, content) {
+            (DiffEntryKind::File, true) => {
+                let old = a.contents()?;
+                match (diff::as_text(&old), diff::as_text(&entry.data)) {
+                    (Some(old), Some(new)) => Some(diff::unified_diff(old, new, path, other_path)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        Ok(Some(Change::Modified {
+            path: String::from(other_path),
+            entry,
+            content,
+            metadata,
+            text,
+        }))
     }
 
-    // Just like `getLink`, but also dereference/resolves symbolic links.
-    pub fn get_resolved_link(&mut self, filename: String) -> Option<Arc<RefCell<Link>>> {
-        let steps = util::filename_to_steps(filename.clone(), None);
-        let mut link = self.root.clone();
-        let mut i = 0;
-        while i < steps.len() {
-            let step = steps[i].clone();
-            link = link?.borrow_mut().get_child(step.clone());
-            if link.is_none() {
-                return None;
+    // Makes `path` the entry `entry` describes, replacing entries of other
+    // kinds and symlinks.
+    fn put_diff_entry(
+        &mut self,
+        path: &str,
+        entry: &DiffEntry,
+        func_name: Option<String>,
+    ) -> Result<(), FsError> {
+        let path = String::from(path);
+        let existing = match self.lstat_sync(path.clone(), None) {
+            Ok(stats) if stats.is_directory() => Some(DiffEntryKind::Directory),
+            Ok(stats) if stats.is_symbolic_link() => Some(DiffEntryKind::Symlink),
+            Ok(_) => Some(DiffEntryKind::File),
+            Err(err) if err.code == FSError::ENOENT => None,
+            Err(err) => return Err(err),
+        };
+        if existing.is_some_and(|kind| kind != entry.kind || kind == DiffEntryKind::Symlink) {
+            let force = RmOptions {
+                force: Some(true),
+                recursive: Some(true),
+            };
+            self.rm_sync(path.clone(), Some(force))?;
+        }
+        match entry.kind {
+            DiffEntryKind::File => self.write_file_sync(path, &entry.data, None),
+            DiffEntryKind::Directory => {
+                let recursive = MkdirOptions {
+                    recursive: Some(true),
+                    ..Default::default()
+                };
+                self.mkdir_sync(path, Some(recursive)).map(|_| ())
             }
-            let node = link.clone()?.borrow_mut().get_node();
-            if node.borrow().is_symlink() {
-                let steps = node.borrow().symlink.concat();
-                link = self.root.clone();
-                i = 0;
-                continue;
+            DiffEntryKind::Symlink => {
+                let target = String::from_utf8(entry.data.clone()).map_err(|_| {
+                    create_error(FSError::EINVAL, func_name, Some(vec![path.clone()]))
+                })?;
+                self.symlink_sync(target, path)
             }
-            i += 1;
         }
-        link
     }
 
-    // Just like `getLinkOrThrow`, but also dereference/resolves symbolic links.
-    pub fn get_resolved_link_or_throw(
+    // Gives the entry `path`, not following a symlink there, the mode, owner
+    // and mtime of `metadata`, failing with EROFS if it is read-only. The
+    // mode of symlinks is left alone, mounted backends only take the mode.
+    fn set_metadata(
         &mut self,
-        filename: String,
+        path: String,
+        metadata: EntryMetadata,
         func_name: Option<String>,
-    ) -> Result<Arc<RefCell<Link>>, js_sys::Error> {
-        let link = self.get_resolved_link(filename.clone());
-        if link.is_none() {
-            return Err(crate::error::create_error(
-                crate::error::FSError::ENOENT,
-                func_name,
-                Some(vec![filename]),
-            ));
+    ) -> Result<(), FsError> {
+        if self
+            .find_writable_mount(&path, false, func_name.clone())?
+            .is_some()
+        {
+            let stats = self.lstat_sync(path.clone(), None)?;
+            if let Some(mode) = metadata.mode.filter(|_| !stats.is_symbolic_link()) {
+                self.chmod_sync(path, mode)?;
+            }
+            return Ok(());
+        }
+        self.copy_up(&path, false, func_name.clone())?;
+        let link = self.get_link_or_throw(path, func_name)?;
+        let ino = self.links[link].node;
+        let node = &mut self.nodes[ino];
+        if let Some(mode) = metadata.mode.filter(|_| !node.is_symlink()) {
+            node.chmod(mode & 0o777);
+        }
+        if let Some(uid) = metadata.uid {
+            node.set_uid(uid);
         }
-        Ok(link.unwrap())
+        if let Some(gid) = metadata.gid {
+            node.set_gid(gid);
+        }
+        if let Some(mtime) = metadata.mtime {
+            node.set_mtime(mtime);
+        }
+        self.node_changed(ino);
+        Ok(())
     }
 
-    pub fn resolve_symlinks(&mut self, link: Arc<RefCell<Link>>) -> Option<Arc<RefCell<Link>>> {
-        let path = link.borrow_mut().get_steps()[1..].to_vec().join("/");
-        self.get_resolved_link(path)
+    fn create_root(&mut self) -> LinkId {
+        let node = self.create_node(Some(true), Some(0o777));
+        self.nodes[node].inc_nlink();
+        self.links.insert(Link::new(None, String::new(), node))
     }
 
-    // Just like `getLinkOrThrow`, but also verifies that the link is a directory.
-    fn get_link_as_dir_or_throw(
-        &mut self,
-        filename: String,
-        func_name: Option<String>,
-    ) -> Result<Arc<RefCell<Link>>, js_sys::Error> {
-        let link = self.get_link_or_throw(filename.clone(), func_name.clone())?;
-        if !link.borrow_mut().get_node().borrow().is_directory() {
-            return Err(crate::error::create_error(
-                crate::error::FSError::ENOTDIR,
-                func_name,
-                Some(vec![filename]),
-            ));
-        }
-        Ok(link)
+    pub fn root(&self) -> LinkId {
+        self.root
     }
 
-    // Get the immediate parent directory of the link.
-    fn get_link_parent(&mut self, steps: Vec<String>) -> Option<Arc<RefCell<Link>>> {
-        let limit = steps.len() - 1;
-        self.root
-            .as_ref()?
-            .borrow_mut()
-            .walk(steps, Some(limit), None)
+    pub fn link(&self, link: LinkId) -> &Link {
+        &self.links[link]
     }
 
-    fn get_link_parent_as_dir_or_throw(
-        &mut self,
-        filename: String,
-        func_name: Option<String>,
-    ) -> Result<Arc<RefCell<Link>>, js_sys::Error> {
-        let steps = util::filename_to_steps(filename.clone(), None);
-        let link = self.get_link_parent(steps.clone());
-        if link.is_none() {
-            return Err(crate::error::create_error(
-                crate::error::FSError::ENOENT,
-                func_name,
-                Some(vec![filename]),
-            ));
-        }
-        if !link
-            .clone()
-            .unwrap()
-            .borrow_mut()
-            .get_node()
-            .borrow()
-            .is_directory()
-        {
-            return Err(crate::error::create_error(
-                crate::error::FSError::ENOTDIR,
-                func_name,
-                Some(vec![filename]),
-            ));
+    pub fn node(&self, node: InodeId) -> &Node {
+        &self.nodes[node]
+    }
+
+    pub fn node_mut(&mut self, node: InodeId) -> &mut Node {
+        &mut self.nodes[node]
+    }
+
+    // Node the hard link `link` points to.
+    pub fn link_node(&self, link: LinkId) -> &Node {
+        &self.nodes[self.links[link].node]
+    }
+
+    // Path to `link` as steps: ['usr', 'bin', 'node'], empty for the root.
+    pub fn get_steps(&self, link: LinkId) -> Vec<String> {
+        let mut steps = Vec::new();
+        let mut link = &self.links[link];
+        while let Some(parent) = link.parent {
+            steps.push(link.name.clone());
+            link = &self.links[parent];
         }
-        Ok(link.unwrap())
+        steps.reverse();
+        steps
     }
 
-    fn get_file_by_fd(&mut self, fd: usize) -> Option<Arc<RefCell<File>>> {
-        self.fds.get(&fd).map(|file| file.clone())
+    pub fn get_path(&self, link: LinkId) -> String {
+        format!("/{}", self.get_steps(link).join("/"))
     }
 
-    fn get_file_by_fd_or_throw(
+    pub fn create_link(
         &mut self,
-        fd: usize,
-        func_name: Option<String>,
-    ) -> Result<Arc<RefCell<File>>, js_sys::Error> {
-        let file = self.get_file_by_fd(fd);
-        if file.is_none() {
-            return Err(crate::error::create_error(
-                crate::error::FSError::EBADF,
-                func_name,
-                None,
-            ));
+        parent: LinkId,
+        name: String,
+        is_directory: Option<bool>,
+        perm: Option<usize>,
+    ) -> LinkId {
+        let node = self.create_node(is_directory, perm);
+        if self.nodes[node].is_directory() {
+            // The directory's own "." entry.
+            self.nodes[node].inc_nlink();
         }
-        Ok(file.unwrap())
+        let link = self.links.insert(Link::new(None, name.clone(), node));
+        self.attach_link(parent, name, link);
+        link
     }
 
-    // todo: fromJSON / toJSON api
+    // Removes `link` from its parent and frees it.
+    pub fn delete_link(&mut self, link: LinkId) -> bool {
+        if !self.detach_link(link) {
+            return false;
+        }
+        self.links.remove(link);
+        true
+    }
 
-    fn _to_json(
-        &mut self,
-        link: Option<Arc<RefCell<Link>>>,
-        json: Option<js_sys::Object>,
-        path: Option<String>,
-    ) -> js_sys::Object {
-        let mut json = json.unwrap_or(js_sys::Object::new());
-        let mut is_empty = true;
-        if let Some(root) = self.root.clone() {
-            let mut link = link.unwrap_or(root);
-            let mut children = link.borrow_mut().children.clone();
-
-            if link.borrow_mut().get_node().borrow_mut().is_file() {
-                children = HashMap::new();
-                let k = link.borrow_mut().get_name();
-                let v = link
-                    .borrow_mut()
-                    .get_parent()
-                    .unwrap()
-                    .borrow_mut()
-                    .get_child(k.clone())
-                    .unwrap();
-                children.insert(k, v);
-                link = link.clone().borrow_mut().get_parent().unwrap();
-            }
-
-            for (name, _) in children {
-                if name == "." || name == ".." {
-                    continue;
-                }
-                is_empty = false;
+    // Adds the detached `link` to the directory `dir` as `name`.
+    fn attach_link(&mut self, dir: LinkId, name: String, link: LinkId) {
+        // A directory taking the place of a removed one hides the lower
+        // volume's directory of the same path.
+        if self.links[dir].whiteouts.remove(&name) && self.link_node(link).is_directory() {
+            self.links[link].opaque = true;
+        }
+        let child = &mut self.links[link];
+        child.parent = Some(dir);
+        child.name = name.clone();
+        let is_directory = self.nodes[child.node].is_directory();
+        let dir_link = &mut self.links[dir];
+        dir_link.children.insert(name.clone(), link);
+        let dir_node = &mut self.nodes[dir_link.node];
+        if is_directory {
+            // The child's ".." entry.
+            dir_node.inc_nlink();
+        }
+        dir_node.set_mtime(platform::now());
+        if self.events.listener_count() > 0 {
+            let event = VolumeEvent::ChildAdd {
+                dir: self.get_path(dir),
+                name,
+                entries: self.tree_entries(link, None),
+            };
+            self.events.emit(&event);
+        }
+    }
 
-                let child = match link.clone().borrow_mut().get_child(name.clone()) {
-                    Some(child) => child,
-                    None => panic!("_toJSON: unexpected undefined"),
-                };
+    // Removes `link` from its parent without freeing it, returns `false` for
+    // the root.
+    fn detach_link(&mut self, link: LinkId) -> bool {
+        let child = &mut self.links[link];
+        let dir = match child.parent.take() {
+            Some(dir) => dir,
+            None => return false,
+        };
+        let name = child.name.clone();
+        let is_directory = self.nodes[child.node].is_directory();
+        let dir_link = &mut self.links[dir];
+        dir_link.children.remove(&name);
+        let dir_node = &mut self.nodes[dir_link.node];
+        if is_directory {
+            dir_node.dec_nlink();
+        }
+        dir_node.set_mtime(platform::now());
+        if self.lower_child(dir, &name).is_some() {
+            self.links[dir].whiteouts.insert(name.clone());
+        }
+        if self.events.listener_count() > 0 {
+            let event = VolumeEvent::ChildDelete {
+                dir: self.get_path(dir),
+                name,
+            };
+            self.events.emit(&event);
+        }
+        true
+    }
 
-                let node = child.clone().borrow_mut().get_node();
-                if node.borrow_mut().is_file() {
-                    let mut filename = child.clone().borrow_mut().get_path();
-                    if let Some(path) = path.clone() {
-                        filename = util::path_relative(path, filename, None);
-                        let v = node.borrow_mut().get_string();
-                        js_sys::Reflect::set(&mut json, &filename.into(), &v.into()).unwrap();
-                    }
-                } else if node.borrow_mut().is_directory() {
-                    json = self._to_json(Some(child), Some(json), path.clone());
+    // `link` followed by everything below it, down to `max_depth` levels.
+    pub(crate) fn tree_entries(&self, link: LinkId, max_depth: Option<usize>) -> Vec<TreeEntry> {
+        let mut entries = Vec::new();
+        let mut pending = vec![(link, self.get_path(link), 0)];
+        while let Some((link, path, depth)) = pending.pop() {
+            let link = &self.links[link];
+            let is_directory = self.nodes[link.node].is_directory();
+            if is_directory && max_depth.is_none_or(|max_depth| depth < max_depth) {
+                for (name, child) in link.children.iter().rev() {
+                    let child_path = util::path_join(vec![path.clone(), name.clone()]);
+                    pending.push((*child, child_path, depth + 1));
                 }
             }
+            entries.push(TreeEntry {
+                path,
+                ino: link.node,
+                is_directory,
+            });
+        }
+        entries
+    }
 
-            let mut dir_path = link.clone().borrow_mut().get_path();
-            if let Some(path) = path.clone() {
-                dir_path = util::path_relative(path, dir_path, None);
-            }
-            if dir_path != "." && is_empty {
-                js_sys::Reflect::set(&mut json, &dir_path.into(), &wasm_bindgen::JsValue::null())
-                    .unwrap();
-            }
+    // Listeners run synchronously from within the operation that caused the
+    // event, while the volume is borrowed.
+    pub fn subscribe(&mut self, listener: impl FnMut(&VolumeEvent) + 'static) -> ListenerId {
+        self.events.on(listener)
+    }
+
+    pub fn unsubscribe(&mut self, id: ListenerId) -> bool {
+        self.events.off(id)
+    }
+
+    // Notifies watchers that the contents or metadata of `node` changed.
+    fn node_changed(&mut self, node: InodeId) {
+        self.events.emit(&VolumeEvent::Change { ino: node });
+    }
+
+    // Hands the directory at `path` over to `backend`: lookups reaching it
+    // continue in the backend, whatever the directory holds stays hidden.
+    // Entries below it can not be moved or linked elsewhere (EXDEV) and are
+    // not watched. Writes to open files reach the backend right away, see
+    // `reload_backing`. Returns the device number of the mount.
+    pub fn mount_backend(
+        &mut self,
+        path: String,
+        backend: Box<dyn Backend>,
+        options: Option<MountOptions>,
+    ) -> Result<usize, FsError> {
+        let func_name = Some(String::from("mount"));
+        let link = self.get_resolved_link_or_throw(path.clone(), func_name.clone())?;
+        if !self.link_node(link).is_directory() {
+            return Err(create_error(FSError::ENOTDIR, func_name, Some(vec![path])));
         }
-        json
+        if self.mounts.contains_key(&link) {
+            return Err(create_error(FSError::EBUSY, func_name, Some(vec![path])));
+        }
+        let dev = self.next_dev;
+        self.next_dev += 1;
+        let read_only = options.unwrap_or_default().read_only.unwrap_or(false);
+        let mount = Mount {
+            backend,
+            dev,
+            read_only,
+        };
+        self.mounts.insert(link, mount);
+        Ok(dev)
     }
 
-    pub fn to_json(
+    // Mounts `volume` at `path`, see `mount_backend`. The mounted volume
+    // resolves absolute symlinks of its own against its own root.
+    pub fn mount(
         &mut self,
-        paths: Option<Vec<String>>,
-        json: Option<js_sys::Object>,
-        is_relative: Option<bool>,
-    ) -> js_sys::Object {
-        let mut links: Vec<Arc<RefCell<Link>>> = vec![];
+        path: String,
+        volume: Arc<RefCell<Volume>>,
+        options: Option<MountOptions>,
+    ) -> Result<usize, FsError> {
+        if Weak::ptr_eq(&self.this, &Arc::downgrade(&volume)) {
+            return Err(create_error(
+                FSError::EINVAL,
+                Some(String::from("mount")),
+                Some(vec![path]),
+            ));
+        }
+        self.mount_backend(path, Box::new(volume), options)
+    }
 
-        if let Some(paths) = paths {
-            for path in paths {
-                let filename = path.clone();
-                let link = self.get_resolved_link(filename);
-                if link.is_none() {
-                    continue;
-                }
-                links.push(link.unwrap());
-            }
-        } else {
-            links.push(self.root.clone().unwrap());
+    // Detaches the backend mounted at `path` and hands it back, uncovering
+    // the directory's own entries. Fails with EBUSY while files of the mount
+    // are open.
+    pub fn umount(&mut self, path: String) -> Result<Box<dyn Backend>, FsError> {
+        let func_name = Some(String::from("umount"));
+        let link = self.get_resolved_link_or_throw(path.clone(), func_name.clone())?;
+        if !self.mounts.contains_key(&link) {
+            return Err(create_error(FSError::EINVAL, func_name, Some(vec![path])));
         }
+        let is_busy = self.fds.values().any(|file| {
+            file.backing
+                .as_ref()
+                .is_some_and(|(mount, _)| *mount == link)
+        });
+        if is_busy {
+            return Err(create_error(FSError::EBUSY, func_name, Some(vec![path])));
+        }
+        Ok(self.mounts.remove(&link).unwrap().backend)
+    }
 
-        let mut json = json.unwrap_or(js_sys::Object::new());
+    // Changes the options of the mount at `path`, or of the volume itself for
+    // "/". Files already open for writing fail with EROFS once read-only.
+    pub fn remount(&mut self, path: String, options: MountOptions) -> Result<(), FsError> {
+        let func_name = Some(String::from("remount"));
+        let link = self.get_resolved_link_or_throw(path.clone(), func_name.clone())?;
+        let read_only = options.read_only.unwrap_or(false);
+        if let Some(mount) = self.mounts.get_mut(&link) {
+            mount.read_only = read_only;
+            return Ok(());
+        }
+        if link != self.root {
+            return Err(create_error(FSError::EINVAL, func_name, Some(vec![path])));
+        }
+        self.read_only = read_only;
+        self.noatime = options.noatime.unwrap_or(read_only);
+        Ok(())
+    }
 
-        if links.is_empty() {
-            return json;
+    // Like `find_mount`, but fails with EROFS if the entry is read-only.
+    fn find_writable_mount(
+        &self,
+        filename: &str,
+        follow_last: bool,
+        func_name: Option<String>,
+    ) -> Result<Option<(LinkId, String)>, FsError> {
+        let mount = self.find_mount(filename, follow_last, func_name.clone())?;
+        let read_only = match &mount {
+            Some((mount, _)) => self.mounts[mount].read_only,
+            None => self.read_only,
+        };
+        if read_only {
+            return Err(create_error(
+                FSError::EROFS,
+                func_name,
+                Some(vec![String::from(filename)]),
+            ));
         }
+        Ok(mount)
+    }
 
-        for link in links {
-            let is_relative = is_relative.unwrap_or(false);
-            json = self._to_json(
-                Some(link.clone()),
-                Some(json),
-                if is_relative {
-                    Some(link.borrow_mut().get_path())
-                } else {
-                    None
-                },
-            );
+    // Updates the access time of `node` after a read, unless mounted with
+    // `noatime`.
+    fn accessed(&mut self, node: InodeId) {
+        if !self.noatime {
+            self.nodes[node].set_atime(platform::now());
         }
+    }
 
-        json
+    // Active mounts, sorted by path.
+    pub fn mounts(&self) -> Vec<MountInfo> {
+        let mut mounts: Vec<MountInfo> = self
+            .mounts
+            .iter()
+            .map(|(link, mount)| MountInfo {
+                path: self.get_path(*link),
+                dev: mount.dev,
+                read_only: mount.read_only,
+            })
+            .collect();
+        mounts.sort_by(|a, b| a.path.cmp(&b.path));
+        mounts
     }
 
-    pub fn from_json(&mut self, json: js_sys::Object, cwd: Option<String>) {
-        let cwd = cwd.unwrap_or_else(|| crate::util::process_cwd());
-        for filename in js_sys::Object::keys(&json).iter() {
-            let filename = filename.as_string().unwrap();
-            let data = js_sys::Reflect::get(&json, &filename.clone().into()).unwrap();
-            let filename = util::path_resolve(vec![filename, cwd.clone()], None);
-            if data.is_string() {
-                // todo
-                // let dir = util::path_dirname(filename.clone(), None);
-                // self.mkdirp_base(dir, MODE::DIR);
-                // self.write_file_sync(filename.clone(), data.as_string().unwrap());
-            } else {
-                // todo
-                // self.mkdirp_base(filename.clone(), MODE::DIR);
+    // Runs `op` on the backend mounted at `mount`, reporting its errors with
+    // `paths` as seen through this volume.
+    fn with_backend<T>(
+        &mut self,
+        mount: LinkId,
+        func_name: &str,
+        paths: Vec<String>,
+        op: impl FnOnce(&mut dyn Backend) -> Result<T, FsError>,
+    ) -> Result<T, FsError> {
+        let backend = &mut self.mounts.get_mut(&mount).unwrap().backend;
+        op(backend.as_mut())
+            .map_err(|err| create_error(err.code, Some(String::from(func_name)), Some(paths)))
+    }
+
+    // Whether something is mounted at `link` or below it.
+    fn contains_mount(&self, link: LinkId) -> bool {
+        self.mounts.keys().any(|mount| {
+            let mut ancestor = Some(*mount);
+            while let Some(current) = ancestor {
+                if current == link {
+                    return true;
+                }
+                ancestor = self.links[current].get_parent();
             }
+            false
+        })
+    }
+
+    fn new_fd_number(&mut self) -> usize {
+        if let Some(fd) = self.released_fds.pop() {
+            fd
+        } else {
+            let fd = self.fd_counter;
+            self.fd_counter -= 1;
+            fd
+        }
+    }
+
+    pub fn create_node(&mut self, is_directory: Option<bool>, perm: Option<usize>) -> InodeId {
+        let is_directory = is_directory.unwrap_or(false);
+        let ino = self.nodes.next_id();
+        let mut node = Node::new(ino.index(), perm);
+        if is_directory {
+            node.set_is_directory();
         }
+        self.nodes.insert(node)
     }
 
-    pub fn from_nested_json(&mut self, json: js_sys::Object, cwd: Option<String>) {
-        self.from_json(util::flatten_json(json), cwd);
+    fn get_node(&self, ino: usize) -> Option<&Node> {
+        self.nodes.get(InodeId::from_index(ino))
     }
 
-    pub fn reset(&mut self) {
-        let def = Volume::default();
-        self.fds.clear();
-        // self.root.clear(); // todo
-        self.inodes.clear();
-        self.open_files = def.open_files;
-        self.released_fds.clear();
-        self.released_inos.clear();
-        self.max_files = def.max_files;
-        self.storage.clear();
+    // Frees `node` once no link points to it and no file has it open.
+    fn release_node(&mut self, node: InodeId) {
+        if self.nodes[node].get_nlink() > 0 || self.fds.values().any(|file| file.node == node) {
+            return;
+        }
+        self.used -= self.nodes[node].get_size();
+        self.nodes.remove(node);
     }
 
-    pub fn mount_sync(&mut self, mount_point: String, json: js_sys::Object) {
-        self.from_json(json, Some(mount_point));
+    // Accounts for `node` now occupying its size instead of `size` bytes.
+    fn resized(&mut self, node: InodeId, size: usize) {
+        self.used = self.used + self.nodes[node].get_size() - size;
     }
+
+    // Replaces the random number generator, e.g. with a seeded one to get
+    // reproducible `mkdtemp` names. `rng` must return numbers in `[0, 1)`.
+    pub fn set_rng(&mut self, rng: impl FnMut() -> f64 + 'static) {
+        self.rng = Box::new(rng);
+    }
+
+    // Generates 6 character long random string, used by `mkdtemp`.
+    pub fn gen_rand_str(&mut self) -> String {
+        let rand = ((self.rng)() * 36f64.powi(6)) as u64;
+        format!("{:0>6}", radix_36(rand).to_string())
+    }
+
+    // Walks `steps` down from `link` without following symlinks.
+    fn walk(&self, mut link: LinkId, steps: &[String]) -> Option<LinkId> {
+        for step in steps {
+            link = self.links[link].get_child(step)?;
+        }
+        Some(link)
+    }
+
+    // Returns a `Link` (hard link) referenced by path "split" into steps.
+    pub fn get_link(&self, steps: Vec<String>) -> Option<LinkId> {
+        self.walk(self.root, &steps)
+    }
+
+    pub fn get_link_or_throw(
+        &self,
+        filename: String,
+        func_name: Option<String>,
+    ) -> Result<LinkId, FsError> {
+        let steps = util::filename_to_steps(filename.clone(), None);
+        match self.get_link(steps) {
+            Some(link) => Ok(link),
+            None => Err(create_error(
+                FSError::ENOENT,
+                func_name,
+                Some(vec![filename]),
+            )),
+        }
+    }
+
+    // Just like `getLink`, but also dereference/resolves symbolic links.
+    pub fn get_resolved_link(&self, filename: String) -> Option<LinkId> {
+        let steps = util::filename_to_steps(filename, None);
+        self.get_resolved_link_by_steps(steps, None).ok().flatten()
+    }
+
+    // Fails with ELOOP when more than `MAX_SYMLINK_DEPTH` symlinks have to be
+    // followed, e.g. because they form a cycle, and with ENOTSUP for paths
+    // below a mounted backend, which only some operations support.
+    fn get_resolved_link_by_steps(
+        &self,
+        steps: Vec<String>,
+        func_name: Option<String>,
+    ) -> Result<Option<LinkId>, FsError> {
+        let path = format!("/{}", steps.join("/"));
+        match self.resolve_steps(steps, func_name.clone())? {
+            Lookup::Found(link) => Ok(Some(link)),
+            Lookup::Missing => Ok(None),
+            Lookup::Mounted(link, rest) if rest == "/" => Ok(Some(link)),
+            Lookup::Mounted(..) => Err(create_error(FSError::ENOTSUP, func_name, Some(vec![path]))),
+            // Operations copy such entries up or read them from the lower
+            // volume before getting here.
+            Lookup::Lower { .. } => Ok(None),
+        }
+    }
+
+    // Follows `steps` from the root, symlinks included, until they run out or
+    // reach a mounted backend. Overlays continue in the lower volume where
+    // this one has no entry.
+    fn resolve_steps(
+        &self,
+        mut steps: Vec<String>,
+        func_name: Option<String>,
+    ) -> Result<Lookup, FsError> {
+        let path = format!("/{}", steps.join("/"));
+        let lower = self.lower.as_ref().map(|lower| lower.borrow());
+        let mut link = Some(self.root);
+        // Counterpart of `link` in the lower volume, and where the path left
+        // this volume once `link` is `None`.
+        let mut below = lower.as_ref().map(|lower| lower.root);
+        let mut left = None;
+        let mut depth = 0;
+        let mut i = 0;
+        loop {
+            if let Some(link) = link.filter(|link| self.mounts.contains_key(link)) {
+                let rest = format!("/{}", steps[i..].join("/"));
+                return Ok(Lookup::Mounted(link, rest));
+            }
+            if i == steps.len() {
+                return Ok(match (link, left) {
+                    (Some(link), _) => Lookup::Found(link),
+                    (None, Some((dir, name, source))) => Lookup::Lower {
+                        link: below.unwrap(),
+                        dir,
+                        name,
+                        source,
+                    },
+                    (None, None) => Lookup::Missing,
+                });
+            }
+            let name = &steps[i];
+            let next = link.and_then(|dir| self.links[dir].get_child(name));
+            let hidden = link.is_some_and(|dir| self.links[dir].whiteouts.contains(name))
+                || next.is_some_and(|next| {
+                    self.links[next].opaque || !self.link_node(next).is_directory()
+                });
+            // Only directories of both volumes merge.
+            let next_below = match (&lower, below) {
+                (Some(lower), Some(dir)) if !hidden => lower.links[dir]
+                    .get_child(name)
+                    .filter(|child| next.is_none() || lower.link_node(*child).is_directory()),
+                _ => None,
+            };
+            let node = match (next, next_below) {
+                (Some(next), _) => self.link_node(next),
+                (None, Some(child)) => {
+                    if let Some(dir) = link {
+                        left = Some((dir, name.clone(), child));
+                    }
+                    lower.as_ref().unwrap().link_node(child)
+                }
+                (None, None) => return Ok(Lookup::Missing),
+            };
+            if node.is_symlink() {
+                depth += 1;
+                if depth > MAX_SYMLINK_DEPTH {
+                    return Err(create_error(FSError::ELOOP, func_name, Some(vec![path])));
+                }
+                let mut target = node.symlink.clone();
+                target.extend_from_slice(&steps[i + 1..]);
+                steps = target;
+                link = Some(self.root);
+                below = lower.as_ref().map(|lower| lower.root);
+                left = None;
+                i = 0;
+                continue;
+            }
+            link = next;
+            below = next_below;
+            i += 1;
+        }
+    }
+
+    // Like `resolve_steps`, but a symlink at `filename` itself is only
+    // followed with `follow_last`.
+    fn lookup(
+        &self,
+        filename: &str,
+        follow_last: bool,
+        func_name: Option<String>,
+    ) -> Result<Lookup, FsError> {
+        let mut steps = util::filename_to_steps(String::from(filename), None);
+        let last = match follow_last {
+            true => None,
+            false => steps.pop(),
+        };
+        let lookup = self.resolve_steps(steps, func_name)?;
+        let name = match last {
+            Some(name) => name,
+            None => return Ok(lookup),
+        };
+        Ok(match lookup {
+            Lookup::Found(dir) => match self.links[dir].get_child(&name) {
+                Some(link) => Lookup::Found(link),
+                None => match self.lower_child(dir, &name) {
+                    Some(source) => Lookup::Lower {
+                        link: source,
+                        dir,
+                        name,
+                        source,
+                    },
+                    None => Lookup::Missing,
+                },
+            },
+            Lookup::Lower {
+                link,
+                dir,
+                name: left,
+                source,
+            } => match self.lower_volume().links[link].get_child(&name) {
+                Some(link) => Lookup::Lower {
+                    link,
+                    dir,
+                    name: left,
+                    source,
+                },
+                None => Lookup::Missing,
+            },
+            Lookup::Mounted(mount, path) => {
+                Lookup::Mounted(mount, util::path_join(vec![path, name]))
+            }
+            Lookup::Missing => Lookup::Missing,
+        })
+    }
+
+    fn lower_volume(&self) -> Ref<'_, Volume> {
+        self.lower.as_ref().unwrap().borrow()
+    }
+
+    // The link of the lower volume `filename` leads to if only the lower
+    // volume of this overlay has it.
+    fn find_lower(
+        &self,
+        filename: &str,
+        follow_last: bool,
+        func_name: Option<String>,
+    ) -> Result<Option<LinkId>, FsError> {
+        if self.lower.is_none() {
+            return Ok(None);
+        }
+        match self.lookup(filename, follow_last, func_name)? {
+            Lookup::Lower { link, .. } => Ok(Some(link)),
+            _ => Ok(None),
+        }
+    }
+
+    // Counterpart of the directory `dir` in the lower volume, if it merges
+    // with one.
+    fn lower_dir(&self, dir: LinkId) -> Option<LinkId> {
+        let lower = self.lower.as_ref()?.borrow();
+        let mut ancestors = Vec::new();
+        let mut link = dir;
+        while let Some(parent) = self.links[link].get_parent() {
+            if self.links[link].opaque {
+                return None;
+            }
+            ancestors.push(link);
+            link = parent;
+        }
+        let mut below = lower.root;
+        for link in ancestors.iter().rev() {
+            below = lower.links[below].get_child(&self.links[*link].name)?;
+        }
+        lower.link_node(below).is_directory().then_some(below)
+    }
+
+    // The entry `name` the lower volume adds to the directory `dir`.
+    fn lower_child(&self, dir: LinkId, name: &str) -> Option<LinkId> {
+        if self.lower.is_none() || self.links[dir].whiteouts.contains(name) {
+            return None;
+        }
+        let below = self.lower_dir(dir)?;
+        self.lower_volume().links[below].get_child(name)
+    }
+
+    // Entries the lower volume adds to the directory `dir`, by name.
+    fn lower_entries(&self, dir: LinkId) -> Vec<(String, LinkId)> {
+        let below = match self.lower_dir(dir) {
+            Some(below) => below,
+            None => return Vec::new(),
+        };
+        let link = &self.links[dir];
+        self.lower_volume().links[below]
+            .children
+            .iter()
+            .filter(|(name, _)| {
+                !link.children.contains_key(*name) && !link.whiteouts.contains(*name)
+            })
+            .map(|(name, child)| (name.clone(), *child))
+            .collect()
+    }
+
+    fn is_empty_dir(&self, dir: LinkId) -> bool {
+        self.links[dir].length() == 0 && self.lower_entries(dir).is_empty()
+    }
+
+    // Copies the entry `filename` leads to and the directories on the way up
+    // from the lower volume of this overlay, ahead of modifying it.
+    fn copy_up(
+        &mut self,
+        filename: &str,
+        follow_last: bool,
+        func_name: Option<String>,
+    ) -> Result<(), FsError> {
+        if self.lower.is_none() {
+            return Ok(());
+        }
+        self.copy_up_parent(filename, func_name.clone())?;
+        while let Lookup::Lower {
+            dir, name, source, ..
+        } = self.lookup(filename, follow_last, func_name.clone())?
+        {
+            self.copy_up_link(dir, name, source).map_err(|err| {
+                create_error(
+                    err.code,
+                    func_name.clone(),
+                    Some(vec![filename.to_string()]),
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    // Like `copy_up`, for the directory a new entry at `filename` goes into.
+    fn copy_up_parent(&mut self, filename: &str, func_name: Option<String>) -> Result<(), FsError> {
+        if self.lower.is_none() {
+            return Ok(());
+        }
+        let steps = util::filename_to_steps(String::from(filename), None);
+        let dir_steps = match steps.split_last() {
+            Some((_, dir_steps)) => dir_steps.to_vec(),
+            None => return Ok(()),
+        };
+        while let Lookup::Lower {
+            dir, name, source, ..
+        } = self.resolve_steps(dir_steps.clone(), func_name.clone())?
+        {
+            self.copy_up_link(dir, name, source).map_err(|err| {
+                create_error(
+                    err.code,
+                    func_name.clone(),
+                    Some(vec![filename.to_string()]),
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    // Copies the entry `source` of the lower volume to `name` of `dir`.
+    // Directories are copied without their entries, which keep showing
+    // through.
+    fn copy_up_link(
+        &mut self,
+        dir: LinkId,
+        name: String,
+        source: LinkId,
+    ) -> Result<LinkId, FsError> {
+        let ino = self.nodes.next_id();
+        let mut node = self.lower_volume().link_node(source).copy(ino.index())?;
+        node.set_nlink(if node.is_directory() { 2 } else { 1 });
+        let node = self.nodes.insert(node);
+        self.resized(node, 0);
+        // Copying up does not modify the directory.
+        let dir_node = self.links[dir].node;
+        let mtime = self.nodes[dir_node].get_mtime();
+        let link = self.links.insert(Link::new(None, name.clone(), node));
+        self.attach_link(dir, name, link);
+        self.nodes[dir_node].set_mtime(mtime);
+        Ok(link)
+    }
+
+    // Copies everything the lower volume has below `link` up, so it can move
+    // to where the lower volume has nothing to merge with it.
+    fn copy_up_tree(&mut self, link: LinkId) -> Result<(), FsError> {
+        if !self.link_node(link).is_directory() {
+            return Ok(());
+        }
+        for (name, source) in self.lower_entries(link) {
+            self.copy_up_link(link, name, source)?;
+        }
+        let children: Vec<LinkId> = self.links[link].children.values().copied().collect();
+        for child in children {
+            self.copy_up_tree(child)?;
+        }
+        Ok(())
+    }
+
+    // The mount `filename` leads into and the path within its backend, `None`
+    // for paths of this volume. Symlinks on the way are followed, one at
+    // `filename` itself only with `follow_last`.
+    fn find_mount(
+        &self,
+        filename: &str,
+        follow_last: bool,
+        func_name: Option<String>,
+    ) -> Result<Option<(LinkId, String)>, FsError> {
+        if self.mounts.is_empty() {
+            return Ok(None);
+        }
+        let mut steps = util::filename_to_steps(String::from(filename), None);
+        let last = if follow_last { None } else { steps.pop() };
+        match self.resolve_steps(steps, func_name)? {
+            Lookup::Mounted(mount, path) => {
+                let path = match last {
+                    Some(name) => util::path_join(vec![path, name]),
+                    None => path,
+                };
+                Ok(Some((mount, path)))
+            }
+            Lookup::Found(dir) => {
+                let link = last.and_then(|name| self.links[dir].get_child(&name));
+                Ok(link
+                    .filter(|link| self.mounts.contains_key(link))
+                    .map(|link| (link, String::from("/"))))
+            }
+            Lookup::Missing | Lookup::Lower { .. } => Ok(None),
+        }
+    }
+
+    // Just like `getLinkOrThrow`, but also dereference/resolves symbolic links.
+    pub fn get_resolved_link_or_throw(
+        &self,
+        filename: String,
+        func_name: Option<String>,
+    ) -> Result<LinkId, FsError> {
+        let steps = util::filename_to_steps(filename.clone(), None);
+        match self.get_resolved_link_by_steps(steps, func_name.clone())? {
+            Some(link) => Ok(link),
+            None => Err(create_error(
+                FSError::ENOENT,
+                func_name,
+                Some(vec![filename]),
+            )),
+        }
+    }
+
+    pub fn resolve_symlinks(&self, link: LinkId) -> Option<LinkId> {
+        let steps = self.get_steps(link);
+        self.get_resolved_link_by_steps(steps, None).ok().flatten()
+    }
+
+    // Just like `getLinkOrThrow`, but also verifies that the link is a directory.
+    fn get_link_as_dir_or_throw(
+        &self,
+        filename: String,
+        func_name: Option<String>,
+    ) -> Result<LinkId, FsError> {
+        let link = self.get_link_or_throw(filename.clone(), func_name.clone())?;
+        if !self.link_node(link).is_directory() {
+            return Err(create_error(
+                FSError::ENOTDIR,
+                func_name,
+                Some(vec![filename]),
+            ));
+        }
+        Ok(link)
+    }
+
+    // Get the immediate parent directory of the link.
+    fn get_link_parent(&self, steps: Vec<String>) -> Option<LinkId> {
+        let (_, dir_steps) = steps.split_last()?;
+        self.walk(self.root, dir_steps)
+    }
+
+    fn get_link_parent_as_dir_or_throw(
+        &self,
+        filename: String,
+        func_name: Option<String>,
+    ) -> Result<LinkId, FsError> {
+        let steps = util::filename_to_steps(filename.clone(), None);
+        let link = match self.get_link_parent(steps) {
+            Some(link) => link,
+            None => {
+                return Err(create_error(
+                    FSError::ENOENT,
+                    func_name,
+                    Some(vec![filename]),
+                ))
+            }
+        };
+        if !self.link_node(link).is_directory() {
+            return Err(create_error(
+                FSError::ENOTDIR,
+                func_name,
+                Some(vec![filename]),
+            ));
+        }
+        Ok(link)
+    }
+
+    pub(crate) fn get_file_by_fd_or_throw(
+        &self,
+        fd: usize,
+        func_name: Option<String>,
+    ) -> Result<&File, FsError> {
+        match self.fds.get(&fd) {
+            Some(file) => Ok(file),
+            None => Err(create_error(FSError::EBADF, func_name, None)),
+        }
+    }
+
+    // Shared handle of this volume.
+    pub(crate) fn handle(&self) -> Arc<RefCell<Volume>> {
+        self.this.upgrade().unwrap()
+    }
+
+    fn open_link(
+        &mut self,
+        link: LinkId,
+        flags: usize,
+        resolve_symlinks: bool,
+    ) -> Result<usize, FsError> {
+        let path = self.get_path(link);
+        let func_name = Some(String::from("open"));
+        if self.open_files >= self.max_files {
+            return Err(create_error(FSError::EMFILE, func_name, Some(vec![path])));
+        }
+
+        let real_link = if resolve_symlinks {
+            let steps = self.get_steps(link);
+            self.get_resolved_link_by_steps(steps, func_name.clone())?
+        } else {
+            Some(link)
+        };
+        let real_link = match real_link {
+            Some(real_link) => real_link,
+            None => return Err(create_error(FSError::ENOENT, func_name, Some(vec![path]))),
+        };
+
+        let node_id = self.links[real_link].node;
+        let node = &self.nodes[node_id];
+        if node.is_directory() {
+            let access_mode =
+                flags & (constants::O_RDONLY | constants::O_WRONLY | constants::O_RDWR);
+            if access_mode != constants::O_RDONLY {
+                return Err(create_error(FSError::EISDIR, func_name, Some(vec![path])));
+            }
+        } else if flags & constants::O_DIRECTORY != 0 {
+            return Err(create_error(FSError::ENOTDIR, func_name, Some(vec![path])));
+        }
+
+        if flags & constants::O_WRONLY == 0 && !node.can_read(None, None) {
+            return Err(create_error(FSError::EACCES, func_name, Some(vec![path])));
+        }
+        if flags & constants::O_TRUNC == 0 {
+            self.nodes[node_id]
+                .load()
+                .map_err(|err| create_error(err.code, func_name, Some(vec![path])))?;
+        }
+
+        let fd = self.new_fd_number();
+        self.fds.insert(fd, File::new(node_id, flags, fd));
+        self.open_files += 1;
+        if flags & constants::O_TRUNC != 0 {
+            let size = self.nodes[node_id].get_size();
+            self.nodes[node_id].truncate(0);
+            self.resized(node_id, size);
+            self.node_changed(node_id);
+        }
+        Ok(fd)
+    }
+
+    fn open_file(
+        &mut self,
+        filename: String,
+        flags: usize,
+        mode: usize,
+        resolve_symlinks: bool,
+    ) -> Result<usize, FsError> {
+        let func_name = Some(String::from("open"));
+        let steps = util::filename_to_steps(filename.clone(), None);
+        if steps.iter().any(|step| step.len() > MAX_NAME_LENGTH) {
+            return Err(create_error(
+                FSError::ENAMETOOLONG,
+                func_name,
+                Some(vec![filename]),
+            ));
+        }
+        let modifies =
+            constants::O_WRONLY | constants::O_RDWR | constants::O_CREAT | constants::O_TRUNC;
+        let mount = match flags & modifies {
+            0 => self.find_mount(&filename, resolve_symlinks, func_name.clone())?,
+            _ => self.find_writable_mount(&filename, resolve_symlinks, func_name.clone())?,
+        };
+        if let Some((mount, path)) = mount {
+            return self.open_mounted(mount, path, filename, flags, mode, resolve_symlinks);
+        }
+        if flags & modifies != 0 {
+            self.copy_up(&filename, resolve_symlinks, func_name.clone())?;
+        } else if let Some(link) =
+            self.find_lower(&filename, resolve_symlinks, func_name.clone())?
+        {
+            return self.open_lower(link, filename, flags);
+        }
+        let mut link = if resolve_symlinks {
+            self.get_resolved_link_by_steps(steps.clone(), func_name.clone())?
+        } else {
+            self.get_link(steps.clone())
+        };
+
+        if link.is_some() && flags & constants::O_EXCL != 0 {
+            return Err(create_error(
+                FSError::EEXIST,
+                func_name,
+                Some(vec![filename]),
+            ));
+        }
+
+        // Try creating a new file, if it does not exist.
+        if link.is_none() && flags & constants::O_CREAT != 0 {
+            let (name, dir_steps) = steps.split_last().unwrap();
+            let dir_link =
+                match self.get_resolved_link_by_steps(dir_steps.to_vec(), func_name.clone())? {
+                    Some(dir_link) => dir_link,
+                    None => {
+                        let path = format!("/{}", steps.join("/"));
+                        return Err(create_error(FSError::ENOENT, func_name, Some(vec![path])));
+                    }
+                };
+            if !self.link_node(dir_link).is_directory() {
+                return Err(create_error(
+                    FSError::ENOTDIR,
+                    func_name,
+                    Some(vec![filename]),
+                ));
+            }
+            link = Some(self.create_link(dir_link, name.clone(), Some(false), Some(mode)));
+        }
+
+        match link {
+            Some(link) => self.open_link(link, flags, resolve_symlinks),
+            None => Err(create_error(
+                FSError::ENOENT,
+                func_name,
+                Some(vec![filename]),
+            )),
+        }
+    }
+
+    // Opens the entry `path` of the backend mounted at `mount` as a copy in
+    // memory, see `reload_backing`. `filename` is the path as opened.
+    fn open_mounted(
+        &mut self,
+        mount: LinkId,
+        path: String,
+        filename: String,
+        flags: usize,
+        mode: usize,
+        resolve_symlinks: bool,
+    ) -> Result<usize, FsError> {
+        let func_name = Some(String::from("open"));
+        let paths = Some(vec![filename]);
+        let with_details = |err: FsError| create_error(err.code, func_name.clone(), paths.clone());
+        if self.open_files >= self.max_files {
+            return Err(create_error(FSError::EMFILE, func_name, paths));
+        }
+
+        let backend = &mut self.mounts.get_mut(&mount).unwrap().backend;
+        let stats = match resolve_symlinks {
+            true => backend.stat(&path),
+            false => backend.lstat(&path),
+        };
+        let stats = match stats {
+            Ok(_) if flags & constants::O_EXCL != 0 => {
+                return Err(create_error(FSError::EEXIST, func_name, paths))
+            }
+            Ok(stats) => Some(stats),
+            Err(err) if err.code == FSError::ENOENT && flags & constants::O_CREAT != 0 => None,
+            Err(err) => return Err(with_details(err)),
+        };
+        let is_directory = stats.as_ref().is_some_and(Stats::is_directory);
+        let access_mode = flags & (constants::O_RDONLY | constants::O_WRONLY | constants::O_RDWR);
+        if is_directory && access_mode != constants::O_RDONLY {
+            return Err(create_error(FSError::EISDIR, func_name, paths));
+        }
+        if !is_directory && flags & constants::O_DIRECTORY != 0 {
+            return Err(create_error(FSError::ENOTDIR, func_name, paths));
+        }
+        let truncate = flags & constants::O_TRUNC != 0;
+        let contents = match stats {
+            Some(_) if !is_directory && !truncate => backend.read(&path).map_err(with_details)?,
+            _ => Vec::new(),
+        };
+        if stats.is_none() || (truncate && !is_directory) {
+            backend.write(&path, &[]).map_err(with_details)?;
+        }
+
+        let node_id = self.create_node(Some(is_directory), Some(mode));
+        let node = &mut self.nodes[node_id];
+        if !is_directory {
+            node.set_buffer(contents);
+        }
+        if let Some(stats) = stats {
+            node.ino = stats.ino;
+            node.mode = stats.mode;
+            node.chown(stats.uid, stats.gid);
+            node.set_nlink(stats.nlink);
+            node.set_atime(stats.atime);
+            node.set_mtime(stats.mtime);
+            node.set_ctime(stats.ctime);
+        }
+        let fd = self.new_fd_number();
+        let mut file = File::new(node_id, flags, fd);
+        file.backing = Some((mount, path));
+        file.detached = true;
+        self.fds.insert(fd, file);
+        self.open_files += 1;
+        Ok(fd)
+    }
+
+    // Opens the entry `link` of the lower volume for reading, as a copy
+    // dropped when closed.
+    fn open_lower(
+        &mut self,
+        link: LinkId,
+        filename: String,
+        flags: usize,
+    ) -> Result<usize, FsError> {
+        let func_name = Some(String::from("open"));
+        let paths = Some(vec![filename]);
+        if self.open_files >= self.max_files {
+            return Err(create_error(FSError::EMFILE, func_name, paths));
+        }
+        let node = {
+            let lower = self.lower_volume();
+            let node = lower.link_node(link);
+            if !node.is_directory() && flags & constants::O_DIRECTORY != 0 {
+                return Err(create_error(FSError::ENOTDIR, func_name, paths));
+            }
+            if !node.can_read(None, None) {
+                return Err(create_error(FSError::EACCES, func_name, paths));
+            }
+            node.copy(node.ino)
+                .map_err(|err| create_error(err.code, func_name, paths))?
+        };
+        let node = self.nodes.insert(node);
+        let fd = self.new_fd_number();
+        let mut file = File::new(node, flags, fd);
+        file.detached = true;
+        self.fds.insert(fd, file);
+        self.open_files += 1;
+        Ok(fd)
+    }
+
+    // Files of mounted backends are copies, reloaded from the backend before
+    // each operation on their fd and written back by `write_backing` after
+    // each write, so that other fds and paths see the same contents. A file
+    // removed from the backend keeps the contents it had.
+    fn reload_backing(&mut self, fd: usize, func_name: &str) -> Result<(), FsError> {
+        let file = &self.fds[&fd];
+        let (mount, path) = match &file.backing {
+            Some((mount, path)) => (*mount, path.clone()),
+            None => return Ok(()),
+        };
+        let node = file.node;
+        if self.nodes[node].is_directory() {
+            return Ok(());
+        }
+        let filename = util::path_join(vec![self.get_path(mount), path.clone()]);
+        let loaded = self.with_backend(mount, func_name, vec![filename], |backend| {
+            Ok((backend.stat(&path)?, backend.read(&path)?))
+        });
+        let (stats, contents) = match loaded {
+            Ok(loaded) => loaded,
+            Err(err) if err.code == FSError::ENOENT => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let node = &mut self.nodes[node];
+        node.buf = Some(Arc::new(contents));
+        node.set_mtime(stats.mtime);
+        node.set_ctime(stats.ctime);
+        Ok(())
+    }
+
+    // Stores the copy of a mounted file open as `fd` in its backend. On
+    // failure the fd stays open with the contents written to it.
+    fn write_backing(&mut self, fd: usize, func_name: &str) -> Result<(), FsError> {
+        let file = &self.fds[&fd];
+        let (mount, path) = match &file.backing {
+            Some((mount, path)) => (*mount, path.clone()),
+            None => return Ok(()),
+        };
+        let contents = self.nodes[file.node].buf.clone().unwrap_or_default();
+        let filename = util::path_join(vec![self.get_path(mount), path.clone()]);
+        self.with_backend(mount, func_name, vec![filename], |backend| {
+            backend.write(&path, &contents)
+        })
+    }
+
+    fn close_file(&mut self, fd: usize) -> Result<(), FsError> {
+        let file = match self.fds.remove(&fd) {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        self.open_files -= 1;
+        self.released_fds.push(fd);
+        if file.detached {
+            self.nodes.remove(file.node);
+        } else {
+            self.release_node(file.node);
+        }
+        Ok(())
+    }
+
+    pub fn open_sync(
+        &mut self,
+        path: String,
+        flags: Option<String>,
+        mode: Option<usize>,
+    ) -> Result<usize, FsError> {
+        let flags = util::flags_to_number(flags.unwrap_or(String::from("r")))?;
+        self.open_file(path, flags, mode.unwrap_or(0o666), true)
+    }
+
+    pub fn close_sync(&mut self, fd: usize) -> Result<(), FsError> {
+        self.get_file_by_fd_or_throw(fd, Some(String::from("close")))?;
+        self.close_file(fd)
+    }
+
+    pub fn read_sync(
+        &mut self,
+        fd: usize,
+        buffer: &mut [u8],
+        offset: Option<usize>,
+        length: Option<usize>,
+        position: Option<usize>,
+    ) -> Result<usize, FsError> {
+        let func_name = Some(String::from("read"));
+        self.get_file_by_fd_or_throw(fd, func_name.clone())?;
+        self.reload_backing(fd, "read")?;
+        let offset = offset.unwrap_or(0);
+        if offset > buffer.len() || offset + length.unwrap_or(0) > buffer.len() {
+            return Err(create_error(FSError::EINVAL, func_name, None));
+        }
+        let file = self.fds.get_mut(&fd).unwrap();
+        let node = &mut self.nodes[file.node];
+        if node.is_directory() {
+            return Err(create_error(FSError::EISDIR, func_name, None));
+        }
+        let bytes = file.read(node, buffer, Some(offset), length, position);
+        let node = file.node;
+        self.accessed(node);
+        Ok(bytes)
+    }
+
+    pub fn write_sync(
+        &mut self,
+        fd: usize,
+        buffer: &[u8],
+        offset: Option<usize>,
+        length: Option<usize>,
+        position: Option<usize>,
+    ) -> Result<usize, FsError> {
+        let func_name = Some(String::from("write"));
+        self.get_writable_file_or_throw(fd, func_name.clone())?;
+        let offset = offset.unwrap_or(0);
+        if offset > buffer.len() || offset + length.unwrap_or(0) > buffer.len() {
+            return Err(create_error(FSError::EINVAL, func_name, None));
+        }
+        let length = length.unwrap_or(buffer.len() - offset);
+        self.reload_backing(fd, "write")?;
+        self.check_file_growth(fd, position, length, func_name)?;
+        let file = self.fds.get_mut(&fd).unwrap();
+        let size = self.nodes[file.node].get_size();
+        let bytes = file.write(
+            &mut self.nodes[file.node],
+            buffer,
+            Some(offset),
+            Some(length),
+            position,
+        );
+        let node = file.node;
+        if !file.detached {
+            self.resized(node, size);
+        }
+        self.write_backing(fd, "write")?;
+        self.node_changed(node);
+        Ok(bytes)
+    }
+
+    // Writes all `buffers` one after another, starting at `position` or at the
+    // current file position.
+    pub fn writev_sync(
+        &mut self,
+        fd: usize,
+        buffers: &[Vec<u8>],
+        position: Option<usize>,
+    ) -> Result<usize, FsError> {
+        let func_name = Some(String::from("writev"));
+        self.get_writable_file_or_throw(fd, func_name.clone())?;
+        let length = buffers.iter().map(|buffer| buffer.len()).sum();
+        self.reload_backing(fd, "writev")?;
+        self.check_file_growth(fd, position, length, func_name)?;
+        let file = self.fds.get_mut(&fd).unwrap();
+        let node = &mut self.nodes[file.node];
+        let size = node.get_size();
+        let mut position = position;
+        let mut bytes_written = 0;
+        for buffer in buffers {
+            let bytes = file.write(node, buffer, None, None, position.take());
+            bytes_written += bytes;
+            if bytes < buffer.len() {
+                break;
+            }
+        }
+        let node = file.node;
+        if !file.detached {
+            self.resized(node, size);
+        }
+        self.write_backing(fd, "writev")?;
+        self.node_changed(node);
+        Ok(bytes_written)
+    }
+
+    // Moves the position of `fd` like `lseek`, returns the new position.
+    pub fn seek_sync(&mut self, fd: usize, pos: SeekFrom) -> Result<usize, FsError> {
+        let func_name = Some(String::from("seek"));
+        self.get_file_by_fd_or_throw(fd, func_name.clone())?;
+        self.reload_backing(fd, "seek")?;
+        let file = self.fds.get_mut(&fd).unwrap();
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset as i64),
+            SeekFrom::End(offset) => (self.nodes[file.node].get_size() as i64).checked_add(offset),
+            SeekFrom::Current(offset) => (file.position as i64).checked_add(offset),
+        };
+        match position {
+            Some(position) if position >= 0 => {
+                file.seek_to(position as usize);
+                Ok(position as usize)
+            }
+            _ => Err(create_error(FSError::EINVAL, func_name, None)),
+        }
+    }
+
+    // Sets the max number of bytes all files of this volume may occupy
+    // together, writes beyond it fail with ENOSPC.
+    pub fn set_quota(&mut self, quota: Option<usize>) {
+        self.quota = quota;
+    }
+
+    // Number of bytes occupied by the contents of all files.
+    pub fn used_bytes(&self) -> usize {
+        self.used
+    }
+
+    // Fails with EFBIG or ENOSPC if writing `length` bytes at `position` would
+    // grow the file open as `fd` past the max file size or the volume's quota.
+    // Detached files are copies and do not count against the quota.
+    fn check_file_growth(
+        &self,
+        fd: usize,
+        position: Option<usize>,
+        length: usize,
+        func_name: Option<String>,
+    ) -> Result<(), FsError> {
+        let file = &self.fds[&fd];
+        let node = &self.nodes[file.node];
+        let size = node.get_size();
+        let end = match file.write_position(node, position).checked_add(length) {
+            Some(end) if end <= MAX_FILE_SIZE => end,
+            _ => return Err(create_error(FSError::EFBIG, func_name, None)),
+        };
+        if let Some(quota) = self.quota.filter(|_| !file.detached) {
+            if self.used_bytes() + end.saturating_sub(size) > quota {
+                return Err(create_error(FSError::ENOSPC, func_name, None));
+            }
+        }
+        Ok(())
+    }
+
+    fn get_writable_file_or_throw(
+        &self,
+        fd: usize,
+        func_name: Option<String>,
+    ) -> Result<&File, FsError> {
+        let file = self.get_file_by_fd_or_throw(fd, func_name.clone())?;
+        if file.flags & (constants::O_WRONLY | constants::O_RDWR) == 0 {
+            return Err(create_error(FSError::EBADF, func_name, None));
+        }
+        if self.is_file_read_only(file) {
+            return Err(create_error(FSError::EROFS, func_name, None));
+        }
+        Ok(file)
+    }
+
+    // Whether `file` is on a read-only volume or mount.
+    fn is_file_read_only(&self, file: &File) -> bool {
+        match &file.backing {
+            Some((mount, _)) => self.mounts.get(mount).is_some_and(|mount| mount.read_only),
+            None => self.read_only,
+        }
+    }
+
+    // Resolves the directory a new entry at `filename` goes into, following
+    // symlinks on the way, and returns it along with the entry's name.
+    fn get_resolved_parent_dir_or_throw(
+        &self,
+        filename: String,
+        func_name: Option<String>,
+    ) -> Result<(LinkId, String), FsError> {
+        let mut steps = util::filename_to_steps(filename.clone(), None);
+        let name = match steps.pop() {
+            Some(name) => name,
+            None => {
+                return Err(create_error(
+                    FSError::EEXIST,
+                    func_name,
+                    Some(vec![filename]),
+                ))
+            }
+        };
+        if name.len() > MAX_NAME_LENGTH {
+            return Err(create_error(
+                FSError::ENAMETOOLONG,
+                func_name,
+                Some(vec![filename]),
+            ));
+        }
+        let dir = match self.get_resolved_link_by_steps(steps, func_name.clone())? {
+            Some(dir) => dir,
+            None => {
+                return Err(create_error(
+                    FSError::ENOENT,
+                    func_name,
+                    Some(vec![filename]),
+                ))
+            }
+        };
+        if !self.link_node(dir).is_directory() {
+            return Err(create_error(
+                FSError::ENOTDIR,
+                func_name,
+                Some(vec![filename]),
+            ));
+        }
+        Ok((dir, name))
+    }
+
+    // Removes `link` from its parent and frees its node once no hard link to
+    // it is left.
+    fn unlink_link(&mut self, link: LinkId) {
+        let node = self.links[link].node;
+        self.delete_link(link);
+        self.nodes[node].dec_nlink();
+        self.release_node(node);
+    }
+
+    // Removes the directory `link` along with everything below it.
+    fn delete_tree(&mut self, link: LinkId) {
+        let children: Vec<LinkId> = self.links[link].children.values().copied().collect();
+        for child in children {
+            if self.link_node(child).is_directory() {
+                self.delete_tree(child);
+            } else {
+                self.unlink_link(child);
+            }
+        }
+        let node = self.links[link].node;
+        self.delete_link(link);
+        self.nodes[node].set_nlink(0);
+        self.release_node(node);
+    }
+
+    // With `recursive` set, creates missing parents too and returns the first
+    // directory created, like Node.
+    pub fn mkdir_sync(
+        &mut self,
+        path: String,
+        options: Option<MkdirOptions>,
+    ) -> Result<Option<String>, FsError> {
+        let options = options.unwrap_or_default();
+        let mode = options.mode.unwrap_or(0o777);
+        let recursive = options.recursive.unwrap_or(false);
+        let func_name = Some(String::from("mkdir"));
+        // Existing entries are reported before a read-only file system is.
+        if recursive {
+            let stats = self.stat_sync(path.clone(), None);
+            if stats.is_ok_and(|stats| stats.is_directory()) {
+                return Ok(None);
+            }
+        } else if self.lstat_sync(path.clone(), None).is_ok() {
+            return Err(create_error(FSError::EEXIST, func_name, Some(vec![path])));
+        }
+        if let Some((mount, mounted_path)) =
+            self.find_writable_mount(&path, true, func_name.clone())?
+        {
+            return self.mkdir_mounted(mount, mounted_path, path, recursive, mode);
+        }
+        if recursive {
+            return self.mkdirp(path, mode);
+        }
+        if self.find_lower(&path, false, func_name.clone())?.is_some() {
+            return Err(create_error(FSError::EEXIST, func_name, Some(vec![path])));
+        }
+        self.copy_up_parent(&path, func_name.clone())?;
+        let (dir, name) = self.get_resolved_parent_dir_or_throw(path.clone(), func_name.clone())?;
+        if self.links[dir].get_child(&name).is_some() {
+            return Err(create_error(FSError::EEXIST, func_name, Some(vec![path])));
+        }
+        self.create_link(dir, name, Some(true), Some(mode));
+        Ok(None)
+    }
+
+    // `mkdir_sync` for the entry `mounted_path` of the backend mounted at
+    // `mount`.
+    fn mkdir_mounted(
+        &mut self,
+        mount: LinkId,
+        mounted_path: String,
+        path: String,
+        recursive: bool,
+        mode: usize,
+    ) -> Result<Option<String>, FsError> {
+        if !recursive {
+            self.with_backend(mount, "mkdir", vec![path], |backend| {
+                backend.mkdir(&mounted_path, mode)
+            })?;
+            return Ok(None);
+        }
+        let mount_path = self.get_path(mount);
+        let steps = util::filename_to_steps(mounted_path, None);
+        let mut created = None;
+        self.with_backend(mount, "mkdir", vec![path], |backend| {
+            for i in 1..=steps.len() {
+                let dir = format!("/{}", steps[..i].join("/"));
+                match backend.stat(&dir) {
+                    Ok(stats) if stats.is_directory() => {}
+                    Ok(_) => return Err(FsError::new(FSError::ENOTDIR)),
+                    Err(err) if err.code == FSError::ENOENT => {
+                        backend.mkdir(&dir, mode)?;
+                        created
+                            .get_or_insert_with(|| util::path_join(vec![mount_path.clone(), dir]));
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(())
+        })?;
+        Ok(created)
+    }
+
+    fn mkdirp(&mut self, path: String, mode: usize) -> Result<Option<String>, FsError> {
+        let func_name = Some(String::from("mkdir"));
+        let steps = util::filename_to_steps(path.clone(), None);
+        let mut created = None;
+        for i in 1..=steps.len() {
+            let dir_steps = steps[..i].to_vec();
+            let dir_path = format!("/{}", dir_steps.join("/"));
+            if let Some(link) = self.find_lower(&dir_path, true, func_name.clone())? {
+                if !self.lower_volume().link_node(link).is_directory() {
+                    return Err(create_error(FSError::ENOTDIR, func_name, Some(vec![path])));
+                }
+                continue;
+            }
+            match self.get_resolved_link_by_steps(dir_steps.clone(), func_name.clone())? {
+                Some(link) => {
+                    if !self.link_node(link).is_directory() {
+                        return Err(create_error(FSError::ENOTDIR, func_name, Some(vec![path])));
+                    }
+                }
+                None => {
+                    let name = steps[i - 1].clone();
+                    if name.len() > MAX_NAME_LENGTH {
+                        return Err(create_error(
+                            FSError::ENAMETOOLONG,
+                            func_name,
+                            Some(vec![path]),
+                        ));
+                    }
+                    self.copy_up_parent(&dir_path, func_name.clone())?;
+                    let parent = self
+                        .get_resolved_link_by_steps(steps[..i - 1].to_vec(), func_name.clone())?
+                        .unwrap();
+                    self.create_link(parent, name, Some(true), Some(mode));
+                    created.get_or_insert(dir_path);
+                }
+            }
+        }
+        Ok(created)
+    }
+
+    // Entry names of the directory at `path`, sorted.
+    pub fn readdir_sync(&mut self, path: String) -> Result<Vec<String>, FsError> {
+        let dirents = self.readdir_with_file_types_sync(path)?;
+        Ok(dirents.into_iter().map(|dirent| dirent.name).collect())
+    }
+
+    // Like `readdirSync` with `withFileTypes: true`.
+    pub fn readdir_with_file_types_sync(&mut self, path: String) -> Result<Vec<Dirent>, FsError> {
+        let func_name = Some(String::from("scandir"));
+        if let Some((mount, mounted_path)) = self.find_mount(&path, true, func_name.clone())? {
+            return self.with_backend(mount, "scandir", vec![path], |backend| {
+                backend.readdir(&mounted_path)
+            });
+        }
+        if let Some(link) = self.find_lower(&path, true, func_name.clone())? {
+            let lower = self.lower_volume();
+            if !lower.link_node(link).is_directory() {
+                return Err(create_error(FSError::ENOTDIR, func_name, Some(vec![path])));
+            }
+            let dirents = lower.links[link]
+                .children
+                .iter()
+                .map(|(name, child)| Dirent {
+                    name: name.clone(),
+                    mode: lower.link_node(*child).mode,
+                })
+                .collect();
+            return Ok(dirents);
+        }
+        let link = self.get_resolved_link_or_throw(path.clone(), func_name.clone())?;
+        if !self.link_node(link).is_directory() {
+            return Err(create_error(FSError::ENOTDIR, func_name, Some(vec![path])));
+        }
+        let mut dirents: Vec<Dirent> = self.links[link]
+            .children
+            .iter()
+            .map(|(name, child)| Dirent {
+                name: name.clone(),
+                mode: self.link_node(*child).mode,
+            })
+            .collect();
+        let lower_entries = self.lower_entries(link);
+        if !lower_entries.is_empty() {
+            let lower = self.lower_volume();
+            dirents.extend(lower_entries.into_iter().map(|(name, child)| Dirent {
+                name,
+                mode: lower.link_node(child).mode,
+            }));
+            dirents.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        Ok(dirents)
+    }
+
+    pub fn fstat_sync(&mut self, fd: usize, bigint: Option<bool>) -> Result<Stats, FsError> {
+        self.get_file_by_fd_or_throw(fd, Some(String::from("fstat")))?;
+        self.reload_backing(fd, "fstat")?;
+        let file = &self.fds[&fd];
+        // Detached files are copies of an entry of a mount or of the lower
+        // volume.
+        let dev = match (&file.backing, file.detached) {
+            (Some((mount, _)), _) => self.mounts.get(mount).map_or(0, |mount| mount.dev),
+            (None, true) => LOWER_DEV,
+            (None, false) => 0,
+        };
+        let stats = Stats::build(&self.nodes[file.node], bigint.unwrap_or(false));
+        Ok(Stats { dev, ..stats })
+    }
+
+    // Takes `lock` on the file of `fd`, or releases its lock if `None`,
+    // replacing any lock it already holds like flock(2). Fails with EAGAIN
+    // instead of waiting if another file description of the same node holds
+    // a conflicting lock, nothing else can run while the volume waits. Locks
+    // are advisory and released when the file is closed.
+    pub fn flock_sync(&mut self, fd: usize, lock: Option<FileLock>) -> Result<(), FsError> {
+        let func_name = Some(String::from("flock"));
+        let node = self.get_file_by_fd_or_throw(fd, func_name.clone())?.node;
+        if let Some(lock) = lock {
+            let conflict = self.fds.values().any(|other| {
+                other.fd != fd
+                    && other.node == node
+                    && match other.lock {
+                        Some(held) => held == FileLock::Exclusive || lock == FileLock::Exclusive,
+                        None => false,
+                    }
+            });
+            if conflict {
+                return Err(create_error(FSError::EAGAIN, func_name, None));
+            }
+        }
+        self.fds.get_mut(&fd).unwrap().lock = lock;
+        Ok(())
+    }
+
+    pub fn ftruncate_sync(&mut self, fd: usize, len: Option<usize>) -> Result<(), FsError> {
+        let func_name = Some(String::from("ftruncate"));
+        let node = self.get_writable_file_or_throw(fd, func_name.clone())?.node;
+        let len = len.unwrap_or(0);
+        if len > MAX_FILE_SIZE {
+            return Err(create_error(FSError::EFBIG, func_name, None));
+        }
+        self.reload_backing(fd, "ftruncate")?;
+        let size = self.nodes[node].get_size();
+        let detached = self.fds[&fd].detached;
+        if let Some(quota) = self.quota.filter(|_| !detached) {
+            if self.used_bytes() + len.saturating_sub(size) > quota {
+                return Err(create_error(FSError::ENOSPC, func_name, None));
+            }
+        }
+        self.nodes[node].truncate(len);
+        if !detached {
+            self.resized(node, size);
+        }
+        self.write_backing(fd, "ftruncate")?;
+        self.node_changed(node);
+        Ok(())
+    }
+
+    // Like `stat_sync`, but does not follow a symlink at `path`.
+    pub fn lstat_sync(&mut self, path: String, bigint: Option<bool>) -> Result<Stats, FsError> {
+        let func_name = Some(String::from("lstat"));
+        if let Some((mount, mounted_path)) = self.find_mount(&path, false, func_name.clone())? {
+            let stats = self.with_backend(mount, "lstat", vec![path], |backend| {
+                backend.lstat(&mounted_path)
+            })?;
+            return Ok(Stats {
+                dev: self.mounts[&mount].dev,
+                bigint: bigint.unwrap_or(false),
+                ..stats
+            });
+        }
+        if let Some(link) = self.find_lower(&path, false, func_name.clone())? {
+            let lower = self.lower_volume();
+            return Ok(Stats {
+                dev: LOWER_DEV,
+                ..Stats::build(lower.link_node(link), bigint.unwrap_or(false))
+            });
+        }
+        let link = self.get_link_or_throw(path, func_name)?;
+        let stats = Stats::build(self.link_node(link), bigint.unwrap_or(false));
+        Ok(stats)
+    }
+
+    pub fn unlink_sync(&mut self, path: String) -> Result<(), FsError> {
+        let func_name = Some(String::from("unlink"));
+        if let Some((mount, mounted_path)) =
+            self.find_writable_mount(&path, false, func_name.clone())?
+        {
+            return self.with_backend(mount, "unlink", vec![path], |backend| {
+                backend.unlink(&mounted_path)
+            });
+        }
+        self.copy_up(&path, false, func_name.clone())?;
+        let link = self.get_link_or_throw(path.clone(), func_name.clone())?;
+        if self.link_node(link).is_directory() {
+            return Err(create_error(FSError::EISDIR, func_name, Some(vec![path])));
+        }
+        self.unlink_link(link);
+        Ok(())
+    }
+
+    pub fn rmdir_sync(&mut self, path: String, recursive: Option<bool>) -> Result<(), FsError> {
+        let func_name = Some(String::from("rmdir"));
+        let recursive = recursive.unwrap_or(false);
+        if let Some((mount, mounted_path)) =
+            self.find_writable_mount(&path, false, func_name.clone())?
+        {
+            if mounted_path == "/" {
+                return Err(create_error(FSError::EBUSY, func_name, Some(vec![path])));
+            }
+            return self.with_backend(mount, "rmdir", vec![path], |backend| {
+                if !backend.lstat(&mounted_path)?.is_directory() {
+                    return Err(FsError::new(FSError::ENOTDIR));
+                }
+                match recursive {
+                    true => remove_mounted_tree(backend, &mounted_path),
+                    false => backend.rmdir(&mounted_path),
+                }
+            });
+        }
+        self.copy_up(&path, false, func_name.clone())?;
+        let link = self.get_link_as_dir_or_throw(path.clone(), func_name.clone())?;
+        if self.links[link].get_parent().is_none() {
+            return Err(create_error(FSError::EBUSY, func_name, Some(vec![path])));
+        }
+        if !recursive && !self.is_empty_dir(link) {
+            return Err(create_error(
+                FSError::ENOTEMPTY,
+                func_name,
+                Some(vec![path]),
+            ));
+        }
+        if self.contains_mount(link) {
+            return Err(create_error(FSError::EBUSY, func_name, Some(vec![path])));
+        }
+        self.delete_tree(link);
+        Ok(())
+    }
+
+    pub fn rm_sync(&mut self, path: String, options: Option<RmOptions>) -> Result<(), FsError> {
+        let options = options.unwrap_or_default();
+        let func_name = Some(String::from("rm"));
+        if let Some((mount, mounted_path)) =
+            self.find_writable_mount(&path, false, func_name.clone())?
+        {
+            return self.rm_mounted(mount, mounted_path, path, options);
+        }
+        self.copy_up(&path, false, func_name.clone())?;
+        let link = match self.get_link(util::filename_to_steps(path.clone(), None)) {
+            Some(link) => link,
+            None if options.force.unwrap_or(false) => return Ok(()),
+            None => return Err(create_error(FSError::ENOENT, func_name, Some(vec![path]))),
+        };
+        if !self.link_node(link).is_directory() {
+            self.unlink_link(link);
+            return Ok(());
+        }
+        if !options.recursive.unwrap_or(false) {
+            return Err(create_error(
+                FSError::ERR_FS_EISDIR,
+                func_name,
+                Some(vec![path]),
+            ));
+        }
+        if self.links[link].get_parent().is_none() || self.contains_mount(link) {
+            return Err(create_error(FSError::EBUSY, func_name, Some(vec![path])));
+        }
+        self.delete_tree(link);
+        Ok(())
+    }
+
+    // `rm_sync` for the entry `mounted_path` of the backend mounted at
+    // `mount`.
+    fn rm_mounted(
+        &mut self,
+        mount: LinkId,
+        mounted_path: String,
+        path: String,
+        options: RmOptions,
+    ) -> Result<(), FsError> {
+        let result = self.with_backend(mount, "rm", vec![path.clone()], |backend| {
+            if !backend.lstat(&mounted_path)?.is_directory() {
+                return backend.unlink(&mounted_path);
+            }
+            if !options.recursive.unwrap_or(false) {
+                return Err(FsError::new(FSError::ERR_FS_EISDIR));
+            }
+            if mounted_path == "/" {
+                return Err(FsError::new(FSError::EBUSY));
+            }
+            remove_mounted_tree(backend, &mounted_path)
+        });
+        match result {
+            Err(err) if err.code == FSError::ENOENT && options.force.unwrap_or(false) => Ok(()),
+            result => result,
+        }
+    }
+
+    // Replaces an existing entry at `new_path`, as long as both are files or
+    // the replaced directory is empty.
+    pub fn rename_sync(&mut self, old_path: String, new_path: String) -> Result<(), FsError> {
+        let func_name = Some(String::from("rename"));
+        let paths = Some(vec![old_path.clone(), new_path.clone()]);
+        let old_mount = self.find_writable_mount(&old_path, false, func_name.clone())?;
+        let new_mount = self.find_writable_mount(&new_path, false, func_name.clone())?;
+        match (old_mount, new_mount) {
+            (None, None) => {}
+            // Mount points stay where they are.
+            (Some((_, mounted)), _) | (_, Some((_, mounted))) if mounted == "/" => {
+                return Err(create_error(FSError::EBUSY, func_name, paths));
+            }
+            (Some((old, old_mounted)), Some((new, new_mounted))) if old == new => {
+                return self.with_backend(old, "rename", vec![old_path, new_path], |backend| {
+                    backend.rename(&old_mounted, &new_mounted)
+                });
+            }
+            _ => return Err(create_error(FSError::EXDEV, func_name, paths)),
+        }
+        self.copy_up(&old_path, false, func_name.clone())?;
+        self.copy_up(&new_path, false, func_name.clone())?;
+        let link = match self.get_link(util::filename_to_steps(old_path.clone(), None)) {
+            Some(link) => link,
+            None => return Err(create_error(FSError::ENOENT, func_name, paths)),
+        };
+        if self.links[link].get_parent().is_none() {
+            return Err(create_error(FSError::EBUSY, func_name, paths));
+        }
+        let (dir, name) = self
+            .get_resolved_parent_dir_or_throw(new_path.clone(), func_name.clone())
+            .map_err(|err| create_error(err.code, func_name.clone(), paths.clone()))?;
+
+        // A directory can not be moved into itself.
+        let mut ancestor = Some(dir);
+        while let Some(current) = ancestor {
+            if current == link {
+                return Err(create_error(FSError::EINVAL, func_name, paths));
+            }
+            ancestor = self.links[current].get_parent();
+        }
+
+        let is_directory = self.link_node(link).is_directory();
+        if let Some(existing) = self.links[dir].get_child(&name) {
+            if existing == link {
+                return Ok(());
+            }
+            let existing_is_directory = self.link_node(existing).is_directory();
+            let code = match (is_directory, existing_is_directory) {
+                (false, true) => Some(FSError::EISDIR),
+                (true, false) => Some(FSError::ENOTDIR),
+                (true, true) if !self.is_empty_dir(existing) => Some(FSError::ENOTEMPTY),
+                _ => None,
+            };
+            if let Some(code) = code {
+                return Err(create_error(code, func_name, paths));
+            }
+            if existing_is_directory {
+                self.delete_tree(existing);
+            } else {
+                self.unlink_link(existing);
+            }
+        }
+
+        // The lower volume's entries of a moved directory stay behind otherwise.
+        self.copy_up_tree(link)
+            .map_err(|err| create_error(err.code, func_name, paths))?;
+        self.detach_link(link);
+        self.attach_link(dir, name, link);
+        Ok(())
+    }
+
+    // Relative targets are resolved against the directory of `path`.
+    pub fn symlink_sync(&mut self, target: String, path: String) -> Result<(), FsError> {
+        let func_name = Some(String::from("symlink"));
+        let paths = Some(vec![target.clone(), path.clone()]);
+        if let Some((mount, mounted_path)) =
+            self.find_writable_mount(&path, false, func_name.clone())?
+        {
+            return self.with_backend(
+                mount,
+                "symlink",
+                vec![target.clone(), path.clone()],
+                |backend| backend.symlink(&target, &mounted_path),
+            );
+        }
+        self.copy_up(&path, false, func_name.clone())?;
+        let (dir, name) = self
+            .get_resolved_parent_dir_or_throw(path, func_name.clone())
+            .map_err(|err| create_error(err.code, func_name.clone(), paths.clone()))?;
+        if self.links[dir].get_child(&name).is_some() {
+            return Err(create_error(FSError::EEXIST, func_name, paths));
+        }
+        let dir_path = self.get_path(dir);
+        let target_steps = util::filename_to_steps(target.clone(), Some(dir_path));
+        let link = self.create_link(dir, name, Some(false), Some(0o777));
+        let node = self.links[link].node;
+        self.nodes[node].make_symlink(target, target_steps);
+        Ok(())
+    }
+
+    pub fn readlink_sync(&mut self, path: String) -> Result<String, FsError> {
+        let func_name = Some(String::from("readlink"));
+        if let Some((mount, mounted_path)) = self.find_mount(&path, false, func_name.clone())? {
+            return self.with_backend(mount, "readlink", vec![path], |backend| {
+                backend.readlink(&mounted_path)
+            });
+        }
+        if let Some(link) = self.find_lower(&path, false, func_name.clone())? {
+            let lower = self.lower_volume();
+            let node = lower.link_node(link);
+            if !node.is_symlink() {
+                return Err(create_error(FSError::EINVAL, func_name, Some(vec![path])));
+            }
+            return Ok(node.target.clone());
+        }
+        let link = self.get_link_or_throw(path.clone(), func_name.clone())?;
+        let node = self.link_node(link);
+        if !node.is_symlink() {
+            return Err(create_error(FSError::EINVAL, func_name, Some(vec![path])));
+        }
+        Ok(node.target.clone())
+    }
+
+    // Creates a hard link `new_path` to the file at `existing_path`.
+    pub fn link_sync(&mut self, existing_path: String, new_path: String) -> Result<(), FsError> {
+        let func_name = Some(String::from("link"));
+        let paths = Some(vec![existing_path.clone(), new_path.clone()]);
+        let old_mount = self.find_writable_mount(&existing_path, false, func_name.clone())?;
+        let new_mount = self.find_writable_mount(&new_path, false, func_name.clone())?;
+        match (old_mount, new_mount) {
+            (None, None) => {}
+            (Some((old, old_path)), Some((new, mounted_path))) if old == new => {
+                return self.with_backend(old, "link", vec![existing_path, new_path], |backend| {
+                    backend.link(&old_path, &mounted_path)
+                });
+            }
+            _ => return Err(create_error(FSError::EXDEV, func_name, paths)),
+        }
+        self.copy_up(&existing_path, false, func_name.clone())?;
+        self.copy_up(&new_path, false, func_name.clone())?;
+        let link = self
+            .get_link_or_throw(existing_path, func_name.clone())
+            .map_err(|err| create_error(err.code, func_name.clone(), paths.clone()))?;
+        let node = self.links[link].node;
+        if self.nodes[node].is_directory() {
+            return Err(create_error(FSError::EPERM, func_name, paths));
+        }
+        let (dir, name) = self
+            .get_resolved_parent_dir_or_throw(new_path, func_name.clone())
+            .map_err(|err| create_error(err.code, func_name.clone(), paths.clone()))?;
+        if self.links[dir].get_child(&name).is_some() {
+            return Err(create_error(FSError::EEXIST, func_name, paths));
+        }
+        let link = self.links.insert(Link::new(None, name.clone(), node));
+        self.attach_link(dir, name, link);
+        self.nodes[node].inc_nlink();
+        Ok(())
+    }
+
+    // `flags` takes `constants::COPYFILE_EXCL` to fail if `dest` exists.
+    pub fn copy_file_sync(
+        &mut self,
+        src: String,
+        dest: String,
+        flags: Option<usize>,
+    ) -> Result<(), FsError> {
+        let func_name = Some(String::from("copyfile"));
+        let paths = Some(vec![src.clone(), dest.clone()]);
+        if let Some((mount, mounted_path)) = self.find_mount(&src, true, func_name.clone())? {
+            let data = self.with_backend(mount, "copyfile", paths.clone().unwrap(), |backend| {
+                backend.read(&mounted_path)
+            })?;
+            if flags.unwrap_or(0) & constants::COPYFILE_EXCL != 0 && self.exists_sync(dest.clone())
+            {
+                return Err(create_error(FSError::EEXIST, func_name, paths));
+            }
+            return self.write_file_sync(dest, &data, None);
+        }
+        if let Some(link) = self.find_lower(&src, true, func_name.clone())? {
+            let data = {
+                let lower = self.lower_volume();
+                let node = lower.link_node(link);
+                if node.is_directory() {
+                    return Err(create_error(FSError::EISDIR, func_name, paths));
+                }
+                node.contents()
+                    .map_err(|err| create_error(err.code, func_name.clone(), paths.clone()))?
+                    .into_owned()
+            };
+            if flags.unwrap_or(0) & constants::COPYFILE_EXCL != 0 && self.exists_sync(dest.clone())
+            {
+                return Err(create_error(FSError::EEXIST, func_name, paths));
+            }
+            return self.write_file_sync(dest, &data, None);
+        }
+        let link = self
+            .get_resolved_link_or_throw(src, func_name.clone())
+            .map_err(|err| create_error(err.code, func_name.clone(), paths.clone()))?;
+        let node = self.links[link].node;
+        if self.nodes[node].is_directory() {
+            return Err(create_error(FSError::EISDIR, func_name, paths));
+        }
+        if flags.unwrap_or(0) & constants::COPYFILE_EXCL != 0 && self.exists_sync(dest.clone()) {
+            return Err(create_error(FSError::EEXIST, func_name, paths));
+        }
+        self.nodes[node]
+            .load()
+            .map_err(|err| create_error(err.code, func_name, paths))?;
+        let buf = self.nodes[node].get_buffer();
+        self.accessed(node);
+        self.write_file_sync(dest, &buf, None)
+    }
+
+    pub fn read_file_sync(&mut self, path: String) -> Result<Vec<u8>, FsError> {
+        let func_name = Some(String::from("open"));
+        if let Some((mount, mounted_path)) = self.find_mount(&path, true, func_name.clone())? {
+            return self.with_backend(mount, "read", vec![path], |backend| {
+                backend.read(&mounted_path)
+            });
+        }
+        if let Some(link) = self.find_lower(&path, true, func_name.clone())? {
+            let lower = self.lower_volume();
+            let node = lower.link_node(link);
+            if node.is_directory() {
+                return Err(create_error(
+                    FSError::EISDIR,
+                    Some(String::from("read")),
+                    None,
+                ));
+            }
+            if !node.can_read(None, None) {
+                return Err(create_error(FSError::EACCES, func_name, Some(vec![path])));
+            }
+            let contents = node.contents().map_err(|err| {
+                create_error(err.code, Some(String::from("read")), Some(vec![path]))
+            })?;
+            return Ok(contents.into_owned());
+        }
+        let link = self.get_resolved_link_or_throw(path.clone(), func_name)?;
+        let node = self.links[link].node;
+        let node = &mut self.nodes[node];
+        if node.is_directory() {
+            return Err(create_error(
+                FSError::EISDIR,
+                Some(String::from("read")),
+                None,
+            ));
+        }
+        if !node.can_read(None, None) {
+            return Err(create_error(
+                FSError::EACCES,
+                Some(String::from("open")),
+                Some(vec![path]),
+            ));
+        }
+        node.load()
+            .map_err(|err| create_error(err.code, Some(String::from("read")), Some(vec![path])))?;
+        let buf = node.get_buffer();
+        self.accessed(self.links[link].node);
+        Ok(buf)
+    }
+
+    pub fn write_file_sync(
+        &mut self,
+        path: String,
+        data: &[u8],
+        options: Option<WriteFileOptions>,
+    ) -> Result<(), FsError> {
+        let options = options.unwrap_or_default();
+        let flag = options.flag.unwrap_or(String::from("w"));
+        let fd = self.open_sync(path, Some(flag), options.mode)?;
+        let result = self.write_sync(fd, data, None, None, None);
+        self.close_sync(fd)?;
+        result.map(|_| ())
+    }
+
+    pub fn exists_sync(&mut self, path: String) -> bool {
+        match self.find_mount(&path, true, None) {
+            Ok(Some((mount, mounted_path))) => {
+                return self.mounts[&mount].backend.stat(&mounted_path).is_ok()
+            }
+            Ok(None) => {}
+            Err(_) => return false,
+        }
+        if let Ok(Some(_)) = self.find_lower(&path, true, None) {
+            return true;
+        }
+        self.get_resolved_link(path).is_some()
+    }
+
+    pub fn realpath_sync(&mut self, path: String) -> Result<String, FsError> {
+        let func_name = Some(String::from("realpath"));
+        if let Some((mount, mounted_path)) = self.find_mount(&path, true, func_name.clone())? {
+            let real = self.with_backend(mount, "realpath", vec![path], |backend| {
+                backend.realpath(&mounted_path)
+            })?;
+            return Ok(util::path_join(vec![self.get_path(mount), real]));
+        }
+        if let Some(link) = self.find_lower(&path, true, func_name.clone())? {
+            return Ok(self.lower_volume().get_path(link));
+        }
+        let link = self.get_resolved_link_or_throw(path, func_name)?;
+        Ok(self.get_path(link))
+    }
+
+    pub fn chmod_sync(&mut self, path: String, mode: usize) -> Result<(), FsError> {
+        let func_name = Some(String::from("chmod"));
+        if let Some((mount, mounted_path)) =
+            self.find_writable_mount(&path, true, func_name.clone())?
+        {
+            return self.with_backend(mount, "chmod", vec![path], |backend| {
+                backend.chmod(&mounted_path, mode)
+            });
+        }
+        self.copy_up(&path, true, func_name.clone())?;
+        let link = self.get_resolved_link_or_throw(path, func_name)?;
+        let node = self.links[link].node;
+        self.nodes[node].chmod(mode & 0o777);
+        self.node_changed(node);
+        Ok(())
+    }
+
+    // Mounted backends have no owners to set and fail with ENOTSUP.
+    pub fn chown_sync(&mut self, path: String, uid: usize, gid: usize) -> Result<(), FsError> {
+        let func_name = Some(String::from("chown"));
+        if self
+            .find_writable_mount(&path, true, func_name.clone())?
+            .is_some()
+        {
+            return Err(create_error(FSError::ENOTSUP, func_name, Some(vec![path])));
+        }
+        self.copy_up(&path, true, func_name.clone())?;
+        let link = self.get_resolved_link_or_throw(path, func_name)?;
+        let node = self.links[link].node;
+        self.nodes[node].chown(uid, gid);
+        self.node_changed(node);
+        Ok(())
+    }
+
+    // Sets the access and modification times of `path`, following a symlink
+    // there. Mounted backends have no times to set and fail with ENOTSUP.
+    pub fn utimes_sync(
+        &mut self,
+        path: String,
+        atime: SystemTime,
+        mtime: SystemTime,
+    ) -> Result<(), FsError> {
+        self.set_times(path, atime, mtime, true, Some(String::from("utimes")))
+    }
+
+    // Like `utimes_sync`, but does not follow a symlink at `path`.
+    pub fn lutimes_sync(
+        &mut self,
+        path: String,
+        atime: SystemTime,
+        mtime: SystemTime,
+    ) -> Result<(), FsError> {
+        self.set_times(path, atime, mtime, false, Some(String::from("lutimes")))
+    }
+
+    // Like `utimes_sync`, for the file open as `fd`. Files read from a
+    // backend or a lower volume are copies and fail with ENOTSUP.
+    pub fn futimes_sync(
+        &mut self,
+        fd: usize,
+        atime: SystemTime,
+        mtime: SystemTime,
+    ) -> Result<(), FsError> {
+        let func_name = Some(String::from("futimes"));
+        let file = self.get_file_by_fd_or_throw(fd, func_name.clone())?;
+        if self.is_file_read_only(file) {
+            return Err(create_error(FSError::EROFS, func_name, None));
+        }
+        if file.detached {
+            return Err(create_error(FSError::ENOTSUP, func_name, None));
+        }
+        let node = file.node;
+        self.nodes[node].set_atime(atime);
+        self.nodes[node].set_mtime(mtime);
+        self.node_changed(node);
+        Ok(())
+    }
+
+    fn set_times(
+        &mut self,
+        path: String,
+        atime: SystemTime,
+        mtime: SystemTime,
+        follow_last: bool,
+        func_name: Option<String>,
+    ) -> Result<(), FsError> {
+        if self
+            .find_writable_mount(&path, follow_last, func_name.clone())?
+            .is_some()
+        {
+            return Err(create_error(FSError::ENOTSUP, func_name, Some(vec![path])));
+        }
+        self.copy_up(&path, follow_last, func_name.clone())?;
+        let link = if follow_last {
+            self.get_resolved_link_or_throw(path, func_name)?
+        } else {
+            self.get_link_or_throw(path, func_name)?
+        };
+        let node = self.links[link].node;
+        self.nodes[node].set_atime(atime);
+        self.nodes[node].set_mtime(mtime);
+        self.node_changed(node);
+        Ok(())
+    }
+
+    // Opens `path` as a `std::io` compatible handle, closed when dropped.
+    pub fn open_handle(
+        &mut self,
+        path: String,
+        flags: Option<String>,
+        mode: Option<usize>,
+    ) -> Result<FileHandle, FsError> {
+        let fd = self.open_sync(path, flags, mode)?;
+        Ok(FileHandle::from_fd(self.handle(), fd))
+    }
+
+    // Like `open_handle`, with `flags` already in numeric form.
+    pub fn open_handle_with_flags(
+        &mut self,
+        path: String,
+        flags: usize,
+        mode: Option<usize>,
+    ) -> Result<FileHandle, FsError> {
+        let fd = self.open_file(path, flags, mode.unwrap_or(0o666), true)?;
+        Ok(FileHandle::from_fd(self.handle(), fd))
+    }
+    pub fn create_read_stream(
+        &mut self,
+        path: String,
+        options: Option<ReadStreamOptions>,
+    ) -> Result<ReadStream, FsError> {
+        ReadStream::new(self.handle(), path, options.unwrap_or_default())
+    }
+
+    pub fn create_write_stream(
+        &mut self,
+        path: String,
+        options: Option<WriteStreamOptions>,
+    ) -> Result<WriteStream, FsError> {
+        WriteStream::new(self.handle(), path, options.unwrap_or_default())
+    }
+
+    pub fn watch(
+        &mut self,
+        path: String,
+        options: Option<FSWatcherOptions>,
+        listener: Option<WatchListener>,
+    ) -> Result<FSWatcher, FsError> {
+        let mut watcher = FSWatcher::start(self, path, options.unwrap_or_default(), false)?;
+        if let Some(listener) = listener {
+            watcher.on(listener);
+        }
+        Ok(watcher)
+    }
+
+    // Like `fs.promises.watch`: events are buffered until they are consumed
+    // through `FSWatcher::next`.
+    pub fn watch_async(
+        &mut self,
+        path: String,
+        options: Option<FSWatcherOptions>,
+    ) -> Result<FSWatcher, FsError> {
+        FSWatcher::start(self, path, options.unwrap_or_default(), true)
+    }
+
+    pub fn stat_sync(&mut self, path: String, bigint: Option<bool>) -> Result<Stats, FsError> {
+        let func_name = Some(String::from("stat"));
+        if let Some((mount, mounted_path)) = self.find_mount(&path, true, func_name.clone())? {
+            let stats = self.with_backend(mount, "stat", vec![path], |backend| {
+                backend.stat(&mounted_path)
+            })?;
+            return Ok(Stats {
+                dev: self.mounts[&mount].dev,
+                bigint: bigint.unwrap_or(false),
+                ..stats
+            });
+        }
+        if let Some(link) = self.find_lower(&path, true, func_name.clone())? {
+            let lower = self.lower_volume();
+            return Ok(Stats {
+                dev: LOWER_DEV,
+                ..Stats::build(lower.link_node(link), bigint.unwrap_or(false))
+            });
+        }
+        let link = self.get_resolved_link_or_throw(path, func_name)?;
+        let stats = Stats::build(self.link_node(link), bigint.unwrap_or(false));
+        Ok(stats)
+    }
+
+    // Watchers of the same file are shared, the returned id detaches this
+    // `listener` again through `unwatch_file`. Only a JS host polls them on
+    // its own: native embedders call `poll` on the watcher from
+    // `get_stat_watcher` every `interval`, while the volume is not borrowed.
+    pub fn watch_file(
+        &mut self,
+        path: String,
+        options: Option<StatWatcherOptions>,
+        listener: StatListener,
+    ) -> ListenerId {
+        let filename = util::path_resolve(vec![path], None);
+        let watcher = match self.stat_watchers.get(&filename) {
+            Some(watcher) => watcher.clone(),
+            None => {
+                let watcher =
+                    StatWatcher::start(self, filename.clone(), options.unwrap_or_default());
+                self.stat_watchers.insert(filename, watcher.clone());
+                watcher
+            }
+        };
+        watcher.add_listener(listener)
+    }
+
+    // Detaches `listener`, or every listener of the file when `None`. The
+    // watcher stops polling once it has no listeners left.
+    pub fn unwatch_file(&mut self, path: String, listener: Option<ListenerId>) {
+        let filename = util::path_resolve(vec![path], None);
+        let watcher = match self.stat_watchers.get(&filename) {
+            Some(watcher) => watcher.clone(),
+            None => return,
+        };
+        match listener {
+            Some(listener) => {
+                watcher.remove_listener(listener);
+            }
+            None => watcher.remove_all_listeners(),
+        }
+        if watcher.listener_count() == 0 {
+            watcher.stop();
+            self.stat_watchers.remove(&filename);
+        }
+    }
+
+    pub fn get_stat_watcher(&self, path: String) -> Option<StatWatcher> {
+        let filename = util::path_resolve(vec![path], None);
+        self.stat_watchers.get(&filename).cloned()
+    }
+
+    // Contents of all files below `paths` (the whole volume by default), keyed
+    // by their path or, with `is_relative`, by their path relative to the
+    // exported path. Empty directories map to `None`.
+    pub fn to_directory_json(
+        &self,
+        paths: Option<Vec<String>>,
+        is_relative: Option<bool>,
+    ) -> DirectoryJson {
+        let links: Vec<LinkId> = match paths {
+            Some(paths) => paths
+                .into_iter()
+                .filter_map(|path| self.get_resolved_link(path))
+                .collect(),
+            None => vec![self.root],
+        };
+        let mut json = DirectoryJson::new();
+        for link in links {
+            let path = if is_relative.unwrap_or(false) {
+                Some(self.get_path(link))
+            } else {
+                None
+            };
+            self.collect_directory_json(link, &path, &mut json);
+        }
+        json
+    }
+
+    fn collect_directory_json(
+        &self,
+        link: LinkId,
+        path: &Option<String>,
+        json: &mut DirectoryJson,
+    ) {
+        let relative = |filename: String| match path {
+            Some(path) => util::path_relative(path.clone(), filename, Some(String::from("/"))),
+            None => filename,
+        };
+        let (dir, children) = if self.link_node(link).is_file() {
+            (self.links[link].get_parent().unwrap(), vec![link])
+        } else {
+            let children = self.links[link].children.values().copied().collect();
+            (link, children)
+        };
+        for child in children.iter() {
+            let node = self.link_node(*child);
+            if node.is_file() {
+                let data = node.contents().map(Cow::into_owned).unwrap_or_default();
+                json.insert(relative(self.get_path(*child)), Some(data));
+            } else if node.is_directory() {
+                self.collect_directory_json(*child, path, json);
+            }
+        }
+        let dir_path = relative(self.get_path(dir));
+        if children.is_empty() && !dir_path.is_empty() {
+            json.insert(dir_path, None);
+        }
+    }
+
+    // Like memfs' `toJSON`: file contents are exported as strings, or as
+    // `Uint8Array`s with `as_buffer`, and empty directories as `null`.
+    #[cfg(feature = "wasm")]
+    pub fn to_json(
+        &mut self,
+        paths: Option<Vec<String>>,
+        json: Option<js_sys::Object>,
+        is_relative: Option<bool>,
+        as_buffer: Option<bool>,
+    ) -> js_sys::Object {
+        let json = json.unwrap_or_default();
+        let as_buffer = as_buffer.unwrap_or(false);
+        for (path, data) in self.to_directory_json(paths, is_relative) {
+            js_sys::Reflect::set(&json, &path.into(), &json::to_js_value(data, as_buffer)).unwrap();
+        }
+        json
+    }
+
+    // Counterpart of `from_nested_json`: every step of a path becomes an
+    // object, empty directories are `null`.
+    #[cfg(feature = "wasm")]
+    pub fn to_nested_json(
+        &mut self,
+        paths: Option<Vec<String>>,
+        is_relative: Option<bool>,
+        as_buffer: Option<bool>,
+    ) -> js_sys::Object {
+        let json = js_sys::Object::new();
+        let as_buffer = as_buffer.unwrap_or(false);
+        for (path, data) in self.to_directory_json(paths, is_relative) {
+            let mut steps: Vec<&str> = path.split('/').filter(|step| !step.is_empty()).collect();
+            let name = match steps.pop() {
+                Some(name) => name,
+                None => continue,
+            };
+            let mut dir = json.clone();
+            for step in steps {
+                let child = js_sys::Reflect::get(&dir, &step.into()).unwrap();
+                dir = if child.is_object() {
+                    js_sys::Object::from(child)
+                } else {
+                    let child = js_sys::Object::new();
+                    js_sys::Reflect::set(&dir, &step.into(), &child).unwrap();
+                    child
+                };
+            }
+            js_sys::Reflect::set(&dir, &name.into(), &json::to_js_value(data, as_buffer)).unwrap();
+        }
+        json
+    }
+
+    // Creates the given files, directories and symlinks along with their
+    // missing parent directories, overwriting existing files. Relative paths
+    // are resolved against `cwd`.
+    pub fn from_entries(
+        &mut self,
+        entries: Vec<(String, JsonEntry)>,
+        cwd: Option<String>,
+    ) -> Result<(), FsError> {
+        let cwd = cwd.unwrap_or_else(util::process_cwd);
+        let recursive = MkdirOptions {
+            recursive: Some(true),
+            ..Default::default()
+        };
+        let mut created = Vec::new();
+        for (filename, entry) in entries {
+            let filename = util::path_resolve(vec![filename], Some(cwd.clone()));
+            let dirname = util::path_resolve(
+                vec![filename.clone(), String::from("..")],
+                Some(cwd.clone()),
+            );
+            match &entry.kind {
+                JsonEntryKind::File(data) => {
+                    self.mkdir_sync(dirname, Some(recursive.clone()))?;
+                    self.write_file_sync(filename.clone(), data, None)?;
+                }
+                JsonEntryKind::Directory => {
+                    self.mkdir_sync(filename.clone(), Some(recursive.clone()))?;
+                }
+                JsonEntryKind::Symlink(target) => {
+                    self.mkdir_sync(dirname, Some(recursive.clone()))?;
+                    self.symlink_sync(target.clone(), filename.clone())?;
+                }
+            }
+            created.push((filename, entry));
+        }
+
+        // Only now that all entries exist, so that adding entries to a
+        // directory does not bump its mtime again.
+        for (filename, entry) in created {
+            let metadata = EntryMetadata {
+                mode: entry.mode,
+                uid: entry.uid,
+                gid: entry.gid,
+                mtime: entry.mtime,
+            };
+            self.set_metadata(filename, metadata, None)?;
+        }
+        Ok(())
+    }
+
+    // Counterpart of `to_directory_json`.
+    pub fn from_directory_json(
+        &mut self,
+        json: DirectoryJson,
+        cwd: Option<String>,
+    ) -> Result<(), FsError> {
+        let entries = json
+            .into_iter()
+            .map(|(filename, data)| (filename, data.into()))
+            .collect();
+        self.from_entries(entries, cwd)
+    }
+
+    // Like memfs' `fromJSON`, see `json::parse_entry` for the accepted values.
+    #[cfg(feature = "wasm")]
+    pub fn from_json(&mut self, json: js_sys::Object, cwd: Option<String>) -> Result<(), FsError> {
+        let mut entries = Vec::new();
+        for filename in js_sys::Object::keys(&json).iter() {
+            let filename = filename.as_string().unwrap();
+            let value = js_sys::Reflect::get(&json, &filename.clone().into()).unwrap();
+            match json::parse_entry(&value) {
+                Some(entry) => entries.push((filename, entry)),
+                None => return Err(create_error(FSError::EINVAL, None, Some(vec![filename]))),
+            }
+        }
+        self.from_entries(entries, cwd)
+    }
+
+    #[cfg(feature = "wasm")]
+    pub fn from_nested_json(
+        &mut self,
+        json: js_sys::Object,
+        cwd: Option<String>,
+    ) -> Result<(), FsError> {
+        self.from_json(util::flatten_json(json), cwd)
+    }
+
+    // Serialises everything reachable from the root, nodes with all their
+    // metadata as well as the tree of links, see the `snapshot` module. Open
+    // files and watchers are not part of it.
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        let mut snapshot = Snapshot::default();
+        let mut saved = HashSet::new();
+        let mut pending = vec![(self.root, None)];
+        while let Some((link, parent)) = pending.pop() {
+            let link = &self.links[link];
+            if saved.insert(link.node) {
+                snapshot.nodes.push(self.nodes[link.node].to_snapshot());
+            }
+            for child in link.children.values().rev() {
+                pending.push((*child, Some(snapshot.links.len())));
+            }
+            snapshot.links.push(SnapshotLink {
+                parent,
+                name: link.name.clone(),
+                ino: link.node.index(),
+            });
+        }
+        snapshot.encode()
+    }
+
+    // Restores a volume saved with `to_snapshot`, keeping I-node numbers.
+    // Fails with EINVAL on malformed or inconsistent input.
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Arc<RefCell<Volume>>, FsError> {
+        let mut snapshot = Snapshot::decode(bytes)?;
+        let invalid = || create_error(FSError::EINVAL, Some(String::from("from_snapshot")), None);
+        let max_ino = SNAPSHOT_INO_FACTOR * snapshot.nodes.len() + SNAPSHOT_INO_SLACK;
+        // In order, every slot is taken right after the arena grows to it.
+        snapshot.nodes.sort_by_key(|node| node.ino);
+        let mut nodes = Arena::new();
+        for node in snapshot.nodes {
+            if node.ino >= max_ino {
+                return Err(invalid());
+            }
+            nodes
+                .insert_at(InodeId::from_index(node.ino), Node::from_snapshot(node))
+                .map_err(|_| invalid())?;
+        }
+        let mut links: Arena<LinkId, Link> = Arena::new();
+        let mut ids = Vec::new();
+        for saved in snapshot.links {
+            let node = InodeId::from_index(saved.ino);
+            if !nodes.contains(node) {
+                return Err(invalid());
+            }
+            let parent = match saved.parent {
+                Some(parent) => Some(ids[parent]),
+                None if ids.is_empty() => None,
+                None => return Err(invalid()),
+            };
+            let link = links.insert(Link::new(parent, saved.name.clone(), node));
+            if let Some(parent) = parent {
+                let dir = &mut links[parent];
+                if !nodes[dir.node].is_directory()
+                    || saved.name.is_empty()
+                    || dir.children.insert(saved.name, link).is_some()
+                {
+                    return Err(invalid());
+                }
+            }
+            ids.push(link);
+        }
+        match ids.first() {
+            Some(root) if nodes[links[*root].node].is_directory() => {}
+            _ => return Err(invalid()),
+        }
+        // Link counts must be those the volume keeps itself: one per hard
+        // link, and for directories, which have a single link, two plus one
+        // per subdirectory.
+        let mut counts: HashMap<InodeId, (usize, usize)> = HashMap::new();
+        for (_, link) in links.iter() {
+            counts.entry(link.node).or_default().0 += 1;
+            if let Some(parent) = link.parent {
+                if nodes[link.node].is_directory() {
+                    counts.entry(links[parent].node).or_default().1 += 1;
+                }
+            }
+        }
+        for (id, node) in nodes.iter() {
+            let (hard_links, subdirs) = counts.get(&id).copied().unwrap_or_default();
+            let nlink = match node.is_directory() {
+                true if hard_links == 1 => 2 + subdirs,
+                false if hard_links > 0 => hard_links,
+                _ => return Err(invalid()),
+            };
+            if node.get_nlink() != nlink {
+                return Err(invalid());
+            }
+        }
+        let used = nodes.values().map(Node::get_size).sum();
+        Ok(Arc::new_cyclic(|this| {
+            RefCell::new(Volume {
+                this: this.clone(),
+                nodes,
+                links,
+                root: ids[0],
+                used,
+                ..Volume::default()
+            })
+        }))
+    }
+
+    // Extracts the tar archive `bytes` below `mount_point`, see `tar`.
+    pub fn import_tar(&mut self, bytes: &[u8], mount_point: String) -> Result<(), FsError> {
+        self.import_tar_from(bytes, mount_point)
+    }
+
+    // Like `import_tar`, reading the archive from `reader` one entry at a
+    // time. Entries replace existing files and keep their mode, owner and
+    // mtime, hard links share the node of their target. Entries that would
+    // end up outside of `mount_point` fail with EINVAL.
+    pub fn import_tar_from(
+        &mut self,
+        reader: impl Read,
+        mount_point: String,
+    ) -> Result<(), FsError> {
+        let func_name = Some(String::from("import_tar"));
+        let with_func_name = |err: FsError| FsError {
+            syscall: err.syscall.or(func_name.clone()),
+            ..err
+        };
+        let recursive = MkdirOptions {
+            recursive: Some(true),
+            ..Default::default()
+        };
+        let mount_point = util::path_resolve(vec![mount_point], None);
+        self.mkdir_sync(mount_point.clone(), Some(recursive))?;
+        let mount_point = self.realpath_sync(mount_point)?;
+
+        // Adding entries bumps the mtime of a directory, so directories get
+        // their metadata once everything is extracted.
+        let mut directories = Vec::new();
+        for entry in TarReader::new(reader) {
+            let entry = entry.map_err(|err| with_func_name(err.into()))?;
+            if entry.kind == TarEntryKind::Directory {
+                let path = resolve_archive_path(&mount_point, &entry.path, "import_tar")?;
+                self.make_archive_dir(&mount_point, &path, &entry.path, "import_tar")?;
+                directories.push((path, entry));
+                continue;
+            }
+            if let TarEntryKind::Other(_) = entry.kind {
+                continue;
+            }
+            let path = self.prepare_archive_path(&mount_point, &entry.path, "import_tar")?;
+            match &entry.kind {
+                TarEntryKind::File => self.write_file_sync(path.clone(), &entry.data, None)?,
+                TarEntryKind::Symlink(target) => self.symlink_sync(target.clone(), path.clone())?,
+                // The metadata belongs to the target.
+                TarEntryKind::HardLink(target) => {
+                    let resolved = resolve_archive_path(&mount_point, target, "import_tar")?;
+                    let dirname = util::path_join(vec![resolved.clone(), String::from("..")]);
+                    self.make_archive_dir(&mount_point, &dirname, target, "import_tar")?;
+                    self.link_sync(resolved, path)?;
+                    continue;
+                }
+                TarEntryKind::Directory | TarEntryKind::Other(_) => unreachable!(),
+            }
+            self.set_tar_metadata(path, &entry)?;
+        }
+        for (path, entry) in directories.into_iter().rev() {
+            self.set_tar_metadata(path, &entry)?;
+        }
+        Ok(())
+    }
+
+    // Resolves the archive entry `path` below `mount_point` and makes room
+    // for it: creates its parent and removes whatever non-directory is in the
+    // way. Fails with EINVAL if the parent, following symlinks extracted
+    // earlier, is outside of `mount_point`.
+    fn prepare_archive_path(
+        &mut self,
+        mount_point: &str,
+        path: &str,
+        func_name: &str,
+    ) -> Result<String, FsError> {
+        let resolved = resolve_archive_path(mount_point, path, func_name)?;
+        let dirname = util::path_join(vec![resolved.clone(), String::from("..")]);
+        self.make_archive_dir(mount_point, &dirname, path, func_name)?;
+        if let Ok(stats) = self.lstat_sync(resolved.clone(), None) {
+            if !stats.is_directory() {
+                self.unlink_sync(resolved.clone())?;
+            }
+        }
+        Ok(resolved)
+    }
+
+    // Creates the directory `dir` below `mount_point` one level at a time,
+    // for the archive entry `path`. Fails with EINVAL as soon as a level,
+    // following symlinks extracted earlier, leads outside of `mount_point`,
+    // before anything is created there.
+    fn make_archive_dir(
+        &mut self,
+        mount_point: &str,
+        dir: &str,
+        path: &str,
+        func_name: &str,
+    ) -> Result<(), FsError> {
+        let depth = util::filename_to_steps(String::from(mount_point), None).len();
+        let mut current = String::from(mount_point);
+        for step in util::filename_to_steps(String::from(dir), None).split_off(depth) {
+            current = util::path_join(vec![current, step]);
+            if !self.exists_sync(current.clone()) {
+                self.mkdir_sync(current.clone(), None)?;
+            }
+            if !is_inside(mount_point, &self.realpath_sync(current.clone())?) {
+                return Err(create_error(
+                    FSError::EINVAL,
+                    Some(String::from(func_name)),
+                    Some(vec![String::from(path)]),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn set_tar_metadata(&mut self, path: String, entry: &TarEntry) -> Result<(), FsError> {
+        let metadata = EntryMetadata {
+            mode: Some(entry.mode),
+            uid: Some(entry.uid),
+            gid: Some(entry.gid),
+            mtime: Some(entry.mtime),
+        };
+        self.set_metadata(path, metadata, Some(String::from("import_tar")))
+    }
+
+    // Archives everything below the directory `path` with paths relative to
+    // it, or the file `path` by its name.
+    pub fn export_tar(&self, path: String) -> Result<Vec<u8>, FsError> {
+        self.export_tar_to(path, Vec::new())
+    }
+
+    // Like `export_tar`, writing the archive to `writer` one file at a time.
+    // A node linked more than once is archived under its first name, its
+    // other names become hard links.
+    pub fn export_tar_to<W: Write>(&self, path: String, writer: W) -> Result<W, FsError> {
+        let func_name = Some(String::from("export_tar"));
+        let with_func_name = |err: std::io::Error| FsError {
+            syscall: func_name.clone(),
+            ..err.into()
+        };
+        let link = self.get_resolved_link_or_throw(path, func_name.clone())?;
+        let mut entries = self.tree_entries(link, None);
+        let base = if self.link_node(link).is_directory() {
+            entries.remove(0).path
+        } else {
+            self.get_path(self.links[link].parent.unwrap_or(link))
+        };
+
+        let mut tar = TarWriter::new(writer);
+        let mut archived = HashMap::new();
+        for entry in entries {
+            let node = &self.nodes[entry.ino];
+            let path = util::path_relative(base.clone(), entry.path, Some(String::from("/")));
+            let kind = if node.is_directory() {
+                TarEntryKind::Directory
+            } else if node.is_symlink() {
+                TarEntryKind::Symlink(node.target.clone())
+            } else if let Some(first) = archived.get(&entry.ino) {
+                TarEntryKind::HardLink(String::clone(first))
+            } else {
+                if node.get_nlink() > 1 {
+                    archived.insert(entry.ino, path.clone());
+                }
+                TarEntryKind::File
+            };
+            let data = match kind {
+                TarEntryKind::File => node.contents()?.into_owned(),
+                _ => Vec::new(),
+            };
+            let entry = TarEntry {
+                path,
+                kind,
+                mode: node.mode & 0o7777,
+                uid: node.get_uid(),
+                gid: node.get_gid(),
+                mtime: node.get_mtime(),
+                data,
+            };
+            tar.append(&entry).map_err(with_func_name)?;
+        }
+        tar.finish().map_err(with_func_name)
+    }
+
+    #[cfg(feature = "gzip")]
+    pub fn import_tar_gz(&mut self, bytes: &[u8], mount_point: String) -> Result<(), FsError> {
+        self.import_tar_from(flate2::read::GzDecoder::new(bytes), mount_point)
+    }
+
+    #[cfg(feature = "gzip")]
+    pub fn export_tar_gz(&self, path: String) -> Result<Vec<u8>, FsError> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        Ok(self.export_tar_to(path, encoder)?.finish()?)
+    }
+
+    // Extracts the zip archive `bytes` below `mount_point` like
+    // `import_tar`. With `lazy`, files are decompressed on first read and
+    // report the uncompressed size until then, see `ZipOptions`.
+    #[cfg(feature = "zip")]
+    pub fn import_zip(
+        &mut self,
+        bytes: &[u8],
+        mount_point: String,
+        options: Option<ZipOptions>,
+    ) -> Result<(), FsError> {
+        let lazy = options.unwrap_or_default().lazy.unwrap_or(false);
+        let recursive = MkdirOptions {
+            recursive: Some(true),
+            ..Default::default()
+        };
+        let mount_point = util::path_resolve(vec![mount_point], None);
+        let archive = ZipArchive::new(Arc::from(bytes))?;
+        self.mkdir_sync(mount_point.clone(), Some(recursive))?;
+        let mount_point = self.realpath_sync(mount_point)?;
+
+        let mut directories = Vec::new();
+        for entry in archive.entries() {
+            if entry.kind == ZipEntryKind::Directory {
+                let path = resolve_archive_path(&mount_point, &entry.path, "import_zip")?;
+                self.make_archive_dir(&mount_point, &path, &entry.path, "import_zip")?;
+                directories.push((path, entry));
+                continue;
+            }
+            let path = self.prepare_archive_path(&mount_point, &entry.path, "import_zip")?;
+            match entry.kind {
+                ZipEntryKind::Symlink => {
+                    let target = String::from_utf8_lossy(&archive.read(entry)?).into_owned();
+                    self.symlink_sync(target, path.clone())?;
+                }
+                ZipEntryKind::File if lazy => {
+                    self.write_file_sync(path.clone(), &[], None)?;
+                    let link = self.get_link_or_throw(path.clone(), None)?;
+                    let node = self.links[link].node;
+                    self.nodes[node].lazy = Some(LazyBuffer {
+                        size: entry.size,
+                        load: Arc::new(archive.read_later(entry)),
+                    });
+                    self.resized(node, 0);
+                }
+                ZipEntryKind::File => {
+                    self.write_file_sync(path.clone(), &archive.read(entry)?, None)?
+                }
+                ZipEntryKind::Directory => unreachable!(),
+            }
+            self.set_zip_metadata(path, entry)?;
+        }
+        for (path, entry) in directories.into_iter().rev() {
+            self.set_zip_metadata(path, entry)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "zip")]
+    fn set_zip_metadata(&mut self, path: String, entry: &ZipEntry) -> Result<(), FsError> {
+        let metadata = EntryMetadata {
+            mode: Some(entry.mode),
+            mtime: Some(entry.mtime),
+            ..Default::default()
+        };
+        self.set_metadata(path, metadata, Some(String::from("import_zip")))
+    }
+
+    // Archives everything below the directory `path` with paths relative to
+    // it, or the file `path` by its name. Hard links are archived as copies.
+    #[cfg(feature = "zip")]
+    pub fn export_zip(&self, path: String) -> Result<Vec<u8>, FsError> {
+        let func_name = Some(String::from("export_zip"));
+        let link = self.get_resolved_link_or_throw(path, func_name)?;
+        let mut entries = self.tree_entries(link, None);
+        let base = if self.link_node(link).is_directory() {
+            entries.remove(0).path
+        } else {
+            self.get_path(self.links[link].parent.unwrap_or(link))
+        };
+
+        let mut zip = ZipWriter::new(Vec::new());
+        for entry in entries {
+            let node = &self.nodes[entry.ino];
+            let path = util::path_relative(base.clone(), entry.path, Some(String::from("/")));
+            let (kind, data) = if node.is_directory() {
+                (ZipEntryKind::Directory, Cow::Borrowed(&[][..]))
+            } else if node.is_symlink() {
+                (ZipEntryKind::Symlink, Cow::Borrowed(node.target.as_bytes()))
+            } else {
+                (ZipEntryKind::File, node.contents()?)
+            };
+            zip.append(&path, kind, node.mode & 0o7777, node.get_mtime(), &data)?;
+        }
+        zip.finish()
+    }
+
+    // Drops all files and descriptors and starts over with an empty root,
+    // numbering inodes and descriptors from scratch. The random number
+    // generator, the quota, `noatime` and the lower volume of an overlay are
+    // kept, which drops all changes made to the overlay. Fails with EROFS if
+    // the volume is read-only.
+    pub fn reset(&mut self) -> Result<(), FsError> {
+        if self.read_only {
+            return Err(create_error(
+                FSError::EROFS,
+                Some(String::from("reset")),
+                None,
+            ));
+        }
+        for watcher in self.stat_watchers.values() {
+            watcher.stop();
+        }
+        let rng = std::mem::replace(&mut self.rng, platform::default_rng());
+        *self = Volume {
+            this: self.this.clone(),
+            rng,
+            quota: self.quota,
+            lower: self.lower.take(),
+            noatime: self.noatime,
+            ..Volume::default()
+        };
+        Ok(())
+    }
+
+    #[cfg(feature = "wasm")]
+    pub fn mount_sync(&mut self, mount_point: String, json: js_sys::Object) -> Result<(), FsError> {
+        self.from_json(json, Some(mount_point))
+    }
+}
+
+// `path` joined to `mount_point`, failing with EINVAL if that leads out of
+// it.
+fn resolve_archive_path(mount_point: &str, path: &str, func_name: &str) -> Result<String, FsError> {
+    let resolved = util::path_join(vec![String::from(mount_point), String::from(path)]);
+    if !is_inside(mount_point, &resolved) {
+        return Err(create_error(
+            FSError::EINVAL,
+            Some(String::from(func_name)),
+            Some(vec![String::from(path)]),
+        ));
+    }
+    Ok(resolved)
+}
+
+// Lets a volume be mounted inside another one, see `Volume::mount`. A volume
+// that is already borrowed, for instance because it is mounted inside itself
+// further down, fails with EBUSY.
+impl Backend for Arc<RefCell<Volume>> {
+    fn stat(&self, path: &str) -> Result<Stats, FsError> {
+        borrow_volume(self)?.stat_sync(path.to_string(), None)
+    }
+
+    fn lstat(&self, path: &str) -> Result<Stats, FsError> {
+        borrow_volume(self)?.lstat_sync(path.to_string(), None)
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, FsError> {
+        borrow_volume(self)?.read_file_sync(path.to_string())
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), FsError> {
+        borrow_volume(self)?.write_file_sync(path.to_string(), data, None)
+    }
+
+    fn readdir(&self, path: &str) -> Result<Vec<Dirent>, FsError> {
+        borrow_volume(self)?.readdir_with_file_types_sync(path.to_string())
+    }
+
+    fn mkdir(&mut self, path: &str, mode: usize) -> Result<(), FsError> {
+        let options = MkdirOptions {
+            recursive: None,
+            mode: Some(mode),
+        };
+        borrow_volume(self)?.mkdir_sync(path.to_string(), Some(options))?;
+        Ok(())
+    }
+
+    fn rmdir(&mut self, path: &str) -> Result<(), FsError> {
+        borrow_volume(self)?.rmdir_sync(path.to_string(), None)
+    }
+
+    fn rename(&mut self, old_path: &str, new_path: &str) -> Result<(), FsError> {
+        borrow_volume(self)?.rename_sync(old_path.to_string(), new_path.to_string())
+    }
+
+    fn unlink(&mut self, path: &str) -> Result<(), FsError> {
+        borrow_volume(self)?.unlink_sync(path.to_string())
+    }
+
+    fn symlink(&mut self, target: &str, path: &str) -> Result<(), FsError> {
+        borrow_volume(self)?.symlink_sync(target.to_string(), path.to_string())
+    }
+
+    fn readlink(&self, path: &str) -> Result<String, FsError> {
+        borrow_volume(self)?.readlink_sync(path.to_string())
+    }
+
+    fn chmod(&mut self, path: &str, mode: usize) -> Result<(), FsError> {
+        borrow_volume(self)?.chmod_sync(path.to_string(), mode)
+    }
+
+    fn link(&mut self, existing_path: &str, new_path: &str) -> Result<(), FsError> {
+        borrow_volume(self)?.link_sync(existing_path.to_string(), new_path.to_string())
+    }
+
+    fn realpath(&self, path: &str) -> Result<String, FsError> {
+        borrow_volume(self)?.realpath_sync(path.to_string())
+    }
+}
+
+fn borrow_volume(volume: &RefCell<Volume>) -> Result<RefMut<'_, Volume>, FsError> {
+    volume
+        .try_borrow_mut()
+        .map_err(|_| FsError::new(FSError::EBUSY))
+}
+
+// Removes the directory `path` of `backend` along with everything below it.
+fn remove_mounted_tree(backend: &mut dyn Backend, path: &str) -> Result<(), FsError> {
+    for dirent in backend.readdir(path)? {
+        let child = util::path_join(vec![String::from(path), dirent.name.clone()]);
+        if dirent.is_directory() {
+            remove_mounted_tree(backend, &child)?;
+        } else {
+            backend.unlink(&child)?;
+        }
+    }
+    backend.rmdir(path)
+}
+
+fn is_inside(directory: &str, path: &str) -> bool {
+    path == directory || path.starts_with(&format!("{}/", directory.trim_end_matches('/')))
+}
+
+fn diff_entry_kind(node: &Node) -> DiffEntryKind {
+    if node.is_directory() {
+        DiffEntryKind::Directory
+    } else if node.is_symlink() {
+        DiffEntryKind::Symlink
+    } else {
+        DiffEntryKind::File
+    }
+}
+
+// Directory and name of the absolute, normalized `path`.
+fn split_path(path: &str) -> (String, String) {
+    match path.rsplit_once('/') {
+        Some(("", name)) => (String::from("/"), String::from(name)),
+        Some((dir, name)) => (String::from(dir), String::from(name)),
+        None => (String::from("/"), String::from(path)),
+    }
+}
+
+#[test]
+fn test_write_and_read_sync() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    let fd = vol
+        .open_sync(String::from("/hello.txt"), Some(String::from("w+")), None)
+        .unwrap();
+    assert_eq!(
+        vol.write_sync(fd, b"hello world", None, None, None)
+            .unwrap(),
+        11
+    );
+    let mut buf = [0; 5];
+    assert_eq!(vol.read_sync(fd, &mut buf, None, None, Some(6)).unwrap(), 5);
+    assert_eq!(&buf, b"world");
+    vol.close_sync(fd).unwrap();
+
+    let stats = vol.stat_sync(String::from("/hello.txt"), None).unwrap();
+    assert!(stats.is_file());
+    assert_eq!(stats.size, 11);
+    assert!(stats.mtime_ms > 0);
+
+    let err = vol
+        .open_sync(String::from("/missing.txt"), None, None)
+        .unwrap_err();
+    assert_eq!(err.code, FSError::ENOENT);
+    assert_eq!(vol.close_sync(fd).unwrap_err().code, FSError::EBADF);
+}
+
+#[test]
+fn test_gen_rand_str_with_custom_rng() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    vol.set_rng(|| 0.5);
+    assert_eq!(vol.gen_rand_str(), "i00000");
+    vol.set_rng(|| 0.0);
+    assert_eq!(vol.gen_rand_str(), "000000");
+}
+
+#[test]
+fn test_errors_for_symlink_cycles_long_names_and_quota() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    let root = vol.root();
+    for (name, target) in [("a", "b"), ("b", "a")] {
+        let link = vol.create_link(root, String::from(name), None, None);
+        let node = vol.link(link).get_node();
+        vol.node_mut(node)
+            .make_symlink(String::from(target), vec![String::from(target)]);
+    }
+    let err = vol.open_sync(String::from("/a"), None, None).unwrap_err();
+    assert_eq!(err.code, FSError::ELOOP);
+
+    let long_name = format!("/{}", "x".repeat(MAX_NAME_LENGTH + 1));
+    let err = vol
+        .open_sync(long_name, Some(String::from("w")), None)
+        .unwrap_err();
+    assert_eq!(err.code, FSError::ENAMETOOLONG);
+
+    vol.set_quota(Some(8));
+    let fd = vol
+        .open_sync(String::from("/file"), Some(String::from("w")), None)
+        .unwrap();
+    assert_eq!(
+        vol.write_sync(fd, b"12345678", None, None, None).unwrap(),
+        8
+    );
+    assert_eq!(vol.write_sync(fd, b"1234", None, None, Some(4)).unwrap(), 4);
+    let err = vol.write_sync(fd, b"9", None, None, None).unwrap_err();
+    assert_eq!(err.code, FSError::ENOSPC);
+    assert_eq!(vol.used_bytes(), 8);
+
+    // Usage follows truncation and freeing, snapshots and forks keep theirs.
+    vol.ftruncate_sync(fd, Some(4)).unwrap();
+    assert_eq!(vol.used_bytes(), 4);
+    let id = vol.snapshot();
+    vol.unlink_sync(String::from("/file")).unwrap();
+    assert_eq!(vol.used_bytes(), 4);
+    vol.close_sync(fd).unwrap();
+    assert_eq!(vol.used_bytes(), 0);
+    vol.restore(id).unwrap();
+    assert_eq!(vol.used_bytes(), 4);
+    assert_eq!(vol.fork().borrow().used_bytes(), 4);
+    vol.write_file_sync(String::from("/file"), b"12", None)
+        .unwrap();
+    assert_eq!(vol.used_bytes(), 2);
+
+    // Files of mounted backends take no room in the volume.
+    vol.mkdir_sync(String::from("/data"), None).unwrap();
+    vol.mount(String::from("/data"), Volume::new(), None)
+        .unwrap();
+    vol.write_file_sync(String::from("/data/big"), b"123456789", None)
+        .unwrap();
+    assert_eq!(vol.used_bytes(), 2);
+}
+
+#[test]
+fn test_flock() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    vol.write_file_sync(String::from("/file"), b"x", None)
+        .unwrap();
+    let open = |vol: &mut Volume| vol.open_sync(String::from("/file"), None, None).unwrap();
+    let (a, b) = (open(&mut vol), open(&mut vol));
+    vol.flock_sync(a, Some(FileLock::Shared)).unwrap();
+    vol.flock_sync(b, Some(FileLock::Shared)).unwrap();
+    let err = vol.flock_sync(b, Some(FileLock::Exclusive)).unwrap_err();
+    assert_eq!(err.code, FSError::EAGAIN);
+    assert_eq!(err.errno, 11);
+    assert_eq!(
+        err.to_string(),
+        "EAGAIN: resource temporarily unavailable, flock"
+    );
+
+    // Upgrading works once the other lock is gone, closing releases it.
+    vol.flock_sync(a, None).unwrap();
+    vol.flock_sync(b, Some(FileLock::Exclusive)).unwrap();
+    let err = vol.flock_sync(a, Some(FileLock::Shared)).unwrap_err();
+    assert_eq!(err.code, FSError::EAGAIN);
+    vol.close_sync(b).unwrap();
+    vol.flock_sync(a, Some(FileLock::Exclusive)).unwrap();
+    let err = vol.flock_sync(b, None).unwrap_err();
+    assert_eq!(err.code, FSError::EBADF);
+}
+
+#[test]
+fn test_owners_and_times() {
+    use std::time::{Duration, SystemTime};
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    let file = String::from("/file");
+    let link = String::from("/link");
+    vol.write_file_sync(file.clone(), b"x", None).unwrap();
+    vol.symlink_sync(String::from("file"), link.clone())
+        .unwrap();
+    vol.chown_sync(link.clone(), 1000, 100).unwrap();
+    let stats = vol.stat_sync(file.clone(), None).unwrap();
+    assert_eq!((stats.uid, stats.gid), (1000, 100));
+
+    let (atime, mtime) = (platform::from_ms(1_000), platform::from_ms(2_000));
+    vol.utimes_sync(link.clone(), atime, mtime).unwrap();
+    let stats = vol.stat_sync(file.clone(), None).unwrap();
+    assert_eq!((stats.atime, stats.mtime), (atime, mtime));
+    vol.lutimes_sync(link.clone(), mtime, atime).unwrap();
+    let stats = vol.lstat_sync(link.clone(), None).unwrap();
+    assert_eq!((stats.atime, stats.mtime), (mtime, atime));
+    let stats = vol.stat_sync(file.clone(), None).unwrap();
+    assert_eq!(stats.mtime, mtime);
+
+    let fd = vol.open_sync(file.clone(), None, None).unwrap();
+    let later = SystemTime::UNIX_EPOCH + Duration::from_secs(60);
+    vol.futimes_sync(fd, later, later).unwrap();
+    let stats = vol.fstat_sync(fd, None).unwrap();
+    assert_eq!((stats.atime, stats.mtime), (later, later));
+    vol.close_sync(fd).unwrap();
+    let err = vol.futimes_sync(fd, later, later).unwrap_err();
+    assert_eq!(err.code, FSError::EBADF);
+
+    // Mounted backends keep their own owners and times.
+    vol.mkdir_sync(String::from("/data"), None).unwrap();
+    vol.mount(String::from("/data"), Volume::new(), None)
+        .unwrap();
+    let err = vol
+        .chown_sync(String::from("/data"), 1000, 100)
+        .unwrap_err();
+    assert_eq!(err.code, FSError::ENOTSUP);
+    let err = vol
+        .utimes_sync(String::from("/data"), atime, mtime)
+        .unwrap_err();
+    assert_eq!(err.code, FSError::ENOTSUP);
+}
+
+#[test]
+fn test_independent_volumes_and_reset() {
+    let first = Volume::new();
+    let second = Volume::new();
+    let path = String::from("/file");
+    for vol in [&first, &second] {
+        let mut vol = vol.borrow_mut();
+        let fd = vol
+            .open_sync(path.clone(), Some(String::from("w")), None)
+            .unwrap();
+        assert_eq!(fd, FIRST_FD);
+        assert_eq!(vol.stat_sync(path.clone(), None).unwrap().ino, 1);
+    }
+
+    let mut vol = first.borrow_mut();
+    vol.mkdir_sync(String::from("/dir"), None).unwrap();
+    vol.reset().unwrap();
+    assert!(!vol.exists_sync(path.clone()));
+    assert_eq!(vol.readdir_sync(String::from("/")).unwrap().len(), 0);
+    let fd = vol
+        .open_sync(path.clone(), Some(String::from("w")), None)
+        .unwrap();
+    assert_eq!(fd, FIRST_FD);
+    assert_eq!(vol.links.len(), 2);
+    assert_eq!(vol.nodes.len(), 2);
+}
+
+#[test]
+fn test_volume_events() {
+    use std::rc::Rc;
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    vol.mkdir_sync(String::from("/dir"), None).unwrap();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let log = events.clone();
+    let id = vol.subscribe(move |event| {
+        log.borrow_mut().push(match event {
+            VolumeEvent::Change { ino } => format!("change {}", ino.index()),
+            VolumeEvent::ChildAdd { dir, name, entries } => {
+                format!("add {} {} {}", dir, name, entries.len())
+            }
+            VolumeEvent::ChildDelete { dir, name } => format!("delete {} {}", dir, name),
+        })
+    });
+
+    vol.write_file_sync(String::from("/dir/a"), b"a", None)
+        .unwrap();
+    let ino = vol
+        .link(vol.get_link_or_throw(String::from("/dir/a"), None).unwrap())
+        .node
+        .index();
+    let changes_of_a = |events: &[String]| {
+        events
+            .iter()
+            .filter(|event| **event == format!("change {}", ino))
+            .count()
+    };
+    assert_eq!(events.borrow()[0], "add /dir a 1");
+    events.borrow_mut().clear();
+
+    vol.write_file_sync(String::from("/dir/a"), b"b", None)
+        .unwrap();
+    assert!(changes_of_a(&events.borrow()) > 0);
+    events.borrow_mut().clear();
+
+    vol.rename_sync(String::from("/dir"), String::from("/moved"))
+        .unwrap();
+    assert!(events.borrow().contains(&String::from("delete / dir")));
+    assert!(events.borrow().contains(&String::from("add / moved 2")));
+    events.borrow_mut().clear();
+
+    vol.unlink_sync(String::from("/moved/a")).unwrap();
+    assert!(events.borrow().contains(&String::from("delete /moved a")));
+    events.borrow_mut().clear();
+
+    assert!(vol.unsubscribe(id));
+    vol.write_file_sync(String::from("/b"), b"b", None).unwrap();
+    assert!(events.borrow().is_empty());
+}
+
+#[test]
+fn test_removed_entries_are_freed() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    let options = MkdirOptions {
+        recursive: Some(true),
+        ..Default::default()
+    };
+    vol.mkdir_sync(String::from("/a/b/c"), Some(options))
+        .unwrap();
+    for i in 0..10 {
+        vol.write_file_sync(format!("/a/b/{}", i), b"data", None)
+            .unwrap();
+    }
+    vol.link_sync(String::from("/a/b/0"), String::from("/hard"))
+        .unwrap();
+    vol.rename_sync(String::from("/a/b"), String::from("/moved"))
+        .unwrap();
+    assert_eq!(
+        vol.realpath_sync(String::from("/moved/c")).unwrap(),
+        "/moved/c"
+    );
+    assert_eq!(vol.links.len(), 15);
+
+    let fd = vol
+        .open_sync(String::from("/moved/1"), Some(String::from("r+")), None)
+        .unwrap();
+    let options = RmOptions {
+        recursive: Some(true),
+        ..Default::default()
+    };
+    vol.rm_sync(String::from("/moved"), Some(options)).unwrap();
+    // The root, "/a" and "/hard" are left, the unlinked file stays alive
+    // while it is open.
+    assert_eq!(vol.links.len(), 3);
+    assert_eq!(vol.nodes.len(), 4);
+    assert_eq!(vol.fstat_sync(fd, None).unwrap().nlink, 0);
+    vol.write_sync(fd, b"more", None, None, None).unwrap();
+    vol.close_sync(fd).unwrap();
+    assert_eq!(vol.nodes.len(), 3);
+    assert_eq!(vol.read_file_sync(String::from("/hard")).unwrap(), b"data");
+}
+
+#[test]
+fn test_to_directory_json() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    let options = MkdirOptions {
+        recursive: Some(true),
+        ..Default::default()
+    };
+    vol.mkdir_sync(String::from("/app/empty"), Some(options))
+        .unwrap();
+    vol.write_file_sync(String::from("/app/bin"), &[0xff, 0x00], None)
+        .unwrap();
+    vol.write_file_sync(String::from("/readme"), b"hi", None)
+        .unwrap();
+
+    let json = vol.to_directory_json(None, None);
+    let expected: DirectoryJson = [
+        ("/app/bin", Some(vec![0xff, 0x00])),
+        ("/app/empty", None),
+        ("/readme", Some(b"hi".to_vec())),
+    ]
+    .into_iter()
+    .map(|(path, data)| (String::from(path), data))
+    .collect();
+    assert_eq!(json, expected);
+
+    let json = vol.to_directory_json(Some(vec![String::from("/app")]), Some(true));
+    assert_eq!(json.keys().collect::<Vec<_>>(), ["bin", "empty"]);
+    let json = vol.to_directory_json(Some(vec![String::from("/readme")]), None);
+    assert_eq!(json.keys().collect::<Vec<_>>(), ["/readme"]);
+
+    vol.reset().unwrap();
+    let json = vol.to_directory_json(None, None);
+    assert_eq!(json.get("/"), Some(&None));
+}
+
+#[test]
+fn test_from_entries() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    let mtime = platform::from_ms(1_000_000);
+    let entries = vec![
+        (
+            String::from("app/bin"),
+            JsonEntry {
+                mode: Some(0o100755),
+                mtime: Some(mtime),
+                uid: Some(42),
+                ..JsonEntry::new(JsonEntryKind::File(vec![0xff, 0x00]))
+            },
+        ),
+        (String::from("/app/empty"), Some(Vec::new()).into()),
+        (String::from("/app/logs/"), None.into()),
+        (
+            String::from("/link"),
+            JsonEntry::new(JsonEntryKind::Symlink(String::from("app/bin"))),
+        ),
+    ];
+    vol.from_entries(entries, Some(String::from("/"))).unwrap();
+
+    assert_eq!(
+        vol.read_file_sync(String::from("/link")).unwrap(),
+        [0xff, 0x00]
+    );
+    assert_eq!(vol.readlink_sync(String::from("/link")).unwrap(), "app/bin");
+    let stats = vol.stat_sync(String::from("/app/bin"), None).unwrap();
+    assert_eq!(stats.mode, constants::S_IFREG | 0o755);
+    assert_eq!(stats.mtime, mtime);
+    assert_eq!(stats.uid, 42);
+    assert!(vol
+        .stat_sync(String::from("/app/logs"), None)
+        .unwrap()
+        .is_directory());
+
+    let json = vol.to_directory_json(Some(vec![String::from("/app")]), None);
+    let copy = Volume::new();
+    copy.borrow_mut()
+        .from_directory_json(json.clone(), None)
+        .unwrap();
+    assert_eq!(copy.borrow().to_directory_json(None, None), json);
+
+    let err = vol
+        .from_directory_json(
+            [(String::from("/app/bin/nested"), None)]
+                .into_iter()
+                .collect(),
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(err.code, FSError::ENOTDIR);
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    let options = MkdirOptions {
+        recursive: Some(true),
+        ..Default::default()
+    };
+    vol.mkdir_sync(String::from("/app/empty"), Some(options))
+        .unwrap();
+    vol.write_file_sync(String::from("/app/bin"), &[0xff, 0x00, 0x80], None)
+        .unwrap();
+    vol.link_sync(String::from("/app/bin"), String::from("/bin"))
+        .unwrap();
+    vol.symlink_sync(String::from("app"), String::from("/current"))
+        .unwrap();
+    vol.chmod_sync(String::from("/app/bin"), 0o754).unwrap();
+    // Freed slots must not shift I-node numbers on the way back.
+    vol.write_file_sync(String::from("/gone"), b"", None)
+        .unwrap();
+    vol.unlink_sync(String::from("/gone")).unwrap();
+    let bin = vol.get_link(vec![String::from("bin")]).unwrap();
+    let ino = vol.links[bin].node;
+    vol.nodes[ino].chown(1000, 100);
+    vol.nodes[ino].set_mtime(platform::from_ms(1_234_567));
+
+    let bytes = vol.to_snapshot();
+    let copy = Volume::from_snapshot(&bytes).unwrap();
+    let mut copy = copy.borrow_mut();
+    assert_eq!(copy.to_snapshot(), bytes);
+
+    let stats = copy.lstat_sync(String::from("/app/bin"), None).unwrap();
+    let expected = vol.lstat_sync(String::from("/app/bin"), None).unwrap();
+    assert_eq!(stats.ino, expected.ino);
+    assert_eq!(stats.mode, constants::S_IFREG | 0o754);
+    assert_eq!((stats.uid, stats.gid, stats.nlink), (1000, 100, 2));
+    assert_eq!(stats.mtime, platform::from_ms(1_234_567));
+    assert_eq!(stats.ctime, expected.ctime);
+    assert!(copy
+        .lstat_sync(String::from("/current"), None)
+        .unwrap()
+        .is_symbolic_link());
+    assert_eq!(copy.readlink_sync(String::from("/current")).unwrap(), "app");
+    assert_eq!(
+        copy.read_file_sync(String::from("/current/bin")).unwrap(),
+        [0xff, 0x00, 0x80]
+    );
+
+    // Both names still share one node.
+    copy.write_file_sync(String::from("/bin"), b"new", None)
+        .unwrap();
+    assert_eq!(
+        copy.read_file_sync(String::from("/app/bin")).unwrap(),
+        b"new"
+    );
+    copy.write_file_sync(String::from("/app/empty/file"), b"", None)
+        .unwrap();
+
+    let err = Volume::from_snapshot(&bytes[..bytes.len() - 1])
+        .err()
+        .unwrap();
+    assert_eq!(err.code, FSError::EINVAL);
+    assert!(Volume::from_snapshot(b"WFSS\x02").is_err());
+
+    // Link counts and I-node numbers come from untrusted input.
+    let snapshot = Snapshot::decode(&bytes).unwrap();
+    let mut forged = snapshot.clone();
+    let bin = forged
+        .nodes
+        .iter_mut()
+        .find(|node| node.buf.is_some())
+        .unwrap();
+    assert_eq!(bin.nlink, 2);
+    bin.nlink = 1;
+    let err = Volume::from_snapshot(&forged.encode()).err().unwrap();
+    assert_eq!(err.code, FSError::EINVAL);
+    let mut forged = snapshot.clone();
+    let ino = forged.nodes.last().unwrap().ino;
+    forged.nodes.last_mut().unwrap().ino = usize::MAX / 2;
+    for link in forged.links.iter_mut().filter(|link| link.ino == ino) {
+        link.ino = usize::MAX / 2;
+    }
+    let err = Volume::from_snapshot(&forged.encode()).err().unwrap();
+    assert_eq!(err.code, FSError::EINVAL);
+}
+
+#[test]
+fn test_import_and_export_tar() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    let archive = include_bytes!("../fixtures/site.tar");
+    vol.import_tar(archive, String::from("/srv")).unwrap();
+
+    let copy = vol
+        .lstat_sync(String::from("/srv/site/copy.html"), None)
+        .unwrap();
+    let index = vol
+        .lstat_sync(String::from("/srv/site/index.html"), None)
+        .unwrap();
+    assert_eq!(copy.ino, index.ino);
+    assert_eq!(copy.nlink, 2);
+    assert_eq!(copy.mode, constants::S_IFREG | 0o640);
+    assert_eq!((copy.uid, copy.gid), (1000, 100));
+    let site = vol.stat_sync(String::from("/srv/site"), None).unwrap();
+    assert_eq!(site.mtime, platform::from_ms(1_600_000_000_000));
+    assert_eq!(
+        vol.readlink_sync(String::from("/srv/site/link")).unwrap(),
+        "index.html"
+    );
+
+    let exported = vol.export_tar(String::from("/srv")).unwrap();
+    let other = Volume::new();
+    let mut other = other.borrow_mut();
+    other
+        .import_tar_from(&exported[..], String::from("/"))
+        .unwrap();
+    assert_eq!(
+        other.to_directory_json(Some(vec![String::from("/site")]), Some(true)),
+        vol.to_directory_json(Some(vec![String::from("/srv/site")]), Some(true))
+    );
+    let copy = other
+        .lstat_sync(String::from("/site/copy.html"), None)
+        .unwrap();
+    assert_eq!(copy.nlink, 2);
+    // Symlinks keep their target as given, relative ones still resolve
+    // below the new path.
+    assert_eq!(
+        other.readlink_sync(String::from("/site/link")).unwrap(),
+        "index.html"
+    );
+    assert_eq!(
+        other
+            .stat_sync(String::from("/site/link"), None)
+            .unwrap()
+            .ino,
+        other
+            .stat_sync(String::from("/site/index.html"), None)
+            .unwrap()
+            .ino
+    );
+    assert_eq!(copy.mtime, platform::from_ms(1_600_000_000_000));
+
+    let single = vol.export_tar(String::from("/srv/site/copy.html")).unwrap();
+    other.import_tar(&single, String::from("/single")).unwrap();
+    assert!(other.exists_sync(String::from("/single/copy.html")));
+
+    let mut writer = TarWriter::new(Vec::new());
+    let escape = TarEntry {
+        path: String::from("../../escape"),
+        kind: TarEntryKind::File,
+        mode: 0o644,
+        uid: 0,
+        gid: 0,
+        mtime: platform::now(),
+        data: Vec::new(),
+    };
+    writer.append(&escape).unwrap();
+    let err = other
+        .import_tar(&writer.finish().unwrap(), String::from("/jail"))
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EINVAL);
+    assert!(!other.exists_sync(String::from("/escape")));
+
+    // Directories and hard link targets can not reach out through a symlink
+    // extracted before them either.
+    other
+        .write_file_sync(String::from("/secret"), b"secret", None)
+        .unwrap();
+    let root_mtime = other.stat_sync(String::from("/"), None).unwrap().mtime;
+    for (path, kind) in [
+        ("evil/pwned", TarEntryKind::Directory),
+        ("evil", TarEntryKind::Directory),
+        (
+            "stolen",
+            TarEntryKind::HardLink(String::from("evil/secret")),
+        ),
+    ] {
+        let mut writer = TarWriter::new(Vec::new());
+        let symlink = TarEntry {
+            path: String::from("evil"),
+            kind: TarEntryKind::Symlink(String::from("/")),
+            ..escape.clone()
+        };
+        writer.append(&symlink).unwrap();
+        let entry = TarEntry {
+            path: String::from(path),
+            kind,
+            ..escape.clone()
+        };
+        writer.append(&entry).unwrap();
+        let err = other
+            .import_tar(&writer.finish().unwrap(), String::from("/jail"))
+            .unwrap_err();
+        assert_eq!(err.code, FSError::EINVAL);
+    }
+    assert!(!other.exists_sync(String::from("/pwned")));
+    assert!(!other.exists_sync(String::from("/jail/stolen")));
+    let stats = other.stat_sync(String::from("/"), None).unwrap();
+    assert_eq!(stats.mtime, root_mtime);
+
+    #[cfg(feature = "gzip")]
+    {
+        let compressed = vol.export_tar_gz(String::from("/srv")).unwrap();
+        other
+            .import_tar_gz(&compressed, String::from("/gz"))
+            .unwrap();
+        assert_eq!(
+            other.to_directory_json(Some(vec![String::from("/gz/site")]), Some(true)),
+            vol.to_directory_json(Some(vec![String::from("/srv/site")]), Some(true))
+        );
+    }
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn test_import_and_export_zip() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    let archive = include_bytes!("../fixtures/site.zip");
+    vol.import_zip(archive, String::from("/srv"), None).unwrap();
+
+    let index = vol
+        .lstat_sync(String::from("/srv/site/index.html"), None)
+        .unwrap();
+    assert_eq!(index.mode, constants::S_IFREG | 0o640);
+    assert_eq!(index.mtime, platform::from_ms(1_600_000_000_000));
+    let assets = vol
+        .stat_sync(String::from("/srv/site/assets"), None)
+        .unwrap();
+    assert_eq!(assets.mode, constants::S_IFDIR | 0o750);
+    assert_eq!(assets.mtime, platform::from_ms(1_600_000_000_000));
+    assert_eq!(
+        vol.readlink_sync(String::from("/srv/site/link")).unwrap(),
+        "index.html"
+    );
+
+    let lazy = Some(ZipOptions { lazy: Some(true) });
+    vol.import_zip(archive, String::from("/lazy"), lazy.clone())
+        .unwrap();
+    let stats = vol
+        .stat_sync(String::from("/lazy/site/index.html"), None)
+        .unwrap();
+    assert_eq!(stats.size, index.size);
+    let link = vol
+        .get_link_or_throw(String::from("/lazy/site/index.html"), None)
+        .unwrap();
+    assert!(vol.link_node(link).lazy.is_some());
+    assert_eq!(
+        vol.read_file_sync(String::from("/lazy/site/index.html"))
+            .unwrap(),
+        vol.read_file_sync(String::from("/srv/site/index.html"))
+            .unwrap()
+    );
+    assert!(vol.link_node(link).lazy.is_none());
+
+    let exported = vol.export_zip(String::from("/srv")).unwrap();
+    let other = Volume::new();
+    let mut other = other.borrow_mut();
+    other
+        .import_zip(&exported, String::from("/"), None)
+        .unwrap();
+    assert_eq!(
+        other.to_directory_json(Some(vec![String::from("/site")]), Some(true)),
+        vol.to_directory_json(Some(vec![String::from("/srv/site")]), Some(true))
+    );
+    let run = other.stat_sync(String::from("/site/run.sh"), None).unwrap();
+    assert_eq!(run.mode, constants::S_IFREG | 0o755);
+    assert_eq!(
+        other.readlink_sync(String::from("/site/link")).unwrap(),
+        "index.html"
+    );
+    assert!(other.exists_sync(String::from("/site/link")));
+
+    // A damaged entry fails when it is read, not when it is imported.
+    let data = exported
+        .windows(4)
+        .position(|window| window == b"#!/b")
+        .unwrap();
+    let mut corrupt = exported.clone();
+    corrupt[data] = b'!';
+    other
+        .import_zip(&corrupt, String::from("/corrupt"), lazy)
+        .unwrap();
+    let err = other
+        .read_file_sync(String::from("/corrupt/site/run.sh"))
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EIO);
+    let err = other
+        .import_zip(&corrupt, String::from("/eager"), None)
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EIO);
+
+    // Directories can not reach out through a symlink extracted before them.
+    other.mkdir_sync(String::from("/jail"), None).unwrap();
+    let root_mtime = other.stat_sync(String::from("/"), None).unwrap().mtime;
+    for path in ["evil/pwned", "evil"] {
+        let mut zip = ZipWriter::new(Vec::new());
+        let mtime = platform::now();
+        zip.append("evil", ZipEntryKind::Symlink, 0o777, mtime, b"/")
+            .unwrap();
+        zip.append(path, ZipEntryKind::Directory, 0o755, mtime, b"")
+            .unwrap();
+        let err = other
+            .import_zip(&zip.finish().unwrap(), String::from("/jail"), None)
+            .unwrap_err();
+        assert_eq!(err.code, FSError::EINVAL);
+    }
+    assert!(!other.exists_sync(String::from("/pwned")));
+    let stats = other.stat_sync(String::from("/"), None).unwrap();
+    assert_eq!(stats.mtime, root_mtime);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_mount_host_directory() {
+    use crate::host::HostDirectory;
+    let dir = std::env::temp_dir().join(format!("wasmfs-mount-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib/main.rs"), "fn main() {}\n").unwrap();
+
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    vol.mkdir_sync(String::from("/src"), None).unwrap();
+    let host = HostDirectory::new(dir.clone(), None).unwrap();
+    vol.mount_backend(String::from("/src"), Box::new(host), None)
+        .unwrap();
+    let host = HostDirectory::new(dir.clone(), None).unwrap();
+    let err = vol
+        .mount_backend(String::from("/src"), Box::new(host), None)
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EBUSY);
+
+    assert_eq!(vol.readdir_sync(String::from("/src")).unwrap(), ["lib"]);
+    assert_eq!(
+        vol.read_file_sync(String::from("/src/lib/main.rs"))
+            .unwrap(),
+        b"fn main() {}\n"
+    );
+    let stats = vol
+        .stat_sync(String::from("/src/lib/main.rs"), None)
+        .unwrap();
+    assert_eq!(stats.size, 13);
+    vol.symlink_sync(String::from("/src/lib"), String::from("/lib"))
+        .unwrap();
+    assert!(vol.exists_sync(String::from("/lib/main.rs")));
+    std::os::unix::fs::symlink("lib/main.rs", dir.join("entry")).unwrap();
+    for path in ["/lib/main.rs", "/src/entry", "/src/lib/../entry"] {
+        assert_eq!(
+            vol.realpath_sync(String::from(path)).unwrap(),
+            "/src/lib/main.rs"
+        );
+    }
+    assert_eq!(vol.realpath_sync(String::from("/lib")).unwrap(), "/src/lib");
+    let err = vol.realpath_sync(String::from("/src/missing")).unwrap_err();
+    assert_eq!(err.code, FSError::ENOENT);
+    std::fs::remove_file(dir.join("entry")).unwrap();
+
+    // Writes through file descriptors reach the host right away.
+    vol.write_file_sync(String::from("/lib/out.txt"), b"out", None)
+        .unwrap();
+    let fd = vol
+        .open_sync(
+            String::from("/src/lib/out.txt"),
+            Some(String::from("a")),
+            None,
+        )
+        .unwrap();
+    vol.write_sync(fd, b"put", None, None, None).unwrap();
+    assert_eq!(vol.fstat_sync(fd, None).unwrap().size, 6);
+    assert_eq!(std::fs::read(dir.join("lib/out.txt")).unwrap(), b"output");
+    std::fs::write(dir.join("lib/out.txt"), b"out").unwrap();
+    vol.write_sync(fd, b"put", None, None, None).unwrap();
+    vol.close_sync(fd).unwrap();
+    assert_eq!(std::fs::read(dir.join("lib/out.txt")).unwrap(), b"output");
+
+    let created = vol
+        .mkdir_sync(
+            String::from("/src/a/b"),
+            Some(MkdirOptions {
+                recursive: Some(true),
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+    assert_eq!(created.as_deref(), Some("/src/a"));
+    vol.rename_sync(
+        String::from("/src/lib/out.txt"),
+        String::from("/src/a/b/out.txt"),
+    )
+    .unwrap();
+    assert!(dir.join("a/b/out.txt").exists());
+    vol.copy_file_sync(
+        String::from("/src/a/b/out.txt"),
+        String::from("/copy.txt"),
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        vol.read_file_sync(String::from("/copy.txt")).unwrap(),
+        b"output"
+    );
+
+    let err = vol
+        .rename_sync(String::from("/copy.txt"), String::from("/src/copy.txt"))
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EXDEV);
+    let err = vol
+        .link_sync(String::from("/copy.txt"), String::from("/src/copy.txt"))
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EXDEV);
+    let err = vol
+        .rmdir_sync(String::from("/src"), Some(true))
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EBUSY);
+    vol.chmod_sync(String::from("/src/lib/main.rs"), 0o600)
+        .unwrap();
+    let stats = vol
+        .lstat_sync(String::from("/src/lib/main.rs"), None)
+        .unwrap();
+    assert_eq!(stats.mode & 0o777, 0o600);
+    assert_ne!(stats.dev, 0);
+
+    vol.rm_sync(
+        String::from("/src/a"),
+        Some(RmOptions {
+            recursive: Some(true),
+            ..Default::default()
+        }),
+    )
+    .unwrap();
+    vol.unlink_sync(String::from("/src/lib/main.rs")).unwrap();
+    assert_eq!(vol.readdir_sync(String::from("/src/lib")).unwrap().len(), 0);
+    assert!(!dir.join("a").exists());
+    let err = vol
+        .read_file_sync(String::from("/src/lib/main.rs"))
+        .unwrap_err();
+    assert_eq!(err.code, FSError::ENOENT);
+    assert_eq!(err.path.as_deref(), Some("/src/lib/main.rs"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_mount_volumes() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    vol.mkdir_sync(String::from("/data"), None).unwrap();
+    vol.write_file_sync(String::from("/data/hidden.txt"), b"hidden", None)
+        .unwrap();
+    vol.mkdir_sync(String::from("/cache"), None).unwrap();
+
+    let data = Volume::new();
+    data.borrow_mut()
+        .write_file_sync(String::from("/notes.txt"), b"notes", None)
+        .unwrap();
+    let cache = Volume::new();
+    assert_eq!(
+        vol.mount(String::from("/data"), data.clone(), None)
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        vol.mount(String::from("/cache"), cache.clone(), None)
+            .unwrap(),
+        2
+    );
+    let this = vol.handle();
+    let err = vol.mount(String::from("/cache"), this, None).unwrap_err();
+    assert_eq!(err.code, FSError::EINVAL);
+    data.borrow_mut()
+        .symlink_sync(String::from("/notes.txt"), String::from("/latest"))
+        .unwrap();
+    assert_eq!(
+        vol.realpath_sync(String::from("/data/latest")).unwrap(),
+        "/data/notes.txt"
+    );
+    data.borrow_mut()
+        .unlink_sync(String::from("/latest"))
+        .unwrap();
+    assert_eq!(
+        vol.mounts(),
+        [
+            MountInfo {
+                path: String::from("/cache"),
+                dev: 2,
+                read_only: false,
+            },
+            MountInfo {
+                path: String::from("/data"),
+                dev: 1,
+                read_only: false,
+            },
+        ]
+    );
+
+    // Traversal crosses into the mounted volume and covers the directory.
+    assert_eq!(
+        vol.readdir_sync(String::from("/data")).unwrap(),
+        ["notes.txt"]
+    );
+    vol.write_file_sync(String::from("/data/todo.txt"), b"todo", None)
+        .unwrap();
+    assert_eq!(
+        data.borrow_mut()
+            .read_file_sync(String::from("/todo.txt"))
+            .unwrap(),
+        b"todo"
+    );
+    vol.link_sync(
+        String::from("/data/todo.txt"),
+        String::from("/data/again.txt"),
+    )
+    .unwrap();
+    assert_eq!(
+        data.borrow_mut()
+            .stat_sync(String::from("/again.txt"), None)
+            .unwrap()
+            .nlink,
+        2
+    );
+    let root = vol.stat_sync(String::from("/"), None).unwrap();
+    let notes = vol
+        .stat_sync(String::from("/data/notes.txt"), None)
+        .unwrap();
+    let cached = vol.stat_sync(String::from("/cache"), None).unwrap();
+    assert_eq!((root.dev, notes.dev, cached.dev), (0, 1, 2));
+
+    let err = vol
+        .rename_sync(
+            String::from("/data/notes.txt"),
+            String::from("/cache/notes.txt"),
+        )
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EXDEV);
+    let err = vol
+        .link_sync(String::from("/data/notes.txt"), String::from("/notes.txt"))
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EXDEV);
+    let err = vol.rmdir_sync(String::from("/cache"), None).unwrap_err();
+    assert_eq!(err.code, FSError::EBUSY);
+
+    // Writes through one fd are seen by other fds and paths right away.
+    let notes = String::from("/data/notes.txt");
+    let first = vol
+        .open_sync(notes.clone(), Some(String::from("r+")), None)
+        .unwrap();
+    let second = vol
+        .open_sync(notes.clone(), Some(String::from("a+")), None)
+        .unwrap();
+    vol.write_sync(first, b"N", None, None, None).unwrap();
+    assert_eq!(vol.read_file_sync(notes.clone()).unwrap(), b"Notes");
+    vol.write_sync(second, b"!", None, None, None).unwrap();
+    let mut buffer = [0; 6];
+    vol.read_sync(first, &mut buffer, None, None, Some(0))
+        .unwrap();
+    assert_eq!(&buffer, b"Notes!");
+    assert_eq!(vol.fstat_sync(first, None).unwrap().size, 6);
+    vol.ftruncate_sync(second, Some(5)).unwrap();
+    assert_eq!(vol.stat_sync(notes.clone(), None).unwrap().size, 5);
+    vol.close_sync(second).unwrap();
+    vol.close_sync(first).unwrap();
+    assert_eq!(
+        data.borrow_mut()
+            .read_file_sync(String::from("/notes.txt"))
+            .unwrap(),
+        b"Notes"
+    );
+
+    // Open files keep the mount busy, unmounting uncovers the directory.
+    let fd = vol
+        .open_sync(String::from("/data/notes.txt"), None, None)
+        .unwrap();
+    let err = vol.umount(String::from("/data")).err().unwrap();
+    assert_eq!(err.code, FSError::EBUSY);
+    vol.close_sync(fd).unwrap();
+    vol.umount(String::from("/data")).unwrap();
+    assert_eq!(
+        vol.readdir_sync(String::from("/data")).unwrap(),
+        ["hidden.txt"]
+    );
+    let err = vol.umount(String::from("/data")).err().unwrap();
+    assert_eq!(err.code, FSError::EINVAL);
+    assert_eq!(vol.mounts().len(), 1);
+}
+
+#[test]
+fn test_overlay() {
+    let lower = Volume::new();
+    {
+        let mut lower = lower.borrow_mut();
+        let recursive = Some(MkdirOptions {
+            recursive: Some(true),
+            ..Default::default()
+        });
+        lower.mkdir_sync(String::from("/etc"), None).unwrap();
+        lower
+            .mkdir_sync(String::from("/usr/lib"), recursive.clone())
+            .unwrap();
+        lower
+            .mkdir_sync(String::from("/var/log"), recursive)
+            .unwrap();
+        lower
+            .write_file_sync(String::from("/etc/hosts"), b"127.0.0.1", None)
+            .unwrap();
+        lower
+            .write_file_sync(String::from("/etc/motd"), b"hi", None)
+            .unwrap();
+        lower
+            .write_file_sync(String::from("/usr/lib/libc.so"), b"elf", None)
+            .unwrap();
+        lower
+            .symlink_sync(String::from("/usr/lib"), String::from("/lib"))
+            .unwrap();
+    }
+    let overlay = Volume::overlay(lower.clone()).unwrap();
+    let mut vol = overlay.borrow_mut();
+    let lower_file = |path: &str| lower.borrow_mut().read_file_sync(String::from(path));
+    let err = lower
+        .borrow_mut()
+        .write_file_sync(String::from("/etc/issue"), b"", None)
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EROFS);
+
+    // Reads fall through without copying anything up.
+    assert_eq!(
+        vol.read_file_sync(String::from("/lib/libc.so")).unwrap(),
+        b"elf"
+    );
+    assert_eq!(
+        vol.readdir_sync(String::from("/")).unwrap(),
+        ["etc", "lib", "usr", "var"]
+    );
+    let stats = vol.lstat_sync(String::from("/lib"), None).unwrap();
+    assert!(stats.is_symbolic_link());
+    let fd = vol
+        .open_sync(String::from("/etc/hosts"), None, None)
+        .unwrap();
+    let mut buf = [0; 9];
+    vol.read_sync(fd, &mut buf, None, None, None).unwrap();
+    vol.close_sync(fd).unwrap();
+    assert_eq!(&buf, b"127.0.0.1");
+    assert!(vol.link(vol.root()).children.is_empty());
+
+    // Writes copy files up and leave the lower volume alone.
+    vol.write_file_sync(String::from("/etc/hosts"), b"::1", None)
+        .unwrap();
+    let fd = vol
+        .open_sync(String::from("/etc/motd"), Some(String::from("a")), None)
+        .unwrap();
+    vol.write_sync(fd, b" there", None, None, None).unwrap();
+    vol.close_sync(fd).unwrap();
+    vol.write_file_sync(String::from("/etc/issue"), b"", None)
+        .unwrap();
+    assert_eq!(
+        vol.read_file_sync(String::from("/etc/motd")).unwrap(),
+        b"hi there"
+    );
+    assert_eq!(lower_file("/etc/hosts").unwrap(), b"127.0.0.1");
+    assert_eq!(lower_file("/etc/motd").unwrap(), b"hi");
+    assert!(lower_file("/etc/issue").is_err());
+
+    // Entries of both volumes are told apart by device and I-node number.
+    let paths = [
+        "/",
+        "/etc",
+        "/etc/hosts",
+        "/etc/issue",
+        "/usr",
+        "/usr/lib/libc.so",
+        "/var/log",
+    ];
+    let ids: HashSet<(usize, usize)> = paths
+        .iter()
+        .map(|path| {
+            let stats = vol.lstat_sync(String::from(*path), None).unwrap();
+            (stats.dev, stats.ino)
+        })
+        .collect();
+    assert_eq!(ids.len(), paths.len());
+    let libc = vol
+        .stat_sync(String::from("/usr/lib/libc.so"), None)
+        .unwrap();
+    assert_eq!(libc.dev, LOWER_DEV);
+    let fd = vol
+        .open_sync(String::from("/usr/lib/libc.so"), None, None)
+        .unwrap();
+    let stats = vol.fstat_sync(fd, None).unwrap();
+    assert_eq!((stats.dev, stats.ino), (libc.dev, libc.ino));
+    vol.close_sync(fd).unwrap();
+    vol.chmod_sync(String::from("/lib/libc.so"), 0o600).unwrap();
+    let upper_lib = vol.get_link(vec![String::from("usr"), String::from("lib")]);
+    assert!(upper_lib.is_some());
+
+    // Removals leave whiteouts behind, listings merge both volumes.
+    vol.unlink_sync(String::from("/etc/motd")).unwrap();
+    assert!(!vol.exists_sync(String::from("/etc/motd")));
+    assert_eq!(
+        vol.readdir_sync(String::from("/etc")).unwrap(),
+        ["hosts", "issue"]
+    );
+    let etc = vol.get_link(vec![String::from("etc")]).unwrap();
+    assert!(vol.link(etc).whiteouts.contains("motd"));
+    assert_eq!(lower_file("/etc/motd").unwrap(), b"hi");
+
+    let err = vol.rmdir_sync(String::from("/var"), None).unwrap_err();
+    assert_eq!(err.code, FSError::ENOTEMPTY);
+    vol.rmdir_sync(String::from("/var/log"), None).unwrap();
+    vol.rmdir_sync(String::from("/var"), None).unwrap();
+    vol.rm_sync(
+        String::from("/etc"),
+        Some(RmOptions {
+            recursive: Some(true),
+            ..Default::default()
+        }),
+    )
+    .unwrap();
+    assert_eq!(vol.readdir_sync(String::from("/")).unwrap(), ["lib", "usr"]);
+    // A directory replacing a removed one starts out empty.
+    vol.mkdir_sync(String::from("/etc"), None).unwrap();
+    assert_eq!(vol.readdir_sync(String::from("/etc")).unwrap().len(), 0);
+
+    // Moved directories take their lower entries along.
+    vol.rename_sync(String::from("/usr"), String::from("/opt"))
+        .unwrap();
+    assert_eq!(
+        vol.readdir_sync(String::from("/opt/lib")).unwrap(),
+        ["libc.so"]
+    );
+    assert!(!vol.exists_sync(String::from("/lib/libc.so")));
+    assert_eq!(lower_file("/usr/lib/libc.so").unwrap(), b"elf");
+    let stats = vol
+        .stat_sync(String::from("/opt/lib/libc.so"), None)
+        .unwrap();
+    assert_eq!(stats.mode & 0o777, 0o600);
+
+    // Resetting the overlay drops its changes.
+    vol.reset().unwrap();
+    assert_eq!(
+        vol.readdir_sync(String::from("/etc")).unwrap(),
+        ["hosts", "motd"]
+    );
+    drop(vol);
+    let err = Volume::overlay(overlay).err().unwrap();
+    assert_eq!(err.code, FSError::EINVAL);
+}
+
+#[test]
+fn test_read_only_volume_and_mounts() {
+    use std::time::SystemTime;
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    vol.mkdir_sync(String::from("/fixtures"), None).unwrap();
+    vol.mkdir_sync(String::from("/etc"), None).unwrap();
+    vol.write_file_sync(String::from("/fixtures/a.txt"), b"a", None)
+        .unwrap();
+    let fd = vol
+        .open_sync(
+            String::from("/fixtures/a.txt"),
+            Some(String::from("r+")),
+            None,
+        )
+        .unwrap();
+    vol.mkdir_sync(String::from("/etc/ssl"), None).unwrap();
+    let etc = vol.export_tar(String::from("/etc")).unwrap();
+    let read_only = MountOptions {
+        read_only: Some(true),
+        ..Default::default()
+    };
+    vol.remount(String::from("/"), read_only.clone()).unwrap();
+
+    let a = String::from("/fixtures/a.txt");
+    let b = String::from("/fixtures/b.txt");
+    let errors = [
+        vol.write_file_sync(b.clone(), b"b", None).unwrap_err(),
+        vol.open_sync(a.clone(), Some(String::from("r+")), None)
+            .unwrap_err(),
+        vol.write_sync(fd, b"b", None, None, None).unwrap_err(),
+        vol.ftruncate_sync(fd, None).unwrap_err(),
+        vol.mkdir_sync(String::from("/tmp"), None).unwrap_err(),
+        vol.unlink_sync(a.clone()).unwrap_err(),
+        vol.rename_sync(a.clone(), b.clone()).unwrap_err(),
+        vol.link_sync(a.clone(), b.clone()).unwrap_err(),
+        vol.symlink_sync(a.clone(), b.clone()).unwrap_err(),
+        vol.chmod_sync(a.clone(), 0o600).unwrap_err(),
+        vol.chown_sync(a.clone(), 1000, 100).unwrap_err(),
+        vol.utimes_sync(a.clone(), SystemTime::UNIX_EPOCH, SystemTime::UNIX_EPOCH)
+            .unwrap_err(),
+        vol.lutimes_sync(a.clone(), SystemTime::UNIX_EPOCH, SystemTime::UNIX_EPOCH)
+            .unwrap_err(),
+        vol.futimes_sync(fd, SystemTime::UNIX_EPOCH, SystemTime::UNIX_EPOCH)
+            .unwrap_err(),
+        vol.rm_sync(String::from("/fixtures"), None).unwrap_err(),
+    ];
+    for err in errors {
+        assert_eq!(err.code, FSError::EROFS);
+    }
+    vol.close_sync(fd).unwrap();
+
+    // Existing entries and snapshots are reported as usual.
+    let recursive = MkdirOptions {
+        recursive: Some(true),
+        ..Default::default()
+    };
+    assert_eq!(
+        vol.mkdir_sync(String::from("/fixtures"), Some(recursive.clone()))
+            .unwrap(),
+        None
+    );
+    let err = vol.mkdir_sync(String::from("/fixtures"), None).unwrap_err();
+    assert_eq!(err.code, FSError::EEXIST);
+    let err = vol
+        .mkdir_sync(String::from("/fixtures/new"), Some(recursive))
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EROFS);
+    let before = vol.stat_sync(String::from("/etc"), None).unwrap();
+    let ssl = vol.stat_sync(String::from("/etc/ssl"), None).unwrap();
+    let entry = JsonEntry {
+        mode: Some(0o700),
+        mtime: Some(SystemTime::UNIX_EPOCH),
+        ..JsonEntry::new(JsonEntryKind::Directory)
+    };
+    let err = vol
+        .from_entries(vec![(String::from("/etc"), entry)], None)
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EROFS);
+    let err = vol.import_tar(&etc, String::from("/etc")).unwrap_err();
+    assert_eq!(err.code, FSError::EROFS);
+    let stats = vol.stat_sync(String::from("/etc/ssl"), None).unwrap();
+    assert_eq!(stats.mtime, ssl.mtime);
+    let after = vol.stat_sync(String::from("/etc"), None).unwrap();
+    assert_eq!((after.mode, after.mtime), (before.mode, before.mtime));
+    let id = vol.snapshot();
+    assert_eq!(vol.restore(id).unwrap_err().code, FSError::EROFS);
+    assert_eq!(vol.restore(id + 1).unwrap_err().code, FSError::EINVAL);
+
+    // Reads leave access times alone unless asked to update them.
+    let node = vol
+        .link(vol.get_link_or_throw(a.clone(), None).unwrap())
+        .node;
+    vol.node_mut(node).set_atime(SystemTime::UNIX_EPOCH);
+    assert_eq!(vol.read_file_sync(a.clone()).unwrap(), b"a");
+    assert_eq!(vol.node(node).get_atime(), SystemTime::UNIX_EPOCH);
+    let atime = MountOptions {
+        read_only: Some(true),
+        noatime: Some(false),
+    };
+    vol.remount(String::from("/"), atime).unwrap();
+    vol.read_file_sync(a.clone()).unwrap();
+    assert_ne!(vol.node(node).get_atime(), SystemTime::UNIX_EPOCH);
+    let err = vol.reset().unwrap_err();
+    assert_eq!(err.code, FSError::EROFS);
+    assert!(vol.exists_sync(a.clone()));
+
+    // Mounts have their own mode, whatever the volume's is.
+    let data = Volume::new();
+    vol.mount(String::from("/fixtures"), data.clone(), Some(read_only))
+        .unwrap();
+    assert!(vol.mounts()[0].read_only);
+    let err = vol.write_file_sync(b.clone(), b"b", None).unwrap_err();
+    assert_eq!(err.code, FSError::EROFS);
+    vol.remount(String::from("/fixtures"), MountOptions::default())
+        .unwrap();
+    vol.write_file_sync(b.clone(), b"b", None).unwrap();
+    assert!(data.borrow_mut().exists_sync(String::from("/b.txt")));
+    let err = vol.unlink_sync(a).unwrap_err();
+    assert_eq!(err.code, FSError::ENOENT);
+    let err = vol
+        .remount(String::from("/etc"), MountOptions::default())
+        .unwrap_err();
+    assert_eq!(err.code, FSError::EINVAL);
+
+    // Resetting a writable volume keeps `noatime`.
+    let noatime = MountOptions {
+        read_only: Some(false),
+        noatime: Some(true),
+    };
+    vol.remount(String::from("/"), noatime).unwrap();
+    vol.reset().unwrap();
+    let c = String::from("/c.txt");
+    vol.write_file_sync(c.clone(), b"c", None).unwrap();
+    let node = vol
+        .link(vol.get_link_or_throw(c.clone(), None).unwrap())
+        .node;
+    vol.node_mut(node).set_atime(SystemTime::UNIX_EPOCH);
+    vol.read_file_sync(c).unwrap();
+    assert_eq!(vol.node(node).get_atime(), SystemTime::UNIX_EPOCH);
+}
+
+#[test]
+fn test_fork_and_snapshots() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    let recursive = MkdirOptions {
+        recursive: Some(true),
+        ..Default::default()
+    };
+    vol.mkdir_sync(String::from("/a/b"), Some(recursive))
+        .unwrap();
+    vol.write_file_sync(String::from("/a/b/big.bin"), &[7; 4096], None)
+        .unwrap();
+    vol.write_file_sync(String::from("/a/c.txt"), b"c", None)
+        .unwrap();
+
+    let fork = vol.fork();
+    let mut fork = fork.borrow_mut();
+    fork.write_file_sync(String::from("/a/c.txt"), b"forked", None)
+        .unwrap();
+    fork.chmod_sync(String::from("/a/b/big.bin"), 0o600)
+        .unwrap();
+    fork.mkdir_sync(String::from("/d"), None).unwrap();
+    assert_eq!(vol.read_file_sync(String::from("/a/c.txt")).unwrap(), b"c");
+    assert!(!vol.exists_sync(String::from("/d")));
+    assert_eq!(
+        fork.read_file_sync(String::from("/a/c.txt")).unwrap(),
+        b"forked"
+    );
+
+    // Metadata changes copy the node but not its contents, untouched
+    // directories are not copied at all.
+    let big = vol
+        .link(
+            vol.get_link_or_throw(String::from("/a/b/big.bin"), None)
+                .unwrap(),
+        )
+        .node;
+    assert_ne!(vol.node(big).get_perm(), fork.node(big).get_perm());
+    assert!(!fork.nodes.shares(&vol.nodes, big));
+    let ptr = |vol: &Volume| vol.node(big).buf.as_ref().unwrap().as_ptr();
+    assert_eq!(ptr(&vol), ptr(&fork));
+    let b = vol.get_link_or_throw(String::from("/a/b"), None).unwrap();
+    assert!(fork.links.shares(&vol.links, b));
+
+    let id = vol.snapshot();
+    vol.rm_sync(
+        String::from("/a"),
+        Some(RmOptions {
+            recursive: Some(true),
+            ..Default::default()
+        }),
+    )
+    .unwrap();
+    vol.write_file_sync(String::from("/e.txt"), b"e", None)
+        .unwrap();
+    let fd = vol
+        .open_sync(String::from("/e.txt"), Some(String::from("r")), None)
+        .unwrap();
+    assert_eq!(vol.restore(id).unwrap_err().code, FSError::EBUSY);
+    vol.close_sync(fd).unwrap();
+    vol.restore(id).unwrap();
+    assert_eq!(vol.read_file_sync(String::from("/a/c.txt")).unwrap(), b"c");
+    assert!(!vol.exists_sync(String::from("/e.txt")));
+    vol.write_file_sync(String::from("/f.txt"), b"f", None)
+        .unwrap();
+
+    // Restoring again drops the changes made since the last restore.
+    vol.restore(id).unwrap();
+    assert!(!vol.exists_sync(String::from("/f.txt")));
+    vol.delete_snapshot(id).unwrap();
+    assert_eq!(vol.restore(id).unwrap_err().code, FSError::EINVAL);
+    assert_eq!(vol.delete_snapshot(id).unwrap_err().code, FSError::EINVAL);
+}
+
+#[test]
+fn test_diff_and_apply_patch() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    let recursive = MkdirOptions {
+        recursive: Some(true),
+        ..Default::default()
+    };
+    vol.mkdir_sync(String::from("/src/old"), Some(recursive))
+        .unwrap();
+    let files: [(&str, &[u8]); 6] = [
+        ("/src/main.rs", b"fn main() {}\n"),
+        ("/src/lib.rs", b"pub mod x;\n"),
+        ("/src/old/a.txt", b"a"),
+        ("/README", b"readme"),
+        ("/data.bin", &[0, 1, 2]),
+        ("/gone.txt", b"gone"),
+    ];
+    for (path, data) in files {
+        vol.write_file_sync(String::from(path), data, None).unwrap();
+    }
+    vol.symlink_sync(String::from("/README"), String::from("/link"))
+        .unwrap();
+
+    let fork = vol.fork();
+    let mut fork = fork.borrow_mut();
+    fork.write_file_sync(String::from("/src/main.rs"), b"fn main() { run() }\n", None)
+        .unwrap();
+    fork.chmod_sync(String::from("/src/lib.rs"), 0o600).unwrap();
+    fork.rename_sync(String::from("/src/old"), String::from("/src/new"))
+        .unwrap();
+    fork.rename_sync(String::from("/README"), String::from("/README.md"))
+        .unwrap();
+    fork.write_file_sync(String::from("/data.bin"), &[3], None)
+        .unwrap();
+    fork.unlink_sync(String::from("/gone.txt")).unwrap();
+    fork.unlink_sync(String::from("/link")).unwrap();
+    fork.mkdir_sync(String::from("/link"), None).unwrap();
+    fork.write_file_sync(String::from("/src/gen.rs"), b"// generated\n", None)
+        .unwrap();
+    fork.symlink_sync(String::from("main.rs"), String::from("/src/entry"))
+        .unwrap();
+
+    let diff = vol.diff(&fork).unwrap();
+    let find = |path: &str| {
+        diff.changes
+            .iter()
+            .filter(|change| change.path() == path)
+            .collect::<Vec<_>>()
+    };
+    match find("/src/main.rs")[..] {
+        [Change::Modified {
+            content: true,
+            text: Some(text),
+            ..
+        }] => assert!(text.ends_with("@@ -1 +1 @@\n-fn main() {}\n+fn main() { run() }\n")),
+        ref changes => panic!("{:?}", changes),
+    }
+    assert!(matches!(
+        find("/src/lib.rs")[..],
+        [Change::Modified {
+            content: false,
+            metadata: true,
+            ..
+        }]
+    ));
+    assert!(matches!(
+        find("/data.bin")[..],
+        [Change::Modified { text: None, .. }]
+    ));
+    assert!(matches!(
+        find("/src/new")[..],
+        [Change::Renamed { from, .. }] if from == "/src/old"
+    ));
+    assert!(find("/src/new/a.txt").is_empty());
+    assert!(matches!(
+        find("/README.md")[..],
+        [Change::Renamed { from, .. }] if from == "/README"
+    ));
+    assert!(matches!(
+        find("/gone.txt")[..],
+        [Change::Removed {
+            kind: DiffEntryKind::File,
+            ..
+        }]
+    ));
+    assert!(matches!(
+        find("/link")[..],
+        [Change::TypeChanged {
+            from: DiffEntryKind::Symlink,
+            ..
+        }]
+    ));
+    assert!(matches!(find("/src/gen.rs")[..], [Change::Added { .. }]));
+    assert!(matches!(
+        find("/src/entry")[..],
+        [Change::Added { entry, .. }] if entry.data == b"main.rs"
+    ));
+
+    // Replaying the changes onto another copy makes it the same as the fork.
+    let copy = vol.fork();
+    let mut copy = copy.borrow_mut();
+    copy.apply_patch(&diff).unwrap();
+    assert_eq!(copy.diff(&fork).unwrap(), Diff::default());
+    assert_eq!(
+        copy.read_file_sync(String::from("/src/new/a.txt")).unwrap(),
+        b"a"
+    );
+
+    let id = fork.snapshot();
+    fork.rm_sync(String::from("/src/gen.rs"), None).unwrap();
+    let diff = fork.diff_snapshot(id).unwrap();
+    assert!(matches!(
+        diff.changes
+            .iter()
+            .find(|change| change.path() == "/src/gen.rs"),
+        Some(Change::Removed { .. })
+    ));
+    assert_eq!(
+        fork.diff_snapshot(id + 1).unwrap_err().code,
+        FSError::EINVAL
+    );
 }
@@ -0,0 +1,693 @@
+use crate::{
+    arena::InodeId,
+    encoding::{buffer_to_string, Encoding},
+    error::{create_error, FSError, FsError},
+    events::{EventEmitter, ListenerId},
+    stats::Stats,
+    util,
+    volume::{TreeEntry, Volume, VolumeEvent},
+};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll, Waker},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchEventType {
+    Rename,
+    Change,
+}
+
+impl WatchEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchEventType::Rename => "rename",
+            WatchEventType::Change => "change",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchEvent {
+    pub event_type: WatchEventType,
+    // Path of the affected entry, relative to the watched path.
+    pub filename: String,
+}
+
+pub type WatchListener = Box<dyn FnMut(&WatchEvent)>;
+
+#[derive(Clone, Default)]
+pub struct FSWatcherOptions {
+    // https://nodejs.org/api/fs.html#fswatchfilename-options-listener
+    pub persistent: Option<bool>,
+    pub recursive: Option<bool>,
+    pub encoding: Option<String>,
+}
+
+struct WatcherState {
+    vol: Weak<RefCell<Volume>>,
+    // Subscription on the volume's events, see `close`.
+    listener: Option<ListenerId>,
+
+    filename: String,
+    name: String,
+    recursive: bool,
+    encoding: Encoding,
+    closed: bool,
+
+    events: EventEmitter<WatchEvent>,
+
+    // Events kept for the async iterator form, see `FSWatcher::next`.
+    buffered: bool,
+    queue: VecDeque<WatchEvent>,
+    waker: Option<Waker>,
+
+    // Relative filenames of the entries watched for changes, by I-node.
+    watched: HashMap<InodeId, Vec<String>>,
+}
+
+impl WatcherState {
+    fn emit(state: &Arc<RefCell<WatcherState>>, event_type: WatchEventType, filename: String) {
+        let mut this = state.borrow_mut();
+        if this.closed {
+            return;
+        }
+        let filename = match this.encoding {
+            Encoding::Utf8 => filename,
+            encoding => buffer_to_string(filename.as_bytes(), encoding),
+        };
+        let event = WatchEvent {
+            event_type,
+            filename,
+        };
+        if this.buffered {
+            this.queue.push_back(event.clone());
+            if let Some(waker) = this.waker.take() {
+                waker.wake();
+            }
+        }
+        let listeners = this.events.listeners();
+        drop(this);
+        for listener in listeners {
+            (listener.borrow_mut())(&event);
+        }
+    }
+
+    fn handle(state: &Arc<RefCell<WatcherState>>, event: &VolumeEvent) {
+        match event {
+            VolumeEvent::Change { ino } => {
+                let filenames = state.borrow().watched.get(ino).cloned();
+                for filename in filenames.unwrap_or_default() {
+                    WatcherState::emit(state, WatchEventType::Change, filename);
+                }
+            }
+            VolumeEvent::ChildAdd { dir, entries, .. } => {
+                if !state.borrow().watches_dir(dir) {
+                    return;
+                }
+                let filename = state.borrow().relative(entries[0].path.clone());
+                WatcherState::emit(state, WatchEventType::Rename, filename);
+                let mut this = state.borrow_mut();
+                if this.closed {
+                    return;
+                }
+                let count = if this.recursive { entries.len() } else { 1 };
+                for entry in entries.iter().take(count) {
+                    this.watch(entry);
+                }
+            }
+            VolumeEvent::ChildDelete { dir, name } => {
+                let path = util::path_join(vec![dir.clone(), name.clone()]);
+                if state.borrow().watches_dir(dir) {
+                    let filename = state.borrow().relative(path);
+                    state.borrow_mut().unwatch(&filename);
+                    WatcherState::emit(state, WatchEventType::Rename, filename);
+                } else if path == state.borrow().filename {
+                    let name = state.borrow().name.clone();
+                    WatcherState::emit(state, WatchEventType::Rename, name);
+                }
+            }
+        }
+    }
+
+    // Whether entries added to or removed from `dir` are reported.
+    fn watches_dir(&self, dir: &str) -> bool {
+        if dir == self.filename {
+            return true;
+        }
+        let prefix = if self.filename == "/" {
+            String::from("/")
+        } else {
+            format!("{}/", self.filename)
+        };
+        self.recursive && dir.starts_with(&prefix)
+    }
+
+    fn watch(&mut self, entry: &TreeEntry) {
+        let filename = self.relative(entry.path.clone());
+        self.watched.entry(entry.ino).or_default().push(filename);
+    }
+
+    // Stops watching `filename` and everything below it.
+    fn unwatch(&mut self, filename: &str) {
+        let prefix = format!("{}/", filename);
+        self.watched.retain(|_, filenames| {
+            filenames.retain(|watched| watched != filename && !watched.starts_with(&prefix));
+            !filenames.is_empty()
+        });
+    }
+
+    fn relative(&self, path: String) -> String {
+        let filename = util::path_relative(self.filename.clone(), path, Some(String::from("/")));
+        if filename.is_empty() {
+            return self.name.clone();
+        }
+        filename
+    }
+
+    // The volume may be the one currently emitting (when a watcher is closed
+    // from its own listener), in which case the listener stays behind and
+    // turns into a no-op once it finds the watcher closed.
+    fn close(&mut self) {
+        if self.closed {
+            return;
+        }
+        self.closed = true;
+        self.watched.clear();
+        if let (Some(id), Some(vol)) = (self.listener.take(), self.vol.upgrade()) {
+            if let Ok(mut vol) = vol.try_borrow_mut() {
+                vol.unsubscribe(id);
+            }
+        }
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for WatcherState {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+// Port of memfs' `FSWatcher`: reports "rename" when entries are added to or
+// removed from the watched directory (or its whole subtree when recursive) and
+// "change" when a watched file is modified. Events are delivered synchronously
+// from within the operation that caused them, listeners must therefore not use
+// the volume itself.
+#[derive(Clone)]
+pub struct FSWatcher {
+    state: Arc<RefCell<WatcherState>>,
+    pub persistent: bool,
+}
+
+impl FSWatcher {
+    #[allow(clippy::arc_with_non_send_sync)] // Lives on the thread of its volume.
+    pub fn start(
+        vol: &mut Volume,
+        path: String,
+        options: FSWatcherOptions,
+        buffered: bool,
+    ) -> Result<FSWatcher, FsError> {
+        let func_name = Some(String::from("watch"));
+        let filename = util::path_resolve(vec![path], None);
+        let encoding = options.encoding.unwrap_or(String::from("utf8"));
+        let encoding = match Encoding::parse(&encoding) {
+            Some(encoding) => encoding,
+            None => {
+                return Err(create_error(
+                    FSError::EINVAL,
+                    func_name,
+                    Some(vec![encoding]),
+                ))
+            }
+        };
+        let link = vol.get_link_or_throw(filename.clone(), func_name)?;
+        let name = util::filename_to_steps(filename.clone(), None)
+            .pop()
+            .unwrap_or_default();
+        let recursive = options.recursive.unwrap_or(false);
+        let entries = vol.tree_entries(link, if recursive { None } else { Some(1) });
+
+        let state = Arc::new(RefCell::new(WatcherState {
+            vol: Arc::downgrade(&vol.handle()),
+            listener: None,
+            filename,
+            name,
+            recursive,
+            encoding,
+            closed: false,
+            events: EventEmitter::new(),
+            buffered,
+            queue: VecDeque::new(),
+            waker: None,
+            watched: HashMap::new(),
+        }));
+        for entry in entries.iter() {
+            state.borrow_mut().watch(entry);
+        }
+
+        let weak = Arc::downgrade(&state);
+        let id = vol.subscribe(move |event| {
+            if let Some(state) = weak.upgrade() {
+                WatcherState::handle(&state, event);
+            }
+        });
+        state.borrow_mut().listener = Some(id);
+
+        Ok(FSWatcher {
+            state,
+            persistent: options.persistent.unwrap_or(true),
+        })
+    }
+
+    pub fn on(&mut self, listener: impl FnMut(&WatchEvent) + 'static) -> ListenerId {
+        self.state.borrow_mut().events.on(listener)
+    }
+
+    pub fn off(&mut self, id: ListenerId) -> bool {
+        self.state.borrow_mut().events.off(id)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.borrow().closed
+    }
+
+    pub fn close(&self) {
+        self.state.borrow_mut().close();
+    }
+
+    // Async iterator form, like `fs.promises.watch`: resolves with the next
+    // event, or with `None` once the watcher has been closed.
+    pub fn next(&self) -> NextWatchEvent {
+        NextWatchEvent {
+            state: self.state.clone(),
+        }
+    }
+
+    // Takes the next buffered event without waiting.
+    pub fn try_next(&self) -> Option<WatchEvent> {
+        self.state.borrow_mut().queue.pop_front()
+    }
+}
+
+pub struct NextWatchEvent {
+    state: Arc<RefCell<WatcherState>>,
+}
+
+impl Future for NextWatchEvent {
+    type Output = Option<WatchEvent>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.borrow_mut();
+        if let Some(event) = state.queue.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+const DEFAULT_STAT_INTERVAL: usize = 5007;
+
+pub struct StatChange {
+    pub curr: Stats,
+    pub prev: Stats,
+}
+
+pub type StatListener = Box<dyn FnMut(&Stats, &Stats)>;
+
+#[derive(Clone, Default)]
+pub struct StatWatcherOptions {
+    // https://nodejs.org/api/fs.html#fswatchfilefilename-options-listener
+    pub persistent: Option<bool>,
+    pub interval: Option<usize>,
+    pub bigint: Option<bool>,
+}
+
+// `setInterval` registration driving a `StatWatcher` inside a JS host.
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+struct Timer {
+    handle: wasm_bindgen::JsValue,
+    _callback: wasm_bindgen::closure::Closure<dyn FnMut()>,
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+impl Timer {
+    fn start(watcher: &StatWatcher, interval: usize) -> Timer {
+        use js_sys::{Function, Reflect};
+        use wasm_bindgen::{closure::Closure, JsCast};
+
+        let weak = Arc::downgrade(&watcher.state);
+        let callback = Closure::wrap(Box::new(move || {
+            if let Some(state) = weak.upgrade() {
+                StatWatcher { state }.poll();
+            }
+        }) as Box<dyn FnMut()>);
+        let global = js_sys::global();
+        let set_interval: Function = Reflect::get(&global, &"setInterval".into())
+            .unwrap()
+            .unchecked_into();
+        let handle = set_interval
+            .call2(&global, callback.as_ref(), &(interval as u32).into())
+            .unwrap();
+        Timer {
+            handle,
+            _callback: callback,
+        }
+    }
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+impl Drop for Timer {
+    fn drop(&mut self) {
+        use js_sys::{Function, Reflect};
+        use wasm_bindgen::JsCast;
+
+        let global = js_sys::global();
+        let clear_interval: Function = Reflect::get(&global, &"clearInterval".into())
+            .unwrap()
+            .unchecked_into();
+        let _ = clear_interval.call1(&global, &self.handle);
+    }
+}
+
+struct StatWatcherState {
+    vol: Weak<RefCell<Volume>>,
+    filename: String,
+    interval: usize,
+    persistent: bool,
+    bigint: bool,
+    prev: Stats,
+    events: EventEmitter<StatChange>,
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    timer: Option<Timer>,
+}
+
+// Port of memfs' `StatWatcher`: polls the stats of a file every `interval`
+// milliseconds and notifies listeners with `(curr, prev)` whenever its mtime,
+// size, ino or link count changed. Deleted or missing files are reported with
+// zeroed stats. Inside a JS host polling runs on `setInterval`, elsewhere the
+// embedder drives it by calling `poll`.
+#[derive(Clone)]
+pub struct StatWatcher {
+    state: Arc<RefCell<StatWatcherState>>,
+}
+
+impl StatWatcher {
+    #[allow(clippy::arc_with_non_send_sync)] // Lives on the thread of its volume.
+    pub fn start(vol: &mut Volume, path: String, options: StatWatcherOptions) -> StatWatcher {
+        let filename = util::path_resolve(vec![path], None);
+        let bigint = options.bigint.unwrap_or(false);
+        let interval = options.interval.unwrap_or(DEFAULT_STAT_INTERVAL);
+        let prev = vol
+            .stat_sync(filename.clone(), Some(bigint))
+            .unwrap_or_else(|_| Stats::zeroed(bigint));
+        let watcher = StatWatcher {
+            state: Arc::new(RefCell::new(StatWatcherState {
+                vol: Arc::downgrade(&vol.handle()),
+                filename,
+                interval,
+                persistent: options.persistent.unwrap_or(true),
+                bigint,
+                prev,
+                events: EventEmitter::new(),
+                #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+                timer: None,
+            })),
+        };
+        #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+        {
+            let timer = Timer::start(&watcher, interval);
+            watcher.state.borrow_mut().timer = Some(timer);
+        }
+        watcher
+    }
+
+    pub fn filename(&self) -> String {
+        self.state.borrow().filename.clone()
+    }
+
+    pub fn interval(&self) -> usize {
+        self.state.borrow().interval
+    }
+
+    pub fn persistent(&self) -> bool {
+        self.state.borrow().persistent
+    }
+
+    pub fn add_listener(&self, mut listener: impl FnMut(&Stats, &Stats) + 'static) -> ListenerId {
+        self.state
+            .borrow_mut()
+            .events
+            .on(move |change: &StatChange| listener(&change.curr, &change.prev))
+    }
+
+    pub fn remove_listener(&self, id: ListenerId) -> bool {
+        self.state.borrow_mut().events.off(id)
+    }
+
+    pub fn remove_all_listeners(&self) {
+        self.state.borrow_mut().events.remove_all_listeners();
+    }
+
+    pub fn listener_count(&self) -> usize {
+        self.state.borrow().events.listener_count()
+    }
+
+    // Takes a fresh snapshot of the file stats and notifies listeners if it
+    // differs from the previous one. Skipped while the volume is in use.
+    pub fn poll(&self) {
+        let (vol, filename, bigint) = {
+            let state = self.state.borrow();
+            (state.vol.upgrade(), state.filename.clone(), state.bigint)
+        };
+        let vol = match vol {
+            Some(vol) => vol,
+            None => return,
+        };
+        let curr = match vol.try_borrow_mut() {
+            Ok(mut vol) => vol
+                .stat_sync(filename, Some(bigint))
+                .unwrap_or_else(|_| Stats::zeroed(bigint)),
+            Err(_) => return,
+        };
+
+        let mut state = self.state.borrow_mut();
+        let prev = &state.prev;
+        let changed = curr.mtime_ms != prev.mtime_ms
+            || curr.size != prev.size
+            || curr.ino != prev.ino
+            || curr.nlink != prev.nlink;
+        if !changed {
+            return;
+        }
+        let prev = std::mem::replace(&mut state.prev, curr.clone());
+        let listeners = state.events.listeners();
+        drop(state);
+        let change = StatChange { curr, prev };
+        for listener in listeners {
+            (listener.borrow_mut())(&change);
+        }
+    }
+
+    pub fn stop(&self) {
+        #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+        self.state.borrow_mut().timer.take();
+    }
+}
+
+#[test]
+fn test_fs_watcher_events() {
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    vol.mkdir_sync(String::from("/dir"), None).unwrap();
+    vol.write_file_sync(String::from("/dir/a"), b"a", None)
+        .unwrap();
+    let options = FSWatcherOptions {
+        recursive: Some(true),
+        ..Default::default()
+    };
+    let watcher = vol
+        .watch_async(String::from("/dir"), Some(options))
+        .unwrap();
+
+    vol.write_file_sync(String::from("/dir/a"), b"b", None)
+        .unwrap();
+    vol.mkdir_sync(String::from("/dir/sub"), None).unwrap();
+    vol.write_file_sync(String::from("/dir/sub/b"), b"b", None)
+        .unwrap();
+    vol.rename_sync(String::from("/dir/sub"), String::from("/dir/moved"))
+        .unwrap();
+    vol.write_file_sync(String::from("/dir/moved/b"), b"c", None)
+        .unwrap();
+    vol.write_file_sync(String::from("/outside"), b"c", None)
+        .unwrap();
+    vol.rm_sync(String::from("/dir/a"), None).unwrap();
+
+    let mut events = Vec::new();
+    while let Some(event) = watcher.try_next() {
+        events.push(format!("{} {}", event.event_type.as_str(), event.filename));
+    }
+    assert_eq!(
+        events,
+        [
+            "change a",
+            "change a",
+            "rename sub",
+            "rename sub/b",
+            "change sub/b",
+            "change sub/b",
+            "rename sub",
+            "rename moved",
+            "change moved/b",
+            "change moved/b",
+            "rename a",
+        ]
+    );
+
+    watcher.close();
+    vol.write_file_sync(String::from("/dir/moved/b"), b"d", None)
+        .unwrap();
+    assert!(watcher.try_next().is_none());
+}
+
+#[test]
+fn test_fs_watcher_listener_and_async_forms() {
+    use std::rc::Rc;
+    use std::task::{Wake, Waker};
+    let vol = Volume::new();
+    let mut vol = vol.borrow_mut();
+    vol.mkdir_sync(String::from("/dir"), None).unwrap();
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let log = events.clone();
+    let listener: WatchListener = Box::new(move |event| {
+        log.borrow_mut()
+            .push(format!("{} {}", event.event_type.as_str(), event.filename))
+    });
+    let watcher = vol
+        .watch(String::from("/dir"), None, Some(listener))
+        .unwrap();
+    vol.write_file_sync(String::from("/dir/a"), b"a", None)
+        .unwrap();
+    vol.write_file_sync(String::from("/dir/a"), b"b", None)
+        .unwrap();
+    vol.mkdir_sync(String::from("/dir/sub"), None).unwrap();
+    vol.write_file_sync(String::from("/dir/sub/b"), b"b", None)
+        .unwrap();
+    vol.unlink_sync(String::from("/dir/a")).unwrap();
+    // Without `recursive` nothing below `sub` is reported.
+    assert_eq!(
+        *events.borrow(),
+        [
+            "rename a",
+            "change a",
+            "change a",
+            "change a",
+            "change a",
+            "rename sub",
+            "rename a"
+        ]
+    );
+    assert!(watcher.try_next().is_none());
+    watcher.close();
+    assert!(watcher.is_closed());
+
+    // Watching a file reports it by its name, in the requested encoding.
+    vol.write_file_sync(String::from("/dir/c"), b"c", None)
+        .unwrap();
+    let options = FSWatcherOptions {
+        encoding: Some(String::from("hex")),
+        ..Default::default()
+    };
+    let file_watcher = vol
+        .watch_async(String::from("/dir/c"), Some(options))
+        .unwrap();
+    vol.write_file_sync(String::from("/dir/c"), b"d", None)
+        .unwrap();
+    vol.unlink_sync(String::from("/dir/c")).unwrap();
+
+    struct NoopWake;
+    impl Wake for NoopWake {
+        fn wake(self: Arc<Self>) {}
+    }
+    let waker = Waker::from(Arc::new(NoopWake));
+    let mut cx = Context::from_waker(&waker);
+    let mut next = || Pin::new(&mut file_watcher.next()).poll(&mut cx);
+    let event = |filename: &str, event_type| {
+        Poll::Ready(Some(WatchEvent {
+            event_type,
+            filename: String::from(filename),
+        }))
+    };
+    assert_eq!(next(), event("63", WatchEventType::Change));
+    assert_eq!(next(), event("63", WatchEventType::Change));
+    assert_eq!(next(), event("63", WatchEventType::Rename));
+    assert_eq!(next(), Poll::Pending);
+    file_watcher.close();
+    assert_eq!(next(), Poll::Ready(None));
+}
+
+#[test]
+fn test_stat_watcher_polling() {
+    use std::rc::Rc;
+    let vol = Volume::new();
+    vol.borrow_mut()
+        .write_file_sync(String::from("/a.txt"), b"a", None)
+        .unwrap();
+    let changes = Rc::new(RefCell::new(Vec::new()));
+    let log = changes.clone();
+    let listener: StatListener =
+        Box::new(move |curr, prev| log.borrow_mut().push((curr.size, prev.size, curr.nlink)));
+    let id = vol
+        .borrow_mut()
+        .watch_file(String::from("/a.txt"), None, listener);
+    let watcher = vol
+        .borrow()
+        .get_stat_watcher(String::from("/a.txt"))
+        .unwrap();
+    assert_eq!(watcher.interval(), DEFAULT_STAT_INTERVAL);
+
+    watcher.poll();
+    assert!(changes.borrow().is_empty());
+    vol.borrow_mut()
+        .write_file_sync(String::from("/a.txt"), b"abc", None)
+        .unwrap();
+    watcher.poll();
+    watcher.poll();
+    assert_eq!(*changes.borrow(), [(3, 1, 1)]);
+
+    // Removed files report zeroed stats.
+    vol.borrow_mut()
+        .unlink_sync(String::from("/a.txt"))
+        .unwrap();
+    watcher.poll();
+    assert_eq!(changes.borrow()[1], (0, 3, 0));
+
+    // Polls while the volume is borrowed are skipped.
+    let mut borrowed = vol.borrow_mut();
+    borrowed
+        .write_file_sync(String::from("/a.txt"), b"abcd", None)
+        .unwrap();
+    watcher.poll();
+    drop(borrowed);
+    assert_eq!(changes.borrow().len(), 2);
+
+    vol.borrow_mut()
+        .unwatch_file(String::from("/a.txt"), Some(id));
+    assert!(vol
+        .borrow()
+        .get_stat_watcher(String::from("/a.txt"))
+        .is_none());
+    watcher.poll();
+    assert_eq!(changes.borrow().len(), 2);
+}
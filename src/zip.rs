@@ -0,0 +1,678 @@
+// Reader and writer for zip archives, see `Volume::import_zip` and
+// `Volume::export_zip`.
+//
+// Supports stored and deflated entries, zip64 sizes, offsets and entry
+// counts, Unix modes (and with them symlinks) in the external attributes and
+// mtimes from the extended timestamp field, falling back to the DOS time
+// taken as UTC. Encrypted and multi-disk archives are rejected.
+use crate::{
+    constants::constants,
+    error::{create_error, FSError, FsError},
+};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression, Crc};
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x06064b50;
+const ZIP64_LOCATOR: u32 = 0x07064b50;
+
+const ZIP64_EXTRA: u16 = 0x0001;
+const TIMESTAMP_EXTRA: u16 = 0x5455;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+// Host system in the "version made by" field whose external attributes hold
+// a Unix mode.
+const UNIX: u16 = 3;
+// General purpose flags.
+const ENCRYPTED: u16 = 1;
+const UTF8_NAMES: u16 = 1 << 11;
+
+#[derive(Clone, Default)]
+pub struct ZipOptions {
+    // Decompress files when first read instead of while importing. The
+    // archive is then kept in memory for as long as such files exist.
+    pub lazy: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZipEntryKind {
+    File,
+    Directory,
+    // The target is the entry's data.
+    Symlink,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZipEntry {
+    // Path as stored, without the trailing slash of directories.
+    pub path: String,
+    pub kind: ZipEntryKind,
+    // Permission bits.
+    pub mode: usize,
+    pub mtime: SystemTime,
+    // Uncompressed size.
+    pub size: usize,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    local_header_offset: usize,
+}
+
+fn invalid() -> FsError {
+    create_error(FSError::EINVAL, Some(String::from("import_zip")), None)
+}
+
+// Little-endian fields of `data` from `pos` on.
+struct Fields<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Fields { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], FsError> {
+        let end = self.pos.checked_add(len).ok_or_else(invalid)?;
+        let bytes = self.data.get(self.pos..end).ok_or_else(invalid)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, FsError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, FsError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, FsError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+// The central directory of an archive, with the archive itself to read
+// entries from.
+pub struct ZipArchive {
+    data: Arc<[u8]>,
+    entries: Vec<ZipEntry>,
+}
+
+impl ZipArchive {
+    // Fails with EINVAL if `data` is not a zip archive and with ENOTSUP for
+    // multi-disk archives.
+    pub fn new(data: Arc<[u8]>) -> Result<ZipArchive, FsError> {
+        // The end of central directory record is followed by a comment of at
+        // most 64KiB.
+        let min = data.len().saturating_sub(22 + 0xffff);
+        let eocd = (min..data.len().saturating_sub(21))
+            .rev()
+            .find(|pos| data[*pos..pos + 4] == END_OF_CENTRAL_DIRECTORY.to_le_bytes())
+            .ok_or_else(invalid)?;
+        let mut fields = Fields::new(&data, eocd + 4);
+        let disk = fields.u16()?;
+        let directory_disk = fields.u16()?;
+        fields.u16()?;
+        let mut count = fields.u16()? as u64;
+        fields.u32()?;
+        let mut offset = fields.u32()? as u64;
+        if disk != 0 || directory_disk != 0 {
+            return Err(create_error(
+                FSError::ENOTSUP,
+                Some(String::from("import_zip")),
+                None,
+            ));
+        }
+
+        if count == 0xffff || offset == 0xffff_ffff {
+            let mut locator = Fields::new(&data, eocd.checked_sub(20).ok_or_else(invalid)?);
+            if locator.u32()? != ZIP64_LOCATOR {
+                return Err(invalid());
+            }
+            locator.u32()?;
+            let record = usize::try_from(locator.u64()?).map_err(|_| invalid())?;
+            let mut fields = Fields::new(&data, record);
+            if fields.u32()? != ZIP64_END_OF_CENTRAL_DIRECTORY {
+                return Err(invalid());
+            }
+            fields.bytes(8 + 2 + 2 + 4 + 4 + 8)?;
+            count = fields.u64()?;
+            fields.u64()?;
+            offset = fields.u64()?;
+        }
+
+        let mut fields = Fields::new(&data, usize::try_from(offset).map_err(|_| invalid())?);
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            entries.push(read_central_header(&mut fields)?);
+        }
+        Ok(ZipArchive { data, entries })
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    // Decompressed data of `entry`, failing with ENOTSUP for compression
+    // methods other than stored and deflated and with EIO if the checksum
+    // does not match.
+    pub fn read(&self, entry: &ZipEntry) -> Result<Vec<u8>, FsError> {
+        read_entry(&self.data, entry)
+    }
+
+    // Like `read`, for use after the archive is gone.
+    pub fn read_later(
+        &self,
+        entry: &ZipEntry,
+    ) -> impl Fn() -> Result<Vec<u8>, FsError> + Send + Sync {
+        let data = self.data.clone();
+        let entry = entry.clone();
+        move || read_entry(&data, &entry)
+    }
+}
+
+fn read_central_header(fields: &mut Fields) -> Result<ZipEntry, FsError> {
+    if fields.u32()? != CENTRAL_HEADER {
+        return Err(invalid());
+    }
+    let made_by = fields.u16()?;
+    fields.u16()?;
+    let flags = fields.u16()?;
+    let method = fields.u16()?;
+    let dos_time = fields.u16()?;
+    let dos_date = fields.u16()?;
+    let crc = fields.u32()?;
+    let mut compressed_size = fields.u32()? as u64;
+    let mut size = fields.u32()? as u64;
+    let name_len = fields.u16()? as usize;
+    let extra_len = fields.u16()? as usize;
+    let comment_len = fields.u16()? as usize;
+    fields.u16()?;
+    fields.u16()?;
+    let external_attributes = fields.u32()?;
+    let mut local_header_offset = fields.u32()? as u64;
+    let name = fields.bytes(name_len)?;
+    let extra = fields.bytes(extra_len)?;
+    fields.bytes(comment_len)?;
+    if flags & ENCRYPTED != 0 {
+        return Err(create_error(
+            FSError::ENOTSUP,
+            Some(String::from("import_zip")),
+            None,
+        ));
+    }
+
+    let mut mtime = None;
+    let mut extra = Fields::new(extra, 0);
+    while extra.pos + 4 <= extra.data.len() {
+        let id = extra.u16()?;
+        let len = extra.u16()? as usize;
+        let mut field = Fields::new(extra.bytes(len)?, 0);
+        match id {
+            // Only the values saturated in the header are present.
+            ZIP64_EXTRA => {
+                if size == 0xffff_ffff {
+                    size = field.u64()?;
+                }
+                if compressed_size == 0xffff_ffff {
+                    compressed_size = field.u64()?;
+                }
+                if local_header_offset == 0xffff_ffff {
+                    local_header_offset = field.u64()?;
+                }
+            }
+            // The first flag bit announces the mtime.
+            TIMESTAMP_EXTRA if field.bytes(1)?[0] & 1 != 0 => {
+                mtime = Some(UNIX_EPOCH + Duration::from_secs(field.u32()? as u64));
+            }
+            _ => {}
+        }
+    }
+
+    let mut path = String::from_utf8_lossy(name).into_owned();
+    let unix_mode = match made_by >> 8 {
+        UNIX => (external_attributes >> 16) as usize,
+        _ => 0,
+    };
+    // MS-DOS attributes mark directories with 0x10.
+    let kind = match unix_mode & constants::S_IFMT {
+        constants::S_IFLNK => ZipEntryKind::Symlink,
+        constants::S_IFDIR => ZipEntryKind::Directory,
+        _ if path.ends_with('/') || external_attributes & 0x10 != 0 => ZipEntryKind::Directory,
+        _ => ZipEntryKind::File,
+    };
+    while path.len() > 1 && path.ends_with('/') {
+        path.pop();
+    }
+    let mode = match (unix_mode & 0o7777, kind) {
+        (0, ZipEntryKind::Directory) => 0o755,
+        (0, ZipEntryKind::Symlink) => 0o777,
+        (0, ZipEntryKind::File) => 0o644,
+        (mode, _) => mode,
+    };
+    let to_usize = |value: u64| usize::try_from(value).map_err(|_| invalid());
+    Ok(ZipEntry {
+        path,
+        kind,
+        mode,
+        mtime: mtime.unwrap_or_else(|| from_dos(dos_date, dos_time)),
+        size: to_usize(size)?,
+        method,
+        crc,
+        compressed_size: to_usize(compressed_size)?,
+        local_header_offset: to_usize(local_header_offset)?,
+    })
+}
+
+fn read_entry(data: &[u8], entry: &ZipEntry) -> Result<Vec<u8>, FsError> {
+    let func_name = Some(String::from("read"));
+    let paths = Some(vec![entry.path.clone()]);
+    let mut fields = Fields::new(data, entry.local_header_offset);
+    if fields.u32()? != LOCAL_HEADER {
+        return Err(invalid());
+    }
+    fields.bytes(22)?;
+    let name_len = fields.u16()? as usize;
+    let extra_len = fields.u16()? as usize;
+    fields.bytes(name_len + extra_len)?;
+    let compressed = fields.bytes(entry.compressed_size)?;
+
+    let mut contents = Vec::with_capacity(entry.size.min(compressed.len() * 8));
+    match entry.method {
+        STORED => contents.extend_from_slice(compressed),
+        DEFLATED => {
+            DeflateDecoder::new(compressed)
+                .take(entry.size as u64 + 1)
+                .read_to_end(&mut contents)
+                .map_err(|_| create_error(FSError::EIO, func_name.clone(), paths.clone()))?;
+        }
+        _ => return Err(create_error(FSError::ENOTSUP, func_name, paths)),
+    }
+    let mut crc = Crc::new();
+    crc.update(&contents);
+    if contents.len() != entry.size || crc.sum() != entry.crc {
+        return Err(create_error(FSError::EIO, func_name, paths));
+    }
+    Ok(contents)
+}
+
+// Writes archives entry by entry, compressing files with deflate unless that
+// does not make them smaller.
+pub struct ZipWriter<W> {
+    writer: W,
+    offset: usize,
+    // Central directory headers of the entries written so far.
+    directory: Vec<u8>,
+    count: usize,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(writer: W) -> Self {
+        ZipWriter {
+            writer,
+            offset: 0,
+            directory: Vec::new(),
+            count: 0,
+        }
+    }
+
+    // `data` is the contents of a file or the target of a symlink.
+    pub fn append(
+        &mut self,
+        path: &str,
+        kind: ZipEntryKind,
+        mode: usize,
+        mtime: SystemTime,
+        data: &[u8],
+    ) -> Result<(), FsError> {
+        let mut name = String::from(path);
+        let file_type = match kind {
+            ZipEntryKind::File => constants::S_IFREG,
+            ZipEntryKind::Directory => {
+                if !name.ends_with('/') {
+                    name.push('/');
+                }
+                constants::S_IFDIR
+            }
+            ZipEntryKind::Symlink => constants::S_IFLNK,
+        };
+        let mut crc = Crc::new();
+        crc.update(data);
+        let deflated = match kind {
+            ZipEntryKind::File if !data.is_empty() => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                Some(encoder.finish()?).filter(|deflated| deflated.len() < data.len())
+            }
+            _ => None,
+        };
+        let (method, compressed) = match &deflated {
+            Some(deflated) => (DEFLATED, &deflated[..]),
+            None => (STORED, data),
+        };
+
+        let secs = mtime
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs();
+        let (dos_date, dos_time) = to_dos(secs);
+        let flags = if name.is_ascii() { 0 } else { UTF8_NAMES };
+        let mut timestamp = vec![1];
+        timestamp.extend_from_slice(&(secs.min(u32::MAX as u64) as u32).to_le_bytes());
+
+        let large = |value: usize| value >= 0xffff_ffff;
+        let sizes_zip64 = large(data.len()) || large(compressed.len());
+        let zip64 = sizes_zip64 || large(self.offset);
+        let version = if zip64 { 45 } else { 20 };
+        let saturate = |value: usize| if zip64 { 0xffff_ffff } else { value as u32 };
+
+        let mut local_extra = extra_field(TIMESTAMP_EXTRA, &timestamp);
+        let mut central_extra = local_extra.clone();
+        if zip64 {
+            let mut sizes = Vec::new();
+            sizes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            sizes.extend_from_slice(&(compressed.len() as u64).to_le_bytes());
+            local_extra.extend(extra_field(ZIP64_EXTRA, &sizes));
+            sizes.extend_from_slice(&(self.offset as u64).to_le_bytes());
+            central_extra.extend(extra_field(ZIP64_EXTRA, &sizes));
+        }
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        for value in [version, flags, method, dos_time, dos_date] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [crc.sum(), saturate(compressed.len()), saturate(data.len())] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(local_extra.len() as u16).to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        header.extend_from_slice(&local_extra);
+        self.writer.write_all(&header)?;
+        self.writer.write_all(compressed)?;
+
+        let directory = &mut self.directory;
+        directory.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        for value in [
+            UNIX << 8 | version,
+            version,
+            flags,
+            method,
+            dos_time,
+            dos_date,
+        ] {
+            directory.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [crc.sum(), saturate(compressed.len()), saturate(data.len())] {
+            directory.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [name.len(), central_extra.len(), 0, 0, 0] {
+            directory.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        // MS-DOS attributes in the low bits, for tools that ignore the mode.
+        let dos_attributes = if kind == ZipEntryKind::Directory {
+            0x10
+        } else {
+            0
+        };
+        let external_attributes = ((file_type | mode & 0o7777) as u32) << 16 | dos_attributes;
+        directory.extend_from_slice(&external_attributes.to_le_bytes());
+        directory.extend_from_slice(&saturate(self.offset).to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+        directory.extend_from_slice(&central_extra);
+
+        self.offset += header.len() + compressed.len();
+        self.count += 1;
+        Ok(())
+    }
+
+    // Writes the central directory and hands back the writer.
+    pub fn finish(mut self) -> Result<W, FsError> {
+        let zip64 = self.count >= 0xffff
+            || self.offset >= 0xffff_ffff
+            || self.directory.len() >= 0xffff_ffff;
+        self.writer.write_all(&self.directory)?;
+        let directory_end = self.offset + self.directory.len();
+
+        let mut end = Vec::new();
+        if zip64 {
+            end.extend_from_slice(&ZIP64_END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+            end.extend_from_slice(&44u64.to_le_bytes());
+            end.extend_from_slice(&(UNIX << 8 | 45).to_le_bytes());
+            end.extend_from_slice(&45u16.to_le_bytes());
+            end.extend_from_slice(&[0; 8]);
+            for value in [self.count, self.count, self.directory.len(), self.offset] {
+                end.extend_from_slice(&(value as u64).to_le_bytes());
+            }
+            end.extend_from_slice(&ZIP64_LOCATOR.to_le_bytes());
+            end.extend_from_slice(&0u32.to_le_bytes());
+            end.extend_from_slice(&(directory_end as u64).to_le_bytes());
+            end.extend_from_slice(&1u32.to_le_bytes());
+        }
+        end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        end.extend_from_slice(&[0; 4]);
+        let count = if zip64 { 0xffff } else { self.count as u16 };
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        let (size, offset) = match zip64 {
+            true => (0xffff_ffff, 0xffff_ffff),
+            false => (self.directory.len() as u32, self.offset as u32),
+        };
+        end.extend_from_slice(&size.to_le_bytes());
+        end.extend_from_slice(&offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.writer.write_all(&end)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn extra_field(id: u16, data: &[u8]) -> Vec<u8> {
+    let mut field = Vec::with_capacity(4 + data.len());
+    field.extend_from_slice(&id.to_le_bytes());
+    field.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    field.extend_from_slice(data);
+    field
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn from_dos(date: u16, time: u16) -> SystemTime {
+    let days = days_from_civil(
+        1980 + (date >> 9) as i64,
+        ((date >> 5) & 0xf).max(1) as i64,
+        (date & 0x1f).max(1) as i64,
+    );
+    let secs = days * 86400
+        + (time >> 11) as i64 * 3600
+        + ((time >> 5) & 0x3f) as i64 * 60
+        + (time & 0x1f) as i64 * 2;
+    UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64)
+}
+
+// DOS dates cover 1980 to 2107, times outside are clamped.
+fn to_dos(secs: u64) -> (u16, u16) {
+    let min = days_from_civil(1980, 1, 1) as u64 * 86400;
+    let max = days_from_civil(2107, 12, 31) as u64 * 86400 + 86399;
+    let secs = secs.clamp(min, max);
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs_of_day = secs % 86400;
+    let date = ((year - 1980) << 9 | month << 5 | day) as u16;
+    // Seconds are stored halved.
+    let (hours, minutes, halves) = (
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60 / 2,
+    );
+    let time = (hours << 11 | minutes << 5 | halves) as u16;
+    (date, time)
+}
+
+// `site.zip` was written by Info-ZIP with symlinks stored as such, `large.zip`
+// by Python's zipfile with zip64 records forced for every entry and the end
+// of the central directory.
+#[test]
+fn test_read_info_zip_archive() {
+    let archive = ZipArchive::new(Arc::from(&include_bytes!("../fixtures/site.zip")[..])).unwrap();
+    let find = |path: &str| {
+        archive
+            .entries()
+            .iter()
+            .find(|entry| entry.path == path)
+            .unwrap()
+    };
+    assert_eq!(archive.entries().len(), 7);
+    let mtime = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    let site = find("site");
+    assert_eq!(
+        (site.kind, site.mode, site.mtime),
+        (ZipEntryKind::Directory, 0o755, mtime)
+    );
+    assert_eq!(find("site/assets").mode, 0o750);
+    assert_eq!(find("site/run.sh").mode, 0o755);
+
+    let index = find("site/index.html");
+    assert_eq!(
+        (index.kind, index.mode, index.method),
+        (ZipEntryKind::File, 0o640, DEFLATED)
+    );
+    let html = archive.read(index).unwrap();
+    assert_eq!(html.len(), index.size);
+    assert!(html.starts_with(b"<html><body>hello world"));
+    assert_eq!(find("site/assets/noise.bin").method, STORED);
+    assert_eq!(
+        archive.read(find("site/assets/noise.bin")).unwrap().len(),
+        300
+    );
+    assert_eq!(archive.read(find("site/empty.txt")).unwrap(), b"");
+
+    let link = find("site/link");
+    assert_eq!(link.kind, ZipEntryKind::Symlink);
+    assert_eq!(archive.read(link).unwrap(), b"index.html");
+}
+
+#[test]
+fn test_read_zip64_archive() {
+    let archive = ZipArchive::new(Arc::from(&include_bytes!("../fixtures/large.zip")[..])).unwrap();
+    let paths: Vec<&str> = archive
+        .entries()
+        .iter()
+        .map(|entry| entry.path.as_str())
+        .collect();
+    assert_eq!(paths, ["a.txt", "dir/b.txt"]);
+    let entry = &archive.entries()[1];
+    assert_eq!((entry.mode, entry.size), (0o600, 13));
+    // No extended timestamp, the DOS time is taken as UTC.
+    assert_eq!(entry.mtime, UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    assert_eq!(archive.read(entry).unwrap(), b"second entry\n");
+    assert_eq!(archive.read(&archive.entries()[0]).unwrap().len(), 480);
+}
+
+#[test]
+fn test_write_and_read_back() {
+    let mtime = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    let text = "compressible ".repeat(100);
+    let mut writer = ZipWriter::new(Vec::new());
+    writer
+        .append("dir", ZipEntryKind::Directory, 0o700, mtime, b"")
+        .unwrap();
+    writer
+        .append(
+            "dir/ünïcode.txt",
+            ZipEntryKind::File,
+            0o600,
+            mtime,
+            text.as_bytes(),
+        )
+        .unwrap();
+    writer
+        .append("dir/tiny", ZipEntryKind::File, 0o644, mtime, b"x")
+        .unwrap();
+    writer
+        .append(
+            "dir/link",
+            ZipEntryKind::Symlink,
+            0o777,
+            mtime,
+            b"/dir/tiny",
+        )
+        .unwrap();
+    let bytes = writer.finish().unwrap();
+
+    let archive = ZipArchive::new(Arc::from(&bytes[..])).unwrap();
+    let entries = archive.entries();
+    assert_eq!(entries.len(), 4);
+    assert_eq!(
+        (entries[0].path.as_str(), entries[0].kind),
+        ("dir", ZipEntryKind::Directory)
+    );
+    assert_eq!(
+        (entries[1].path.as_str(), entries[1].method),
+        ("dir/ünïcode.txt", DEFLATED)
+    );
+    assert_eq!(archive.read(&entries[1]).unwrap(), text.as_bytes());
+    assert_eq!((entries[2].method, entries[2].mode), (STORED, 0o644));
+    assert_eq!(archive.read(&entries[3]).unwrap(), b"/dir/tiny");
+    assert!(entries.iter().all(|entry| entry.mtime == mtime));
+
+    let mut corrupt = bytes.clone();
+    let data = entries[2].local_header_offset + 30 + "dir/tiny".len() + 9;
+    corrupt[data] = b'y';
+    let archive = ZipArchive::new(Arc::from(&corrupt[..])).unwrap();
+    assert_eq!(
+        archive.read(&archive.entries()[2]).unwrap_err().code,
+        FSError::EIO
+    );
+    let err = ZipArchive::new(Arc::from(&bytes[..bytes.len() - 30]))
+        .err()
+        .unwrap();
+    assert_eq!(err.code, FSError::EINVAL);
+}
+
+#[test]
+fn test_dos_times() {
+    let secs = 1_600_000_000;
+    let (date, time) = to_dos(secs);
+    assert_eq!(from_dos(date, time), UNIX_EPOCH + Duration::from_secs(secs));
+    assert_eq!(
+        from_dos(to_dos(0).0, to_dos(0).1),
+        UNIX_EPOCH + Duration::from_secs(315_532_800)
+    );
+}